@@ -1,12 +1,32 @@
 // AnyVm C
 // shitty C dialect for writing anyvm code without using the IR
 // it is literally just a slightly nicer way to write anyvm ir. for instance; functions are no more complex than un-type-checked `long`s floating in space.
-use crate::Image;
+// every expression is a long on the stack. calling a function defined in AVC reserves space for its return value (if it has a return type),
+// pushes the arguments in order, and goes through the function's static slot with invokevirtual; the caller pops the arguments once it
+// returns. calling a variable that *isn't* an AVC function (like one filled in by @load_fun) uses the host convention instead: the callee pops
+// its own arguments and pushes exactly one long.
+// intrinsics are calls that start with @: @exit(code) exits (with 0 if there's no code), @load_lib("name") docks a library, and
+// @load_fun(lib, "name") loads a function out of a docked library.
+// arithmetic (+ - * / %) and comparisons are unsigned. comparisons give a long, 1 if they're true and 0 if they aren't. anything that isn't 0
+// counts as true for if and while.
+// exit only takes an immediate, so a computed exit code gets written into the instruction right before it runs. branch reads its condition
+// from memory, but it has to be popped before the branch, so it goes through a scratch byte at the end of the static section.
+// locals (`long x = 5;` in a function body) are scoped to the whole function, like arguments. every local gets an 8-byte slot above the
+// return address; the slots are reserved (and zeroed) when the function starts and dropped right before it returns.
+// types are checked before anything gets emitted. long and char convert freely, but references (&T) have to match exactly, and only
+// references can be dereferenced (*x). adding to or subtracting from a reference moves it along in bytes. char statics take up a single
+// byte; everything else, including char locals and arguments, takes 8.
+// `return value` (or a trailing expression, in a function with a return type) writes the value into the slot the caller reserved and
+// returns. only as many bytes as the return type needs get written.
+use crate::{ DebugInfo, Image };
+use crate::error::IrError;
+use crate::ir::{ Lines, encode, string_char };
 use chumsky::prelude::*;
 use std::collections::HashMap;
+use std::ops::Range;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 enum Type {
     Long,
     Char,
@@ -15,10 +35,38 @@ enum Type {
 
 
 impl Type {
-    fn from_str(thing : &str) -> Type {
+    fn from_str(thing : &str) -> Option<Type> {
         match thing {
-            "long" => Type::Long,
-            _ => panic!("invalid type {}", thing) // TODO: error handling
+            "long" => Some(Type::Long),
+            "char" | "byte" => Some(Type::Char),
+            _ => None
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Type::Char => 1,
+            _ => 8
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(self, Type::Long | Type::Char)
+    }
+
+    fn accepts(&self, value : &Type) -> bool { // whether a value of type `value` can be stored in something of this type.
+        // integers convert freely, but references have to match exactly
+        (self.is_integer() && value.is_integer()) || self == value
+    }
+}
+
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Type::Long => write!(f, "long"),
+            Type::Char => write!(f, "char"),
+            Type::Ref(t) => write!(f, "&{}", t)
         }
     }
 }
@@ -34,25 +82,54 @@ struct Variable {
 
 #[derive(Debug, Clone)]
 enum Command {
-    FunctionCall(String, Vec<Expression>)
+    FunctionCall(String, Vec<Expression>, Range<usize>), // intrinsics are calls to names starting with @
+    Assign(String, Expression, Range<usize>),
+    Expression(Expression), // evaluated, and the result thrown away
+    If(Expression, Vec<Command>, Vec<Command>), // condition, then, else (which is empty if there isn't one)
+    While(Expression, Vec<Command>),
+    Declare(Variable, Range<usize>), // a local. without a value, it's set to 0
+    Return(Option<Expression>, Range<usize>)
+}
+
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual
 }
 
 
 #[derive(Debug)]
 enum TopLevel {
-    StaticDefinition(Variable),
-    Export(String),
-    ExportFn(String)
+    StaticDefinition(Variable, Range<usize>),
+    FunctionDefinition(Variable, Range<usize>), // `fn name(args) -> type { ... }`. these are always public
+    Export(String, Range<usize>),
+    ExportFn(String, Range<usize>)
 }
 
 
-fn type_parser() -> impl Parser<char, Type, Error=Simple<char>> {
-    just('&').repeated().then(text::ident()).padded().map(|(refs, t)| {
-        let mut tp = Type::from_str(&t);
+fn filler() -> impl Parser<char, (), Error=Simple<char>> + Clone { // whitespace and // comments, which can go anywhere whitespace can
+    let comment = just("//").then(none_of("\n").repeated()).ignored();
+    text::whitespace().then(comment.then(text::whitespace()).repeated()).ignored()
+}
+
+
+fn type_parser() -> impl Parser<char, Type, Error=Simple<char>> + Clone {
+    just('&').repeated().then(text::ident()).padded_by(filler()).try_map(|(refs, t), span| {
+        let mut tp = Type::from_str(&t).ok_or_else(|| Simple::custom(span, format!("unknown type {}", t)))?;
         for _ in 0..refs.len() {
             tp = Type::Ref(Box::new(tp));
         }
-        tp
+        Ok(tp)
     })
 }
 
@@ -60,49 +137,113 @@ fn type_parser() -> impl Parser<char, Type, Error=Simple<char>> {
 #[derive(Debug, Clone)]
 enum Expression {
     Number(i64),
-    NtString(String), // null terminated string
-    Function(Vec<Variable>, Vec<Command>), // arguments, functions
+    NtString(Vec<u8>), // null terminated string
+    Function(Vec<Variable>, Option<Type>, Vec<Command>), // arguments, return type, body
     Sref(i64), // reference to a location in the static table
+    Variable(String, Range<usize>),
+    Call(String, Vec<Expression>, Range<usize>),
+    Binary(Box<Expression>, Op, Box<Expression>),
+    Deref(Box<Expression>, Range<usize>) // *x: read the value a reference points to
+}
+
+
+fn string_parse() -> impl Parser<char, Vec<u8>, Error=Simple<char>> + Clone {
+    just('"').ignore_then(string_char().repeated()).then_ignore(just('"')).padded_by(filler()).map(|parts| parts.concat())
+}
+
+
+fn atom() -> impl Parser<char, Expression, Error=Simple<char>> + Clone {
+    text::int(10).try_map(|s : String, span| {
+        s.parse().map(Expression::Number).map_err(|_| Simple::custom(span, format!("{} doesn't fit in a long", s)))
+    }).padded_by(filler()).or(string_parse().map(Expression::NtString))
 }
 
 
-fn string_parse() -> impl Parser<char, String, Error=Simple<char>> {
-    let esc = just('\\').ignored().then(choice((just('\\'), just('n'), just('0')))).map(|(_, c)| match c {
-        'n' => '\n',
-        '0' => '\0',
-        _ => c
-    }).or(none_of('"'));
-    just('"').ignore_then(esc.repeated()).then_ignore(just('"')).padded().collect::<String>()
+fn name_parser() -> impl Parser<char, String, Error=Simple<char>> + Clone { // something that can be called: an identifier, or an intrinsic
+    just('@').or_not().then(text::ident()).padded_by(filler()).map(|(at, name)| match at {
+        Some(_) => format!("@{}", name),
+        None => name
+    })
 }
 
 
-fn atom() -> impl Parser<char, Expression, Error=Simple<char>> {
-    text::int(10).map(|s: String| {
-        Expression::Number(s.parse().unwrap())
-    }).or(string_parse().map(Expression::NtString))
+fn argument_list(expression : impl Parser<char, Expression, Error=Simple<char>> + Clone) -> impl Parser<char, Vec<Expression>, Error=Simple<char>> + Clone {
+    expression.separated_by(just(',').padded_by(filler())).allow_trailing().delimited_by(just('(').padded_by(filler()), just(')').padded_by(filler()))
+}
+
+
+fn parameter_list() -> impl Parser<char, Vec<Variable>, Error=Simple<char>> + Clone {
+    variable_parser().separated_by(just(',').padded_by(filler())).allow_trailing().delimited_by(just('(').padded_by(filler()), just(')').padded_by(filler()))
+}
+
+
+fn block_parser<'a>(expression : impl Parser<char, Expression, Error=Simple<char>> + Clone + 'a) -> impl Parser<char, Vec<Command>, Error=Simple<char>> + Clone + 'a {
+    // a function body, or the body of an if or while. statements can be ended with a semicolon, but don't have to be
+    recursive(|block| {
+        let if_command = text::keyword("if").padded_by(filler()).ignore_then(expression.clone()).then(block.clone())
+            .then(text::keyword("else").padded_by(filler()).ignore_then(block.clone()).or_not()).map(|((condition, then), otherwise)| {
+                Command::If(condition, then, otherwise.unwrap_or_default())
+            });
+        let while_command = text::keyword("while").padded_by(filler()).ignore_then(expression.clone()).then(block).map(|(condition, body)| {
+            Command::While(condition, body)
+        });
+        let declare = variable_parser().then(just('=').padded_by(filler()).ignore_then(expression.clone()).or_not()).map_with_span(|(mut var, value), span| {
+            var.v = value;
+            Command::Declare(var, span)
+        });
+        let assign = text::ident().padded_by(filler()).then_ignore(just('=').padded_by(filler())).then(expression.clone()).map_with_span(|(name, value), span| {
+            Command::Assign(name, value, span)
+        });
+        let return_command = text::keyword("return").padded_by(filler()).ignore_then(expression.clone().or_not()).map_with_span(Command::Return);
+        let expression_command = expression.clone().map(|value| match value { // a call on its own is a call statement. calls aren't parsed as
+            // statements directly, or `f() + 1` would stop at the `+`
+            Expression::Call(name, args, span) => Command::FunctionCall(name, args, span),
+            value => Command::Expression(value)
+        });
+        let command = choice((if_command, while_command, return_command, declare, assign, expression_command)).then_ignore(just(';').padded_by(filler()).or_not());
+        command.repeated().delimited_by(just('{').padded_by(filler()), just('}').padded_by(filler()))
+    })
 }
 
 
-fn expression_parser() -> impl Parser<char, Expression, Error=Simple<char>> {
+fn expression_parser() -> impl Parser<char, Expression, Error=Simple<char>> + Clone {
     recursive(|expression_parser| {
-        let command_parser = text::ident().padded().then(expression_parser.separated_by(just(',')).allow_trailing().delimited_by(just('('), just(')')).collect::<Vec<_>>()).map(|(name, args)| {
-            Command::FunctionCall(name, args)
+        let function = parameter_list().or_not().padded_by(filler()).then(block_parser(expression_parser.clone())).map(|(args, commands)| {
+            Expression::Function(args.unwrap_or_default(), None, commands)
         });
-        let arg_tuple = variable_parser().separated_by(just(',')).allow_trailing().delimited_by(just('('), just(')')).collect::<Vec<_>>();
-        let function = arg_tuple.or_not().padded().then(command_parser.padded().repeated().delimited_by(just('{'), just('}'))).map(|(args, commands)| {
-            Expression::Function(match args {
-                Some(args) => args,
-                None => Vec::new()
-            }, commands)
+        let call = name_parser().then(argument_list(expression_parser.clone())).map_with_span(|(name, args), span| Expression::Call(name, args, span));
+        let variable = text::ident().padded_by(filler()).map_with_span(Expression::Variable);
+        let parenthesized = expression_parser.clone().delimited_by(just('(').padded_by(filler()), just(')').padded_by(filler()));
+        let primary = recursive(|primary| {
+            let deref = just('*').padded_by(filler()).ignore_then(primary).map_with_span(|pointer, span| Expression::Deref(Box::new(pointer), span));
+            choice((function, call, atom(), variable, parenthesized, deref))
         });
-        function.or(atom())
+        // precedence, tightest first: * / %, then + -, then the comparisons. arithmetic is left-associative, comparisons don't chain
+        let product_op = choice((just('*').to(Op::Multiply), just('/').to(Op::Divide), just('%').to(Op::Remainder))).padded_by(filler());
+        let product = primary.clone().then(product_op.then(primary).repeated())
+            .foldl(|a, (op, b)| Expression::Binary(Box::new(a), op, Box::new(b)));
+        let sum_op = choice((just('+').to(Op::Add), just('-').to(Op::Subtract))).padded_by(filler());
+        let sum = product.clone().then(sum_op.then(product).repeated())
+            .foldl(|a, (op, b)| Expression::Binary(Box::new(a), op, Box::new(b)));
+        let comparison = choice((
+            just("==").to(Op::Equal),
+            just("!=").to(Op::NotEqual),
+            just("<=").to(Op::LessEqual),
+            just(">=").to(Op::GreaterEqual),
+            just('<').to(Op::Less),
+            just('>').to(Op::Greater)
+        )).padded_by(filler());
+        sum.clone().then(comparison.then(sum).or_not()).map(|(a, rest)| match rest {
+            Some((op, b)) => Expression::Binary(Box::new(a), op, Box::new(b)),
+            None => a
+        })
     })
 }
 
 
-fn variable_parser() -> impl Parser<char, Variable, Error=Simple<char>> { // parse a typed variable (this can be a function argument)
+fn variable_parser() -> impl Parser<char, Variable, Error=Simple<char>> + Clone { // parse a typed variable (this can be a function argument)
     // C-style
-    type_parser().then(text::ident()).padded().map(|(vtype, name)| {
+    type_parser().then(text::ident()).padded_by(filler()).map(|(vtype, name)| {
         Variable {
             name,
             t : vtype,
@@ -113,31 +254,56 @@ fn variable_parser() -> impl Parser<char, Variable, Error=Simple<char>> { // par
 
 
 fn variable_parser_with_value() -> impl Parser<char, Variable, Error=Simple<char>> { // parse a typed variable with optional `= value` after it
-    variable_parser().padded().then_ignore(just('=')).padded().then(
-        expression_parser().repeated().at_most(1)
-    ).map(|(mut var, expr)| {
-        if expr.len() == 1 {
-            var.v = Some(expr[0].clone())
-        }
-        var
-    })
+    variable_parser().then(just('=').padded_by(filler()).ignore_then(expression_parser()).or_not()).then_ignore(just(';').padded_by(filler()).or_not())
+        .map(|(mut var, value)| {
+            var.v = value;
+            var
+        })
 }
 
 
 fn parser() -> impl Parser<char, Vec<TopLevel>, Error=Simple<char>> {
-    choice((
-        text::keyword("export").padded().ignore_then(text::keyword("function").or_not()).padded().then(text::ident()).map(|(is_function, name)| {
-            if let Some(_) = is_function {
-                TopLevel::ExportFn(name)
+    let function = text::keyword("fn").padded_by(filler()).ignore_then(text::ident().padded_by(filler())).then(parameter_list())
+        .then(just("->").padded_by(filler()).ignore_then(type_parser()).or_not()).then(block_parser(expression_parser()))
+        .map_with_span(|(((name, args), returns), mut body), span| {
+            if returns.is_some() { // a trailing expression (or call, apart from @exit) is what the function returns
+                let value = match body.last().cloned() {
+                    Some(Command::Expression(value)) => Some(value),
+                    Some(Command::FunctionCall(name, args, span)) if name != "@exit" => Some(Expression::Call(name, args, span)),
+                    _ => None
+                };
+                if let Some(value) = value {
+                    let span = value.span();
+                    *body.last_mut().unwrap() = Command::Return(Some(value), span);
+                }
+            }
+            TopLevel::FunctionDefinition(Variable {
+                name,
+                t : Type::Long,
+                v : Some(Expression::Function(args, returns, body))
+            }, span)
+        });
+    filler().ignore_then(choice((
+        function,
+        text::keyword("export").padded_by(filler()).ignore_then(text::keyword("function").or_not()).padded_by(filler()).then(text::ident()).map_with_span(|(is_function, name), span| {
+            if is_function.is_some() {
+                TopLevel::ExportFn(name, span)
             }
             else {
-                TopLevel::Export(name)
+                TopLevel::Export(name, span)
             }
         }),
-        variable_parser_with_value().map(|v| {
-            TopLevel::StaticDefinition(v)
-        })
-    )).padded().repeated().then_ignore(end())
+        variable_parser_with_value().map_with_span(TopLevel::StaticDefinition)
+    )).padded_by(filler()).repeated()).then_ignore(end())
+}
+
+
+struct Frame<'a> { // what's known about the stack while a function body is being emitted
+    args : &'a [Variable],
+    locals : Vec<Variable>, // in slot order: the first local is right above the return address
+    returns : Option<Type>,
+    epilogue : usize, // the label that drops the locals and returns. only used while emitting
+    depth : i64 // how many bytes have been pushed since the function was called, not counting the return address. includes the locals
 }
 
 
@@ -147,7 +313,19 @@ struct ImageBuilder {
     static_table : HashMap<String, i64>,
     function_table : HashMap<String, i64>,
     pub_st_table : HashMap<String, i64>,
-    pub_fn_table : HashMap<String, i64>
+    pub_fn_table : HashMap<String, i64>,
+    function_extents : Vec<(i64, i64)>, // (start, end) offsets into the text section of every emitted function
+    functions : Vec<(String, i64, Vec<Variable>, Vec<Command>)>, // (name, static slot, arguments, body) of every function, collected before any body is emitted
+    signatures : HashMap<String, (i64, Vec<Variable>, Option<Type>)>, // (static slot, arguments, return type) of every function, so calls can
+    // be emitted and checked before the callee
+    static_types : HashMap<String, Type>,
+    strings : HashMap<Vec<u8>, i64>, // where every string literal used in a function body lives in the static section
+    labels : Vec<Option<i64>>, // the address of every generated label, once it's been placed. labels are numbered in order of creation
+    fixups : Vec<(usize, usize, i64)>, // (text offset, label, base): write the label's address minus base as a 64-bit value at the offset
+    condition : i64, // the scratch byte branch_if_false leaves conditions in for branch
+    spans : Vec<(i64, i64, usize)>, // (text start, text end, source offset) of every command, for the debug info
+    frame_sizes : HashMap<String, u64>, // how many bytes of locals every function reserves
+    errors : Vec<IrError>
 }
 
 
@@ -159,23 +337,602 @@ impl ImageBuilder {
             static_table : HashMap::new(),
             function_table : HashMap::new(),
             pub_st_table : HashMap::new(),
-            pub_fn_table : HashMap::new()
+            pub_fn_table : HashMap::new(),
+            function_extents : Vec::new(),
+            functions : Vec::new(),
+            signatures : HashMap::new(),
+            static_types : HashMap::new(),
+            strings : HashMap::new(),
+            labels : Vec::new(),
+            fixups : Vec::new(),
+            condition : 0,
+            spans : Vec::new(),
+            frame_sizes : HashMap::new(),
+            errors : Vec::new()
         }
     }
 
-    fn build(&mut self, program : &mut Vec<TopLevel>) {
-        println!("program: {:?}", program);
-        for statement in program {
+    fn build(&mut self, program : &mut [TopLevel]) {
+        // first pass: lay out the whole static section, including a slot for every function.
+        // nothing is emitted into the text section yet, so every function already has a known address to be called through
+        // by the time any body is compiled - this is what lets functions reference each other regardless of definition order.
+        for statement in program.iter_mut() {
             statement.static_collapse(self);
         }
+        self.condition = self.static_section.len() as i64;
+        self.static_section.push(0);
+        // second pass: type check the bodies. the VM doesn't know about types at all, so this is the only thing standing between a
+        // mistake and silent memory corruption - don't emit anything if it fails
+        self.check_functions();
+        if !self.errors.is_empty() {
+            return;
+        }
+        // third pass: emit the bodies
+        self.emit_functions();
+        for (offset, label, base) in std::mem::take(&mut self.fixups) {
+            let value = self.labels[label].expect("a label was never placed") - base;
+            self.text_section[offset..offset + 8].copy_from_slice(&value.to_be_bytes());
+        }
+        // and now that every function has an address, fill in the public tables. exporting something that doesn't exist is caught here,
+        // so it only gets reported if the bodies checked out
+        let text_start = self.static_section.len() as i64;
+        for statement in program.iter() {
+            match statement {
+                TopLevel::FunctionDefinition(Variable { name, .. }, _) => {
+                    self.pub_fn_table.insert(name.clone(), self.function_table[name] - text_start);
+                },
+                TopLevel::ExportFn(name, span) => match self.function_table.get(name) {
+                    Some(addr) => { self.pub_fn_table.insert(name.clone(), addr - text_start); },
+                    None => self.error(span, format!("can't export {}: there's no function by that name", name))
+                },
+                TopLevel::Export(name, span) => match self.static_table.get(name) {
+                    Some(addr) => { self.pub_st_table.insert(name.clone(), *addr); },
+                    None => self.error(span, format!("can't export {}: there's no static by that name", name))
+                },
+                TopLevel::StaticDefinition(..) => {}
+            }
+        }
+    }
+
+    fn error(&mut self, span : &Range<usize>, message : String) {
+        self.errors.push(IrError { span : span.clone(), message });
+    }
+
+    fn emit(&mut self, mnemonic : &str, args : &[i64]) {
+        encode(mnemonic, args, &mut self.text_section);
+    }
+
+    fn here(&self) -> i64 { // the absolute address of the next byte to be emitted
+        (self.static_section.len() + self.text_section.len()) as i64
+    }
+
+    fn new_label(&mut self) -> usize { // labels come from a counter, so nested ifs and loops can't clash
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place_label(&mut self, label : usize) {
+        self.labels[label] = Some(self.here());
+    }
+
+    fn fixup(&mut self, label : usize, base : i64) { // the last 8 bytes emitted should be the label's address minus base
+        self.fixups.push((self.text_section.len() - 8, label, base));
+    }
+
+    fn jump(&mut self, label : usize) {
+        self.emit("jmp", &[0]);
+        let next = self.here();
+        self.fixup(label, next); // jmp is relative to the next instruction
+    }
+
+    fn branch_if_false(&mut self, frame : &mut Frame, condition : &Expression, label : usize) { // jump to label if condition is 0
+        self.expression(frame, condition);
+        self.emit("pushvl", &[0]);
+        self.emit("cmpl", &[-16, -8]); // 0 if the condition was 0, 1 otherwise
+        self.emit("cpyb", &[-1, self.condition]); // the result is about to be popped, so keep it somewhere branch can still see it
+        self.emit("popb", &[]);
+        self.emit("popl", &[]);
+        self.emit("popl", &[]);
+        frame.depth -= 8;
+        self.emit("branch", &[self.condition, 0]);
+        self.fixup(label, 0);
+    }
+
+    fn emit_functions(&mut self) {
+        let functions = std::mem::take(&mut self.functions);
+        for (name, slot, args, program) in &functions {
+            // the static section is final at this point, so the absolute address of the function is known
+            let start = self.text_section.len() as i64;
+            let addr = self.static_section.len() as i64 + start;
+            let slot = *slot as usize;
+            self.static_section[slot..slot + 8].copy_from_slice(&addr.to_be_bytes());
+            self.function_table.insert(name.clone(), addr);
+            let mut locals = Vec::new();
+            self.collect_locals(args, program, &mut locals);
+            let frame_size = 8 * locals.len() as i64;
+            self.frame_sizes.insert(name.clone(), frame_size as u64);
+            if frame_size > 0 {
+                self.emit("updstck", &[frame_size]);
+                self.emit("scrub", &[-frame_size, frame_size]); // locals start out zeroed
+            }
+            let returns = self.signatures[name].2.clone();
+            let epilogue = self.new_label();
+            let mut frame = Frame { args, depth : frame_size, locals, returns, epilogue };
+            for command in program {
+                self.command(&mut frame, command);
+            }
+            self.place_label(epilogue);
+            if frame_size > 0 {
+                self.emit("updstck", &[-frame_size]);
+            }
+            self.emit("ret", &[]);
+            self.function_extents.push((start, self.text_section.len() as i64));
+        }
+        self.functions = functions;
+    }
+
+    fn collect_locals(&mut self, args : &[Variable], commands : &[Command], locals : &mut Vec<Variable>) { // find every local declared in a
+        // function body, however deeply nested
+        for command in commands {
+            match command {
+                Command::Declare(var, span) => {
+                    if locals.iter().chain(args).any(|other| other.name == var.name) {
+                        self.error(span, format!("{} is already declared in this function", var.name));
+                    }
+                    else {
+                        locals.push(var.clone());
+                    }
+                },
+                Command::If(_, then, otherwise) => {
+                    self.collect_locals(args, then, locals);
+                    self.collect_locals(args, otherwise, locals);
+                },
+                Command::While(_, body) => self.collect_locals(args, body, locals),
+                _ => {}
+            }
+        }
+    }
+
+    fn intern_strings(&mut self, commands : &[Command]) { // give every string literal in a function body a place in the static section
+        for command in commands {
+            match command {
+                Command::FunctionCall(_, args, _) => args.iter().for_each(|arg| self.intern_string(arg)),
+                Command::Assign(_, value, _) | Command::Expression(value) => self.intern_string(value),
+                Command::If(condition, then, otherwise) => {
+                    self.intern_string(condition);
+                    self.intern_strings(then);
+                    self.intern_strings(otherwise);
+                },
+                Command::While(condition, body) => {
+                    self.intern_string(condition);
+                    self.intern_strings(body);
+                },
+                Command::Declare(var, _) => {
+                    if let Some(value) = &var.v {
+                        self.intern_string(value);
+                    }
+                },
+                Command::Return(value, _) => {
+                    if let Some(value) = value {
+                        self.intern_string(value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn intern_string(&mut self, expression : &Expression) {
+        match expression {
+            Expression::NtString(s) if !self.strings.contains_key(s) => {
+                self.strings.insert(s.clone(), self.static_section.len() as i64);
+                self.static_section.extend(s);
+                self.static_section.push(0);
+            },
+            Expression::Call(_, args, _) => args.iter().for_each(|arg| self.intern_string(arg)),
+            Expression::Binary(a, _, b) => {
+                self.intern_string(a);
+                self.intern_string(b);
+            },
+            Expression::Deref(pointer, _) => self.intern_string(pointer),
+            _ => {}
+        }
     }
 
-    fn into_image(self) -> Image {
+    fn variable(&self, frame : &Frame, name : &str) -> Option<(i64, Type)> { // where a variable lives right now, and its type. arguments and
+        // locals are relative to the top of the stack, so this changes as things get pushed
+        if let Some(index) = frame.locals.iter().position(|local| local.name == name) {
+            return Some((-(frame.depth - 8 * index as i64), frame.locals[index].t.clone()));
+        }
+        if let Some(index) = frame.args.iter().position(|arg| arg.name == name) {
+            // skip the return address, then count back from the last argument
+            return Some((-(frame.depth + 8 + 8 * (frame.args.len() - index) as i64), frame.args[index].t.clone()));
+        }
+        Some((*self.static_table.get(name)?, self.static_types[name].clone()))
+    }
+
+    fn variable_addr(&self, frame : &Frame, name : &str) -> Option<i64> {
+        self.variable(frame, name).map(|(addr, _)| addr)
+    }
+
+    fn load(&mut self, frame : &Frame, name : &str) { // push a variable as a long. everything on the stack is 8 bytes, but char statics
+        // are only 1 byte in memory
+        match self.variable(frame, name) {
+            Some((addr, Type::Char)) if addr >= 0 => {
+                self.emit("pushvl", &[0]);
+                self.emit("pushb", &[addr]);
+                self.emit("cpyb", &[-1, -2]);
+                self.emit("popb", &[]);
+            },
+            Some((addr, _)) => self.emit("pushl", &[addr]),
+            None => {}
+        }
+    }
+
+    fn store(&mut self, frame : &mut Frame, name : &str) { // pop a long off the stack into a variable
+        frame.depth -= 8; // popm resolves its address after popping
+        match self.variable(frame, name) {
+            Some((addr, Type::Char)) if addr >= 0 => {
+                self.emit("cpyb", &[-1, addr]);
+                self.emit("popl", &[]);
+            },
+            Some((addr, _)) => self.emit("popml", &[addr]),
+            None => {}
+        }
+    }
+
+    fn check_functions(&mut self) {
+        let functions = std::mem::take(&mut self.functions);
+        for (name, _, args, program) in &functions {
+            let mut locals = Vec::new();
+            self.collect_locals(args, program, &mut locals);
+            let frame = Frame { args, depth : 0, locals, returns : self.signatures[name].2.clone(), epilogue : 0 };
+            self.check_commands(&frame, program);
+        }
+        self.functions = functions;
+    }
+
+    fn check_commands(&mut self, frame : &Frame, commands : &[Command]) {
+        for command in commands {
+            match command {
+                Command::FunctionCall(name, args, span) => {
+                    self.check_call(frame, name, args, span);
+                },
+                Command::Assign(name, value, span) => match self.variable(frame, name) {
+                    Some((_, t)) => self.check_assign(frame, name, &t, value, span),
+                    None => self.error(span, format!("undefined variable {}", name))
+                },
+                Command::Declare(var, span) => {
+                    if let Some(value) = &var.v {
+                        self.check_assign(frame, &var.name, &var.t, value, span);
+                    }
+                },
+                Command::Expression(value) => {
+                    self.type_of(frame, value);
+                },
+                Command::If(condition, then, otherwise) => {
+                    self.type_of(frame, condition);
+                    self.check_commands(frame, then);
+                    self.check_commands(frame, otherwise);
+                },
+                Command::While(condition, body) => {
+                    self.type_of(frame, condition);
+                    self.check_commands(frame, body);
+                },
+                Command::Return(value, span) => match (value, &frame.returns) {
+                    (Some(value), Some(t)) => self.check_assign(frame, "the return value", t, value, span),
+                    (None, None) => {},
+                    (Some(_), None) => self.error(span, "this function doesn't return anything, so it can't return a value".to_string()),
+                    (None, Some(t)) => self.error(span, format!("this function has to return a {}", t))
+                }
+            }
+        }
+    }
+
+    fn check_assign(&mut self, frame : &Frame, name : &str, t : &Type, value : &Expression, span : &Range<usize>) {
+        if let Some(value_type) = self.type_of(frame, value) {
+            if !t.accepts(&value_type) {
+                self.error(span, format!("can't assign {} (a {}) to {}, which is a {}", value.describe(), value_type, name, t));
+            }
+        }
+    }
+
+    fn check_call(&mut self, frame : &Frame, name : &str, args : &[Expression], span : &Range<usize>) -> Option<Option<Type>> { // the
+        // return type of a call, which is None if it doesn't return anything. None overall means the call is broken (and already reported)
+        let arg_types : Vec<Option<Type>> = args.iter().map(|arg| self.type_of(frame, arg)).collect();
+        match name {
+            "@exit" => return Some(None),
+            "@load_lib" | "@load_fun" => return Some(Some(Type::Long)),
+            _ if name.starts_with('@') => return Some(None), // emitting it reports the error
+            _ => {}
+        }
+        if let Some((_, params, returns)) = self.signatures.get(name).cloned() {
+            if params.len() != args.len() {
+                self.error(span, format!("{} takes {} arguments, but got {}", name, params.len(), args.len()));
+                return None;
+            }
+            for ((param, arg), arg_type) in params.iter().zip(args).zip(arg_types) {
+                match arg_type {
+                    Some(t) if !param.t.accepts(&t) => {
+                        self.error(span, format!("argument {} of {} is a {}, but {} is a {}", param.name, name, param.t, arg.describe(), t));
+                    },
+                    _ => {}
+                }
+            }
+            return Some(returns);
+        }
+        if self.variable(frame, name).is_some() {
+            return Some(Some(Type::Long)); // the host convention. there's nothing to check against
+        }
+        self.error(span, format!("undefined function {}", name));
+        None
+    }
+
+    fn type_of(&mut self, frame : &Frame, expression : &Expression) -> Option<Type> { // the type of an expression, or None if it's broken
+        // (problems get reported as they're found, so callers shouldn't report them again)
+        match expression {
+            Expression::Number(_) | Expression::Sref(_) | Expression::Function(..) => Some(Type::Long),
+            Expression::NtString(_) => Some(Type::Ref(Box::new(Type::Char))),
+            Expression::Variable(name, span) => match self.variable(frame, name) {
+                Some((_, t)) => Some(t),
+                None => {
+                    self.error(span, format!("undefined variable {}", name));
+                    None
+                }
+            },
+            Expression::Call(name, args, span) => match self.check_call(frame, name, args, span)? {
+                Some(t) => Some(t),
+                None => {
+                    self.error(span, format!("{} doesn't return anything", name));
+                    None
+                }
+            },
+            Expression::Binary(a, op, b) => {
+                let (a_type, b_type) = (self.type_of(frame, a), self.type_of(frame, b));
+                let (a_type, b_type) = (a_type?, b_type?);
+                match op {
+                    Op::Equal | Op::NotEqual | Op::Less | Op::Greater | Op::LessEqual | Op::GreaterEqual => {
+                        if !a_type.accepts(&b_type) {
+                            self.error(&expression.span(), format!("can't compare {} (a {}) with {} (a {})", a.describe(), a_type, b.describe(), b_type));
+                            return None;
+                        }
+                        Some(Type::Long)
+                    },
+                    _ if a_type.is_integer() && b_type.is_integer() => Some(Type::Long),
+                    Op::Add | Op::Subtract if matches!(a_type, Type::Ref(_)) && b_type.is_integer() => Some(a_type), // moving a reference along, in bytes
+                    _ => {
+                        let (culprit, t) = if a_type.is_integer() { (b, b_type) } else { (a, a_type) };
+                        self.error(&culprit.span(), format!("can't do that arithmetic on {}, which is a {}", culprit.describe(), t));
+                        None
+                    }
+                }
+            },
+            Expression::Deref(pointer, span) => match self.type_of(frame, pointer)? {
+                Type::Ref(t) => Some(*t),
+                t => {
+                    self.error(span, format!("{} isn't a reference (it's a {}), so it can't be dereferenced", pointer.describe(), t));
+                    None
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, frame : &mut Frame, command : &Command) {
+        let start = self.text_section.len() as i64;
+        self.emit_command(frame, command);
+        let span = command.span();
+        if !span.is_empty() { // a bare literal has nowhere to point to
+            self.spans.push((start, self.text_section.len() as i64, span.start));
+        }
+    }
+
+    fn emit_command(&mut self, frame : &mut Frame, command : &Command) {
+        match command {
+            Command::FunctionCall(name, args, span) => {
+                if self.call(frame, name, args, span) {
+                    self.emit("popl", &[]);
+                    frame.depth -= 8;
+                }
+            },
+            Command::Assign(name, value, _) => {
+                self.expression(frame, value);
+                self.store(frame, name);
+            },
+            Command::Expression(value) => {
+                self.expression(frame, value);
+                self.emit("popl", &[]);
+                frame.depth -= 8;
+            },
+            Command::If(condition, then, otherwise) => {
+                let otherwise_label = self.new_label();
+                let end = self.new_label();
+                self.branch_if_false(frame, condition, otherwise_label);
+                for command in then {
+                    self.command(frame, command);
+                }
+                if !otherwise.is_empty() {
+                    self.jump(end);
+                }
+                self.place_label(otherwise_label);
+                for command in otherwise {
+                    self.command(frame, command);
+                }
+                self.place_label(end);
+            },
+            Command::While(condition, body) => {
+                let top = self.new_label();
+                let end = self.new_label();
+                self.place_label(top);
+                self.branch_if_false(frame, condition, end);
+                for command in body {
+                    self.command(frame, command);
+                }
+                self.jump(top);
+                self.place_label(end);
+            },
+            Command::Declare(var, span) => {
+                let value = var.v.clone().unwrap_or(Expression::Number(0)); // so a declaration in a loop starts from 0 every time round
+                self.command(frame, &Command::Assign(var.name.clone(), value, span.clone()));
+            },
+            Command::Return(value, _) => {
+                if let (Some(value), Some(t)) = (value, frame.returns.clone()) {
+                    self.expression(frame, value);
+                    frame.depth -= 8;
+                    // the caller reserved the slot before pushing the arguments, and it's already zeroed, so a char only needs its low byte
+                    let slot = -(frame.depth + 8 + 8 * frame.args.len() as i64 + 8);
+                    if t == Type::Char {
+                        self.emit("cpyb", &[-1, slot - 1]); // cpyb resolves before anything's popped
+                        self.emit("popl", &[]);
+                    }
+                    else {
+                        self.emit("popml", &[slot]);
+                    }
+                }
+                self.jump(frame.epilogue);
+            }
+        }
+    }
+
+    fn expression(&mut self, frame : &mut Frame, expression : &Expression) { // emit code that pushes the value of an expression
+        match expression {
+            Expression::Number(n) => self.emit("pushvl", &[*n]),
+            Expression::NtString(s) => self.emit("pushvl", &[self.strings[s]]),
+            Expression::Sref(addr) => self.emit("pushl", &[*addr]),
+            Expression::Variable(name, _) => self.load(frame, name),
+            Expression::Deref(pointer, _) => {
+                let t = self.type_of(frame, expression);
+                self.expression(frame, pointer);
+                let load = self.new_label();
+                self.emit("popml", &[0]); // there's no instruction that reads through a pointer, so write it into the push below
+                self.fixup(load, -1);
+                frame.depth -= 8;
+                if t == Some(Type::Char) {
+                    self.emit("pushvl", &[0]);
+                    self.place_label(load);
+                    self.emit("pushb", &[0]);
+                    self.emit("cpyb", &[-1, -2]);
+                    self.emit("popb", &[]);
+                }
+                else {
+                    self.place_label(load);
+                    self.emit("pushl", &[0]);
+                }
+            },
+            Expression::Call(name, args, span) => {
+                if !self.call(frame, name, args, span) {
+                    self.error(span, format!("{} doesn't return anything", name));
+                }
+                return; // call keeps track of the depth itself
+            },
+            Expression::Binary(a, op, b) => {
+                self.expression(frame, a);
+                self.expression(frame, b);
+                let arithmetic = match op { // these all leave the result where a was
+                    Op::Add => Some("addl"),
+                    Op::Subtract => Some("subl"),
+                    Op::Multiply => Some("mull"),
+                    Op::Divide => Some("divl"),
+                    Op::Remainder => Some("ureml"),
+                    _ => None
+                };
+                if let Some(mnemonic) = arithmetic {
+                    self.emit(mnemonic, &[-16, -8]);
+                }
+                else {
+                    self.emit("cmpl", &[-16, -8]); // pushes 0 if they're equal, 1 if a is greater, 2 if b is
+                    let (xor, normalize) = match op { // xor it so the interesting outcome is 0, then either not it or bnorm it
+                        Op::Equal => (None, "not"),
+                        Op::NotEqual => (None, "bnorm"),
+                        Op::Less => (Some(2), "not"),
+                        Op::Greater => (Some(1), "not"),
+                        Op::LessEqual => (Some(1), "bnorm"),
+                        _ => (Some(2), "bnorm") // >=
+                    };
+                    if let Some(xor) = xor {
+                        self.emit("vxor", &[-1, xor]);
+                    }
+                    self.emit(normalize, &[-1]); // now it's 1 if the comparison is true
+                    self.emit("cpyvl", &[-17, 0]); // turn it into a long where a was
+                    self.emit("cpyb", &[-1, -10]);
+                    self.emit("popb", &[]);
+                }
+                self.emit("popl", &[]);
+                frame.depth -= 16;
+            },
+            Expression::Function(..) => self.errors.push(IrError { span : 0..0, message : "functions can only be defined at the top level".to_string() })
+        }
+        frame.depth += 8;
+    }
+
+    fn call(&mut self, frame : &mut Frame, name : &str, args : &[Expression], span : &Range<usize>) -> bool { // emit a call. returns whether
+        // it leaves a value on the stack
+        if name.starts_with('@') {
+            return self.intrinsic(frame, name, args, span);
+        }
+        if let Some((slot, _, returns)) = self.signatures.get(name).cloned() {
+            if returns.is_some() {
+                self.emit("pushvl", &[0]); // space for the return value
+                frame.depth += 8;
+            }
+            for arg in args {
+                self.expression(frame, arg);
+            }
+            self.emit("invokevirtual", &[slot]);
+            for _ in args {
+                self.emit("popl", &[]);
+                frame.depth -= 8;
+            }
+            return returns.is_some();
+        }
+        for arg in args {
+            self.expression(frame, arg);
+        }
+        match self.variable_addr(frame, name) { // the host convention
+            Some(addr) => self.emit("invokevirtual", &[addr]),
+            None => self.error(span, format!("undefined function {}", name))
+        }
+        frame.depth -= 8 * args.len() as i64 - 8;
+        true
+    }
+
+    fn intrinsic(&mut self, frame : &mut Frame, name : &str, args : &[Expression], span : &Range<usize>) -> bool {
+        match (name, args) {
+            ("@exit", []) => self.emit("exit", &[0]),
+            ("@exit", [Expression::Number(code)]) => self.emit("exit", &[*code]),
+            ("@exit", [code]) => {
+                self.expression(frame, code);
+                let exit = self.new_label();
+                self.emit("popml", &[0]); // exit only takes an immediate, so write the code into it
+                self.fixup(exit, -1);
+                frame.depth -= 8;
+                self.place_label(exit);
+                self.emit("exit", &[0]);
+            },
+            ("@load_lib", [Expression::NtString(lib)]) => {
+                self.emit("dock", &[self.strings[lib]]);
+                frame.depth += 8;
+                return true;
+            },
+            ("@load_fun", [lib, Expression::NtString(function)]) => {
+                self.expression(frame, lib);
+                self.emit("loadfun", &[self.strings[function]]); // swaps the library for the function
+                return true;
+            },
+            ("@exit" | "@load_lib" | "@load_fun", _) => self.error(span, format!("bad arguments to {}", name)),
+            _ => self.error(span, format!("unknown intrinsic {}", name))
+        }
+        false
+    }
+
+    fn into_image(self, lines : &Lines) -> Image {
         Image {
             function_table : self.pub_fn_table,
             text_section : self.text_section,
             static_table : self.pub_st_table,
-            static_section : self.static_section
+            static_section : self.static_section,
+            function_extents : self.function_extents,
+            relocations : None, // addresses are emitted as plain numbers, so there's no telling which ones they are
+            debug_info : Some(DebugInfo { spans : self.spans.iter().map(|&(start, end, offset)| lines.span(start, end, offset)).collect() }),
+            frame_sizes : Some(self.frame_sizes)
         }
     }
 }
@@ -185,9 +942,25 @@ impl TopLevel {
     fn static_collapse(&mut self, image : &mut ImageBuilder) { // fill a static table
         let static_pointer = image.static_section.len();
         match self {
-            Self::StaticDefinition(var) => {
-                if let Some(v) = &var.v {
-                    v.insert_static(image);
+            Self::StaticDefinition(var, span) | Self::FunctionDefinition(var, span) => {
+                if image.static_table.contains_key(&var.name) { // functions live in static slots too, so this catches both
+                    image.error(span, format!("{} is already defined", var.name));
+                    return;
+                }
+                image.static_table.insert(var.name.clone(), static_pointer as i64);
+                image.static_types.insert(var.name.clone(), var.t.clone());
+                match &var.v {
+                    Some(Expression::Number(n)) if var.t == Type::Char => match u8::try_from(*n) {
+                        Ok(n) => image.static_section.push(n),
+                        Err(_) => image.errors.push(IrError { span : 0..0, message : format!("{} doesn't fit in {}, which is a char", n, var.name) })
+                    },
+                    Some(v) => v.insert_static(image),
+                    None => image.static_section.resize(static_pointer + var.t.size(), 0)
+                }
+                if let Some(Expression::Function(args, returns, program)) = &var.v {
+                    image.functions.push((var.name.clone(), static_pointer as i64, args.clone(), program.clone()));
+                    image.signatures.insert(var.name.clone(), (static_pointer as i64, args.clone(), returns.clone()));
+                    image.intern_strings(program);
                 }
                 var.v = Some(Expression::Sref(static_pointer as i64));
             },
@@ -197,36 +970,79 @@ impl TopLevel {
 }
 
 
+impl Command {
+    fn span(&self) -> Range<usize> { // ifs and loops don't keep a span of their own, so they go by their condition
+        match self {
+            Self::FunctionCall(_, _, span) | Self::Assign(_, _, span) | Self::Declare(_, span) | Self::Return(_, span) => span.clone(),
+            Self::Expression(value) | Self::If(value, _, _) | Self::While(value, _) => value.span()
+        }
+    }
+}
+
+
 impl Expression {
+    fn describe(&self) -> String { // how to refer to this in an error message
+        match self {
+            Self::Variable(name, _) => name.clone(),
+            Self::Call(name, ..) => format!("the result of {}", name),
+            Self::Number(n) => n.to_string(),
+            Self::NtString(_) => "a string".to_string(),
+            _ => "an expression".to_string()
+        }
+    }
+
+    fn span(&self) -> Range<usize> { // where this is in the source, as best as can be told. literals don't keep track, so they're 0..0
+        match self {
+            Self::Variable(_, span) | Self::Call(_, _, span) | Self::Deref(_, span) => span.clone(),
+            Self::Binary(a, _, b) => match (a.span(), b.span()) {
+                (a, b) if a.is_empty() => b,
+                (a, b) if b.is_empty() => a,
+                (a, b) => a.start..b.end
+            },
+            _ => 0..0
+        }
+    }
+
     fn insert_static(&self, image : &mut ImageBuilder) {
         match self {
             Self::Number(i) => {
                 image.static_section.extend(i.to_be_bytes());
             },
-            Self::NtString(s) => {
-                image.static_section.extend(s.as_bytes());
-                image.static_section.extend(s.len().to_be_bytes());
-            }
-            Self::Function(_, program) => {
-                
+            Self::NtString(s) => { // a &char, like any other string: the static points at the string, which goes in after it
+                let slot = image.static_section.len();
+                image.static_section.extend(0i64.to_be_bytes());
+                image.intern_string(self);
+                let addr = image.strings[s];
+                image.static_section[slot..slot + 8].copy_from_slice(&addr.to_be_bytes());
+            },
+            Self::Function(..) => {
+                // functions are just longs pointing into the text section. the pointer isn't known until the static section is
+                // fully laid out, so reserve the slot here and let emit_functions patch it.
+                image.static_section.extend(0i64.to_be_bytes());
             },
-            Self::Sref(to) => {}
+            Self::Sref(_) => {},
+            Self::Variable(_, span) | Self::Call(_, _, span) | Self::Deref(_, span) => {
+                image.error(span, "statics have to be constants".to_string());
+            },
+            Self::Binary(..) => {
+                image.error(&self.span(), "statics have to be constants".to_string());
+            }
         }
     }
 }
 
 
-pub fn build(program : &str) -> Image {
-    let mut irast = parser().parse(r#"
-    long varname = 80
-    long main = {
-        print("Test message!")
-    }
-    export function main
-    "#).unwrap();
+pub fn check(program : &str) -> Result<(), Vec<Simple<char>>> { // parse a program without building it
+    parser().parse(program).map(|_| ())
+}
+
 
+pub fn build(program : &str) -> Result<Image, Vec<IrError>> {
+    let mut ast = parser().parse(program).map_err(|errors| errors.into_iter().map(IrError::from).collect::<Vec<_>>())?;
     let mut builder = ImageBuilder::new();
-    builder.build(&mut irast);
-    println!("nazi: {:?}", builder.static_section);
-    builder.into_image()
+    builder.build(&mut ast);
+    if !builder.errors.is_empty() {
+        return Err(builder.errors);
+    }
+    Ok(builder.into_image(&Lines::new(program)))
 }
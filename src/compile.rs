@@ -0,0 +1,222 @@
+// experimental closure compiler. Image::compile_to_closure walks the text section once, decoding every instruction it can find, and turns
+// the common ones (pushv, add, sub, mul, cmp, jmp, exit) into closures with their arguments already decoded. the run loop then just
+// indexes a table by exec pointer and calls whatever's there, rather than re-reading the opcode and arguments and going through step's
+// dispatch table every time. anything else (and any address the walk didn't land on) falls back to step, so the results are always the same
+// as invoke's.
+// compiled instructions don't call the trace hook, so while one is set everything goes through step.
+// self-modifying code is NOT supported in this mode: compiled instructions keep the arguments they had at compile time, so a program that
+// rewrites its own text will see its old code run wherever the compiler got to first.
+// the instruction cache (Machine::set_insn_cache) uses the same compiled instructions inside step, but decodes each one the first time
+// it runs, and throws it away again whenever something writes over it. so self-modifying code works there, it just doesn't get any faster.
+use crate::error::*;
+use crate::numerical::*;
+use crate::{ Image, Machine };
+use alloc::{ rc::Rc, vec::Vec };
+use core::cmp::Ordering;
+
+
+type Compiled = Rc<dyn Fn(&mut Machine) -> Result<StepResult, InvokeErr>>; // Rc so the cache can hand one out while the machine runs it
+const MAX_INSN_LEN : usize = 17; // the longest instruction_len. a write can change an instruction that starts this far before it, less one
+
+
+#[derive(Clone)]
+pub(crate) enum DecodedInsn { // one byte of the text section, in the instruction cache
+    Undecoded, // nothing has run from here since the cache was last cleared (or this got written over)
+    Step, // step has to run this one: compile_instruction doesn't do it, or it isn't a whole instruction
+    Compiled(Compiled)
+}
+
+
+fn arg_i64(text : &[u8], pos : usize) -> i64 {
+    i64::from_be_bytes(text[pos..pos + 8].try_into().unwrap())
+}
+
+
+fn arithmetic<T : Numerical + 'static>(next : i64, loc1 : i64, loc2 : i64, op : fn(T, T) -> T) -> Compiled {
+    Rc::new(move |m| {
+        if m.overflow_checked { // op wraps, so let step do the checking
+            return m.execute();
+        }
+        m.exec_pointer = next;
+        m.errcode = 0;
+        let val1 : T = m.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = m.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, op(val1, val2)).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    })
+}
+
+
+fn compare<T : Numerical + 'static>(next : i64, loc1 : i64, loc2 : i64) -> Compiled {
+    Rc::new(move |m| {
+        m.exec_pointer = next;
+        m.errcode = 0;
+        let val1 : T = m.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = m.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        let res : u8 = match val1.cmp(&val2) {
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+            Ordering::Less => 2
+        };
+        m.push_as(res).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    })
+}
+
+
+fn compile_instruction(text : &[u8], pos : usize, at : i64) -> Option<Compiled> { // compile the instruction at text[pos], which will live at
+    // the VM address `at`. None means "let step handle it".
+    let op = text[pos];
+    let len = Machine::instruction_len(op)?;
+    let next = at + len as i64;
+    Some(match op {
+        4..=7 => { // pushv
+            let bytes = text[pos + 1..pos + len].to_vec();
+            Rc::new(move |m| {
+                m.exec_pointer = next;
+                m.errcode = 0;
+                m.check_stack(bytes.len() as u64).map_err(InvokeErr::MemErr)?;
+                m.write_bytes(m.stack_pointer, &bytes).map_err(InvokeErr::MemErr)?;
+                m.stack_pointer += bytes.len() as i64;
+                Ok(StepResult::Continue)
+            })
+        },
+        28..=39 | 44..=47 | 132..=135 => {
+            let loc1 = arg_i64(text, pos + 1);
+            let loc2 = arg_i64(text, pos + 9);
+            match op {
+                28 => arithmetic::<u64>(next, loc1, loc2, u64::wrapping_add),
+                29 => arithmetic::<u32>(next, loc1, loc2, u32::wrapping_add),
+                30 => arithmetic::<u16>(next, loc1, loc2, u16::wrapping_add),
+                31 => arithmetic::<u8>(next, loc1, loc2, u8::wrapping_add),
+                32 => arithmetic::<u64>(next, loc1, loc2, u64::wrapping_sub),
+                33 => arithmetic::<u32>(next, loc1, loc2, u32::wrapping_sub),
+                34 => arithmetic::<u16>(next, loc1, loc2, u16::wrapping_sub),
+                35 => arithmetic::<u8>(next, loc1, loc2, u8::wrapping_sub),
+                36 => arithmetic::<u64>(next, loc1, loc2, u64::wrapping_mul),
+                37 => arithmetic::<u32>(next, loc1, loc2, u32::wrapping_mul),
+                38 => arithmetic::<u16>(next, loc1, loc2, u16::wrapping_mul),
+                39 => arithmetic::<u8>(next, loc1, loc2, u8::wrapping_mul),
+                44 => compare::<u64>(next, loc1, loc2),
+                45 => compare::<u32>(next, loc1, loc2),
+                46 => compare::<u16>(next, loc1, loc2),
+                47 => compare::<u8>(next, loc1, loc2),
+                132 => compare::<i64>(next, loc1, loc2),
+                133 => compare::<i32>(next, loc1, loc2),
+                134 => compare::<i16>(next, loc1, loc2),
+                _ => compare::<i8>(next, loc1, loc2)
+            }
+        },
+        63 => { // jmp
+            let target = next.checked_add(arg_i64(text, pos + 1))?; // step turns the overflow into a segfault
+            Rc::new(move |m| {
+                m.exec_pointer = target;
+                m.errcode = 0;
+                Ok(StepResult::Continue)
+            })
+        },
+        73 => { // exit
+            let code = arg_i64(text, pos + 1);
+            Rc::new(move |m| {
+                m.exec_pointer = next;
+                m.errcode = 0;
+                Ok(StepResult::Exited(code))
+            })
+        },
+        _ => return None
+    })
+}
+
+
+impl Image {
+    pub fn compile_to_closure(&self) -> impl FnMut(&mut Machine, &[i64]) -> Result<InvokeResult, InvokeErr> { // compile this image into a
+        // closure that mounts it into a machine, pushes the arguments (as 64-bit values, in order), runs main, and returns how it ended.
+        // the machine has to be big enough to mount the image, just like with mount.
+        let image = self.clone();
+        let text_start = image.static_section.len();
+        let mut compiled : Vec<Option<Compiled>> = (0..image.text_section.len()).map(|_| None).collect();
+        let mut pos = 0;
+        while pos < image.text_section.len() { // linear sweep. data or misaligned code in the text section just means some instructions don't
+            // get compiled, which is harmless.
+            match Machine::instruction_len(image.text_section[pos]) {
+                Some(len) if pos + len <= image.text_section.len() => {
+                    compiled[pos] = compile_instruction(&image.text_section, pos, (text_start + pos) as i64);
+                    pos += len;
+                },
+                _ => pos += 1
+            }
+        }
+        let main = image.function_table.get("main").map(|offset| (text_start as i64) + offset);
+        move |machine, args| {
+            let main = main.ok_or(InvokeErr::BadInstruction)?;
+            machine.mount(&image).map_err(InvokeErr::MemErr)?;
+            machine.start(main);
+            for arg in args {
+                machine.push_as(*arg).map_err(InvokeErr::MemErr)?;
+            }
+            loop {
+                let offset = (machine.exec_pointer as usize).wrapping_sub(text_start);
+                let result = match compiled.get(offset) {
+                    Some(Some(instruction)) if machine.trace.is_none() && machine.opcode_stats.is_none() => { // compiled instructions don't
+                        // call the trace or count, so those go through step
+                        let result = instruction(machine);
+                        machine.catch_fault(result)?
+                    },
+                    _ => machine.step()?
+                };
+                if let Some(result) = machine.finished(result)? {
+                    return Ok(result);
+                }
+            }
+        }
+    }
+}
+
+
+impl Machine {
+    pub fn set_insn_cache(&mut self, enabled : bool) { // cache decoded instructions, so hot loops don't decode the same bytes over and over.
+        // writes to the text section (by the program or the host) drop whatever they overlap, so self-modifying code still works. off by
+        // default: a program that runs everything once only pays for it
+        self.insn_cache = enabled.then(Vec::new);
+        self.clear_insn_cache();
+    }
+
+    pub(crate) fn clear_insn_cache(&mut self) { // forget everything. for when the text section moves or gets replaced wholesale (mount, restore)
+        if let Some(cache) = &mut self.insn_cache {
+            cache.clear();
+            cache.resize((self.stack_start - self.text_start) as usize, DecodedInsn::Undecoded);
+        }
+    }
+
+    pub(crate) fn invalidate_insn_cache(&mut self, start : usize, len : usize) { // memory start..start + len was just written
+        let Some(cache) = &mut self.insn_cache else { return };
+        let text_start = self.text_start as usize;
+        let from = start.saturating_sub(MAX_INSN_LEN - 1).saturating_sub(text_start);
+        let to = start.saturating_add(len).saturating_sub(text_start).min(cache.len());
+        if from < to {
+            cache[from..to].fill(DecodedInsn::Undecoded);
+        }
+    }
+
+    pub(crate) fn execute_cached(&mut self) -> Result<StepResult, InvokeErr> { // execute, through the cache if it's on
+        if self.trace.is_some() || self.opcode_stats.is_some() { // compiled instructions don't call or count
+            return self.execute();
+        }
+        let pos = self.exec_pointer.wrapping_sub(self.text_start) as usize;
+        let Some(slot) = self.insn_cache.as_mut().and_then(|cache| cache.get_mut(pos)) else { return self.execute() };
+        if let DecodedInsn::Undecoded = slot {
+            let text = &self.memory[self.text_start as usize..self.stack_start as usize];
+            *slot = match Machine::instruction_len(text[pos]) {
+                Some(len) if pos + len <= text.len() => compile_instruction(text, pos, self.exec_pointer).map_or(DecodedInsn::Step, DecodedInsn::Compiled),
+                _ => DecodedInsn::Step
+            };
+        }
+        match slot {
+            DecodedInsn::Compiled(insn) => {
+                let insn = insn.clone();
+                insn(self)
+            },
+            _ => self.execute()
+        }
+    }
+}
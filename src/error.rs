@@ -1,17 +1,69 @@
 // error handling and fallable return values stuff
+use core::fmt;
+use alloc::{ string::String, vec::Vec };
 
 
 #[derive(Debug, PartialEq)]
 pub enum InvokeResult {
     Ok(i64),
-    StdabiTestSuccess
+    StdabiTestSuccess,
+    TimedOut, // invoke_timeout ran out of time before the program exited
+    Aborted(u8), // something threw this code with no SBM to catch it
+    Returned(i64) // the function invoke_with_args called returned this (the long in its return slot)
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum StepResult { // outcome of executing a single instruction with Machine::step
+    Continue,
+    Exited(i64),
+    Halted(InvokeResult), // an external called Machine::halt
+    Aborted(u8), // an uncaught throw ended the program
+    Returned, // a ret went back to the host (see invoke_with_args)
+    Yielded // the program ran yield. nothing but a Coroutine stops for it
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum Resumption { // how Coroutine::resume stopped
+    Yielded, // the program ran yield, or used up the budget. resume again to carry on
+    Exited(i64),
+    Finished(InvokeResult), // it ended some other way than exit: an uncaught throw, a halt, or a return to the host
+    Faulted(InvokeErr)
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum BreakOutcome { // how Machine::run_until_break stopped
+    Hit(i64), // the exec pointer got to a breakpoint at this address. the instruction there hasn't run yet
+    Watch(i64), // the instruction at this address wrote to a watched range. it has run, so the exec pointer is past it
+    Finished(InvokeResult) // the program finished without hitting one
 }
 
 
 #[derive(Debug, PartialEq)]
 pub enum MemoryErr { // errors specifically related to memory
     OutOfMemory,
-    SegmentationFault // thrown if you try to do accesses below 0 or beyond the vm memory (rabbit addresses cannot be manipulated by most operations)
+    SegmentationFault, // thrown if you try to do accesses below 0 or beyond the vm memory (rabbit addresses cannot be manipulated by most operations)
+    StackOverflow // a push would have taken the stack past its ceiling (see Machine::set_stack_size)
+}
+
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ErrorCode(pub u8); // a VM error code, as used by throw and geterr (see the throw docs in lib.rs for what they mean)
+
+
+impl ErrorCode {
+    pub const NO_ERROR : ErrorCode = ErrorCode(0);
+    pub const OUT_OF_BOUNDS : ErrorCode = ErrorCode(1);
+    pub const BAD_CALL : ErrorCode = ErrorCode(2);
+    pub const LOOKUP_FAILED : ErrorCode = ErrorCode(3);
+    pub const ALLOC_FAILED : ErrorCode = ErrorCode(4); // tables, the MMU, and running out of memory
+    pub const STACK_OVERFLOW : ErrorCode = ErrorCode(5);
+    pub const ARITHMETIC_OVERFLOW : ErrorCode = ErrorCode(6);
+    pub const ASSERTION_FAILED : ErrorCode = ErrorCode(7);
+    pub const DIVIDE_BY_ZERO : ErrorCode = ErrorCode(8);
+    pub const MMU_CORRUPTED : ErrorCode = ErrorCode(9);
 }
 
 
@@ -21,10 +73,355 @@ pub enum InvokeErr {
     UncaughtThrow(u8),
     BadInstruction,
     StdabiTestFailure,
-    StringProcessingError // failed to build a null-terminated CStr
+    StringProcessingError, // failed to build a null-terminated CStr
+    GasExhausted, // invoke_limited ran out of instructions before the program exited
+    AllocLimitExceeded, // an alloc or realloc asked for more than invoke_sandboxed allows
+    ExternalForbidden, // the program called an external under invoke_sandboxed, which doesn't allow them
+    StackLimitExceeded, // the stack grew past the size invoke_sandboxed allows
+    Interrupted, // the host set the interrupt flag (see Machine::interrupt_handle). resumable, like GasExhausted
+    InterpreterPanic(String) // invoke_catching caught a panic, with its message. the machine is in whatever state the panic left it
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum MmuCorruption { // ways Machine::mmu_check can find the page table broken. pages are numbered from the bottom of the heap.
+    BadHeader, // the page size or page count in the table don't match what startmmu set up
+    UsedCountMismatch { recorded : u32, counted : u32 }, // the table's count of pages in use doesn't agree with the bitmap
+    RunOutOfRange(u32), // the allocation starting at this page runs off the end of the heap
+    RunOverlap(u32), // an allocation starts at this page, but it's inside another allocation
+    FreePageInRun(u32), // this page is part of an allocation but is marked free
+    OrphanPage(u32) // this page is marked in use but isn't part of any allocation
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyErr { // problems Image::verify found. offsets are into the text section.
+    UnknownOpcode(usize, u8), // (offset, opcode)
+    Truncated(usize), // the instruction at this offset runs off the end of the text section
+    BadTarget(usize, i64), // (offset, target): a call, branch or jmp that doesn't land on an instruction boundary in the text section
+    BadFunction(String) // this function table entry doesn't point at an instruction boundary
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum CStrErr { // Machine::read_cstr couldn't read a string
+    MemErr(MemoryErr), // the address is outside of memory, or memory ends before a null
+    Unterminated, // there's no null within the max length
+    NotUtf8(Vec<u8>) // the bytes (without the null) aren't utf-8, so here they are raw
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum LookupErr { // Image::lookup_static or Machine::static_addr couldn't find a symbol
+    NoSuchStatic(String) // there's no public static by this name. private statics aren't in the image's tables at all
 }
 
 
+#[derive(Debug, PartialEq)]
+pub enum RunError { // Machine::run didn't get an exit code
+    NoSuchFunction(String), // the mounted image has no public function by this name
+    Aborted(u8), // something threw this code with no SBM to catch it
+    Stopped(InvokeResult), // the program ended some other way than exit, like an external halting it
+    Invoke(InvokeErr)
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum ImageErr { // problems Image::from_bytes found
+    BadMagic, // it doesn't start with the magic number, so it isn't an image (or it's from a different version of the format)
+    Truncated, // it ends in the middle of something
+    BadName, // a function or static name isn't utf-8
+    BadRelocation, // a relocation has a section or target that doesn't exist
+    BadDebugInfo, // the debug info is neither there nor not there
+    BadFrameSizes, // same for the frame sizes
+    TrailingData // there's more after the end of the image
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum MountErr { // Machine::mount_at couldn't mount an image
+    OutOfMemory, // the image doesn't fit between the base and the end of memory (or the base is negative)
+    NotRelocatable, // the image has no relocation table, so it can only be mounted at 0
+    UnresolvedSymbol(String), // the image still has an extern that Image::link hasn't filled in
+    BadRelocation // one of the image's relocations doesn't fit in its section
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum LinkErr { // problems Image::link found. images are numbered by their position in the list
+    DuplicateSymbol(String), // more than one image exports a function or static by this name
+    UndefinedSymbol(String), // an image uses this extern, but none of the images export it
+    BadRelocation(usize), // one of this image's relocations doesn't fit in its section
+    NotRelocatable(usize) // this image has no relocations (it was built by hand, or by avc), so it can't be moved
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IrError { // something wrong with an IR or AVC program. the span is a range of character offsets into the source.
+    pub span : core::ops::Range<usize>,
+    pub message : String
+}
+
+
+#[cfg(feature = "std")]
+impl From<chumsky::error::Simple<char>> for IrError {
+    fn from(e : chumsky::error::Simple<char>) -> Self {
+        let message = match e.reason() {
+            chumsky::error::SimpleReason::Custom(message) => message.clone(), // Simple's Display doesn't show these
+            _ => e.to_string()
+        };
+        IrError {
+            span : e.span(),
+            message
+        }
+    }
+}
+
+
+pub fn describe_error_code(code : u8) -> &'static str { // human-readable meaning of a VM error code (see the throw docs in lib.rs)
+    match ErrorCode(code) {
+        ErrorCode::NO_ERROR => "no error",
+        ErrorCode::OUT_OF_BOUNDS => "out-of-bounds memory access",
+        ErrorCode::BAD_CALL => "out-of-bounds function call",
+        ErrorCode::LOOKUP_FAILED => "table lookup failure",
+        ErrorCode::ALLOC_FAILED => "table allocation failure",
+        ErrorCode::STACK_OVERFLOW => "stack overflow",
+        ErrorCode::ARITHMETIC_OVERFLOW => "arithmetic overflow",
+        ErrorCode::ASSERTION_FAILED => "assertion failed",
+        ErrorCode::DIVIDE_BY_ZERO => "division by zero",
+        ErrorCode::MMU_CORRUPTED => "MMU page table corrupted",
+        _ => "unknown error"
+    }
+}
+
+
+impl From<MemoryErr> for ErrorCode { // the code a memory error is thrown as when it happens inside an SBM scope
+    fn from(e : MemoryErr) -> Self {
+        match e {
+            MemoryErr::SegmentationFault => ErrorCode::OUT_OF_BOUNDS,
+            MemoryErr::OutOfMemory => ErrorCode::ALLOC_FAILED,
+            MemoryErr::StackOverflow => ErrorCode::STACK_OVERFLOW
+        }
+    }
+}
+
+
+impl TryFrom<ErrorCode> for MemoryErr { // fails (handing the code back) for codes that aren't memory errors
+    type Error = ErrorCode;
+
+    fn try_from(code : ErrorCode) -> Result<Self, ErrorCode> {
+        match code {
+            ErrorCode::OUT_OF_BOUNDS => Ok(MemoryErr::SegmentationFault),
+            ErrorCode::ALLOC_FAILED => Ok(MemoryErr::OutOfMemory),
+            ErrorCode::STACK_OVERFLOW => Ok(MemoryErr::StackOverflow),
+            _ => Err(code)
+        }
+    }
+}
+
+
+impl From<u8> for ErrorCode {
+    fn from(code : u8) -> Self {
+        ErrorCode(code)
+    }
+}
+
+
+impl From<ErrorCode> for u8 {
+    fn from(code : ErrorCode) -> Self {
+        code.0
+    }
+}
+
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.0, describe_error_code(self.0))
+    }
+}
+
+
+impl fmt::Display for MemoryErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfMemory => write!(f, "out of VM memory"),
+            Self::SegmentationFault => write!(f, "segmentation fault (access outside of VM memory)"),
+            Self::StackOverflow => write!(f, "stack overflow")
+        }
+    }
+}
+
+
+impl core::error::Error for MemoryErr {}
+
+
+impl fmt::Display for InvokeErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MemErr(e) => write!(f, "memory error: {}", e),
+            Self::UncaughtThrow(code) => write!(f, "uncaught error {} ({})", code, describe_error_code(*code)),
+            Self::BadInstruction => write!(f, "bad instruction"),
+            Self::StdabiTestFailure => write!(f, "stdabi test failed"),
+            Self::StringProcessingError => write!(f, "couldn't read a null-terminated string"),
+            Self::GasExhausted => write!(f, "instruction limit reached before the program exited"),
+            Self::AllocLimitExceeded => write!(f, "allocation bigger than the sandbox allows"),
+            Self::ExternalForbidden => write!(f, "external call in a sandbox that doesn't allow them"),
+            Self::StackLimitExceeded => write!(f, "stack bigger than the sandbox allows"),
+            Self::Interrupted => write!(f, "interrupted by the host"),
+            Self::InterpreterPanic(message) => write!(f, "interpreter panicked: {}", message)
+        }
+    }
+}
+
+
+impl core::error::Error for InvokeErr {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::MemErr(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+
+impl fmt::Display for MmuCorruption {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadHeader => write!(f, "MMU page table header doesn't match the page size or count the MMU was started with"),
+            Self::UsedCountMismatch { recorded, counted } => write!(f, "MMU page table says {} pages are in use, but {} are marked", recorded, counted),
+            Self::RunOutOfRange(page) => write!(f, "MMU allocation at page {} runs off the end of the heap", page),
+            Self::RunOverlap(page) => write!(f, "MMU allocation at page {} overlaps another allocation", page),
+            Self::FreePageInRun(page) => write!(f, "MMU page {} is part of an allocation but marked free", page),
+            Self::OrphanPage(page) => write!(f, "MMU page {} is marked in use but isn't part of any allocation", page)
+        }
+    }
+}
+
+
+impl core::error::Error for MmuCorruption {}
+
+
+impl fmt::Display for VerifyErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode(offset, op) => write!(f, "unknown opcode {} at text offset {}", op, offset),
+            Self::Truncated(offset) => write!(f, "instruction at text offset {} runs off the end of the text section", offset),
+            Self::BadTarget(offset, target) => write!(f, "instruction at text offset {} jumps to {}, which isn't an instruction boundary", offset, target),
+            Self::BadFunction(name) => write!(f, "function {} doesn't start on an instruction boundary", name)
+        }
+    }
+}
+
+
+impl core::error::Error for VerifyErr {}
+
+
+impl fmt::Display for CStrErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MemErr(e) => write!(f, "couldn't read the string: {}", e),
+            Self::Unterminated => write!(f, "string is longer than the limit"),
+            Self::NotUtf8(bytes) => write!(f, "string of {} bytes isn't utf-8", bytes.len())
+        }
+    }
+}
+
+
+impl core::error::Error for CStrErr {}
+
+
+impl fmt::Display for LookupErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchStatic(name) => write!(f, "no public static called {}", name)
+        }
+    }
+}
+
+
+impl core::error::Error for LookupErr {}
+
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchFunction(name) => write!(f, "no public function called {}", name),
+            Self::Aborted(code) => write!(f, "uncaught error {} ({})", code, describe_error_code(*code)),
+            Self::Stopped(result) => write!(f, "stopped without exiting: {:?}", result),
+            Self::Invoke(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+
+impl core::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Invoke(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+
+impl fmt::Display for ImageErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not an anyvm image"),
+            Self::Truncated => write!(f, "image is truncated"),
+            Self::BadName => write!(f, "image has a name that isn't utf-8"),
+            Self::BadRelocation => write!(f, "image has a bad relocation"),
+            Self::BadDebugInfo => write!(f, "image has bad debug info"),
+            Self::BadFrameSizes => write!(f, "image has bad frame sizes"),
+            Self::TrailingData => write!(f, "image has extra data at the end")
+        }
+    }
+}
+
+
+impl core::error::Error for ImageErr {}
+
+
+impl fmt::Display for MountErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfMemory => write!(f, "image doesn't fit in memory there"),
+            Self::NotRelocatable => write!(f, "image has no relocation table, so it can only be mounted at 0"),
+            Self::UnresolvedSymbol(name) => write!(f, "image uses {}, which hasn't been linked", name),
+            Self::BadRelocation => write!(f, "image has a relocation outside its sections")
+        }
+    }
+}
+
+
+impl core::error::Error for MountErr {}
+
+
+impl fmt::Display for LinkErr {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DuplicateSymbol(name) => write!(f, "{} is exported by more than one image", name),
+            Self::UndefinedSymbol(name) => write!(f, "undefined symbol {}", name),
+            Self::BadRelocation(image) => write!(f, "image {} has a relocation outside its sections", image),
+            Self::NotRelocatable(image) => write!(f, "image {} has no relocation table, so it can't be linked", image)
+        }
+    }
+}
+
+
+impl core::error::Error for LinkErr {}
+
+
+impl fmt::Display for IrError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.span.start, self.span.end)
+    }
+}
+
+
+impl core::error::Error for IrError {}
+
+
 pub fn str_proc_fail<T>(_ : T) -> InvokeErr {
     InvokeErr::StringProcessingError
 }
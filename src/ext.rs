@@ -0,0 +1,162 @@
+// external functions. every external registered with a machine gets a rabbit address; `call`ing or `invokevirtual`ing that address runs the
+// external on the host instead of jumping anywhere. externals take their arguments off the stack and push their results back onto it
+// themselves, and there's no return address involved, since control never leaves the instruction.
+// libraries are tables of externals the host registers by name. the guest docks a library by name (getting the library's rabbit address),
+// and then loadfun looks symbols up in it, handing out a function rabbit for every one it finds (the same one for repeat lookups).
+// the rabbit table maps every live rabbit address to what it does (an external function, or a table). addresses are opaque to the guest,
+// and once one is freed it can be handed out again for something else, so guests shouldn't hang on to rabbits they've been told are gone.
+use crate::{ ExtData, Machine, Table };
+use crate::error::*;
+use alloc::{ boxed::Box, collections::VecDeque, rc::Rc, string::ToString, vec, vec::Vec };
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+
+type ExtFn = Box<dyn FnMut(&mut Machine)>;
+pub(crate) type Unwound = Rc<RefCell<Vec<(i64, ExtFn)>>>;
+
+
+struct Running { // an external that's been taken out of the rabbit table to run. if it panics, dropping this during the unwind leaves it
+    // in the machine's unwound list, so invoke_catching can put it back
+    addr : i64,
+    f : Option<ExtFn>,
+    unwound : Unwound
+}
+
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(f) = self.f.take() {
+            self.unwound.borrow_mut().push((self.addr, f));
+        }
+    }
+}
+
+
+impl Machine {
+    pub fn alloc_rabbit(&mut self, behavior : ExtData) -> i64 { // give something host-side a rabbit address, which the host has to get to
+        // the guest somehow (write it into a static, for instance)
+        let rabbit = self.next_rabbit();
+        self.rabbits.insert(rabbit, behavior);
+        rabbit
+    }
+
+    pub fn free_rabbit(&mut self, rabbit : i64) -> Option<ExtData> { // take a rabbit address out of the table, handing back what it did.
+        // None if it wasn't a live rabbit
+        let behavior = self.rabbits.remove(&rabbit)?;
+        self.ext_names.retain(|_, named| *named != rabbit);
+        self.libraries.retain(|_, named| *named != rabbit);
+        self.loaded_functions.retain(|(lib, _), loaded| *lib != rabbit && *loaded != rabbit);
+        self.free_rabbits.push(rabbit);
+        Some(behavior)
+    }
+
+    pub fn register_ext(&mut self, name : &str, f : impl FnMut(&mut Machine) + 'static) -> i64 { // alloc_rabbit for an external function,
+        // which can be looked up by name later
+        let rabbit = self.alloc_rabbit(ExtData::Function(Box::new(f)));
+        self.ext_names.insert(name.to_string(), rabbit);
+        rabbit
+    }
+
+    pub fn ext_rabbit(&self, name : &str) -> Option<i64> { // the rabbit address of a registered external
+        self.ext_names.get(name).copied()
+    }
+
+    pub fn register_lib(&mut self, name : &str, table : impl Table + 'static) -> i64 { // alloc_rabbit for a library, which the guest can
+        // dock by name. registering a library under a name that's already taken replaces it (the old rabbit stays alive, though)
+        let rabbit = self.alloc_rabbit(ExtData::Table(Box::new(table)));
+        self.libraries.insert(name.to_string(), rabbit);
+        rabbit
+    }
+
+    pub fn lib_rabbit(&self, name : &str) -> Option<i64> { // the rabbit address of a registered library
+        self.libraries.get(name).copied()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_output(&mut self, w : Box<dyn Write>) { // send everything externals print (stdabi's stest, for instance) somewhere other
+        // than stdout: a log, a socket, or a Vec<u8> to check in a test
+        self.output = w;
+    }
+
+    pub fn halt(&mut self, result : InvokeResult) { // for externals: stop the machine once the current instruction finishes, and have
+        // invoke return this instead of waiting for an exit
+        self.halted = Some(result);
+    }
+
+    pub(crate) fn dock(&mut self) -> Result<(), InvokeErr> { // the name is a pointer argument. throws 3 if there's no library by that name
+        let name : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let lib = self.read_str(name)?;
+        match self.libraries.get(lib) {
+            Some(&rabbit) => self.push_as(rabbit).map_err(InvokeErr::MemErr),
+            None => self.throw(ErrorCode::LOOKUP_FAILED)
+        }
+    }
+
+    pub(crate) fn loadfun(&mut self) -> Result<(), InvokeErr> { // the symbol name is a pointer argument, and the library is popped off the
+        // stack. throws 3 if that isn't a library, or the library doesn't have the symbol
+        let name : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let lib : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let key = (lib, self.read_str(name)?.to_string());
+        if let Some(&rabbit) = self.loaded_functions.get(&key) {
+            return self.push_as(rabbit).map_err(InvokeErr::MemErr);
+        }
+        let found = match self.rabbits.get(&lib) {
+            Some(ExtData::Table(table)) => table.lookup(&key.1),
+            _ => None
+        };
+        match found {
+            Some(behavior) => {
+                let rabbit = self.alloc_rabbit(behavior);
+                self.loaded_functions.insert(key, rabbit);
+                self.push_as(rabbit).map_err(InvokeErr::MemErr)
+            },
+            None => self.throw(ErrorCode::LOOKUP_FAILED)
+        }
+    }
+
+    pub(crate) fn call_ext(&mut self, addr : i64) -> Result<bool, InvokeErr> { // run the external at addr. returns false if addr isn't
+        // an external. the external gets the whole machine, so take it out of the table while it runs
+        let f = match self.rabbits.remove(&addr) {
+            Some(ExtData::Function(f)) => f,
+            Some(table) => {
+                self.rabbits.insert(addr, table);
+                return Ok(false);
+            },
+            None => return Ok(false)
+        };
+        if self.sandbox.is_some_and(|sandbox| !sandbox.allow_externals) {
+            self.rabbits.insert(addr, ExtData::Function(f));
+            return Err(InvokeErr::ExternalForbidden);
+        }
+        let mut running = Running { addr, f : Some(f), unwound : self.unwound_externals.clone() };
+        if let Some(f) = &mut running.f {
+            f(self);
+        }
+        if let Some(f) = running.f.take() {
+            self.rabbits.insert(addr, ExtData::Function(f));
+        }
+        Ok(true)
+    }
+
+    pub fn mock_ext(&mut self, name : &str, arg_count : usize, responses : Vec<i64>) -> i64 { // register a scripted external for tests. every
+        // call pops arg_count 64-bit arguments, records them (in the order they were pushed) in the call log, and pushes the next response
+        // (0 once the script runs out). returns the rabbit address, like register_ext.
+        let mut responses = VecDeque::from(responses);
+        let log_name = name.to_string();
+        self.ext_logs.insert(log_name.clone(), vec![]);
+        self.register_ext(name, move |machine| {
+            let mut args = vec![0; arg_count];
+            for arg in args.iter_mut().rev() {
+                *arg = machine.pop_as().unwrap_or(0);
+            }
+            machine.ext_logs.entry(log_name.clone()).or_default().push(args);
+            let _ = machine.push_as(responses.pop_front().unwrap_or(0));
+        })
+    }
+
+    pub fn ext_call_log(&self, name : &str) -> &[Vec<i64>] { // the arguments of every call to a mocked external, oldest first
+        self.ext_logs.get(name).map(|log| log.as_slice()).unwrap_or(&[])
+    }
+}
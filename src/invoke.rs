@@ -1,214 +1,819 @@
 use crate::error::*;
-use crate::{ Machine, RabbitTable, AbiFunction };
-use std::ffi::CStr;
-use std::collections::HashMap;
+use crate::{ Coroutine, Machine, SandboxConfig, StackArg, HOST_RETURN };
+use crate::numerical::Numerical;
+use alloc::{ boxed::Box, string::String, vec::Vec };
+#[cfg(feature = "std")]
+use std::time::{ Duration, Instant };
+
+
+const CLOCK_SAMPLE_INTERVAL : u32 = 1024; // how many instructions invoke_timeout runs between checks of the clock. reading the clock is
+                                          // much slower than most instructions, so checking every time would be a big slowdown.
 
 
 impl Machine {
+    pub(crate) fn start(&mut self, at : i64) { // point the machine at a function with an empty stack, ready to run
+        self.exec_pointer = at;
+        self.stack_pointer = self.stack_start;
+        self.exit_code = None;
+        self.call_stack.clear();
+        self.last_backtrace.clear();
+        self.failed_assertion = None;
+        if let Some(stats) = &mut self.opcode_stats {
+            stats.fill(0);
+        }
+    }
+
     pub fn invoke(&mut self, at : i64) -> Result<InvokeResult, InvokeErr> { // set up the stack and loop through operations until exit() is called
-        self.exec_pointer = at as u64;
-        self.stack_pointer = self.stack_start as u64;
+        self.invoke_limited(at, u64::MAX)
+    }
+
+    pub fn run(&mut self, symbol : &str) -> Result<i64, RunError> { // invoke a public function of the mounted image by name, and hand back
+        // what it passed to exit. for hosts that just want to run main: everything else comes out as a RunError
+        let at = self.function_names.iter().find(|(_, name)| *name == symbol).map(|(at, _)| *at);
+        let at = at.ok_or_else(|| RunError::NoSuchFunction(String::from(symbol)))?;
+        match self.invoke(at).map_err(RunError::Invoke)? {
+            InvokeResult::Ok(code) => Ok(code),
+            InvokeResult::Aborted(code) => Err(RunError::Aborted(code)),
+            other => Err(RunError::Stopped(other))
+        }
+    }
+
+    pub fn invoke_limited(&mut self, at : i64, max_instructions : u64) -> Result<InvokeResult, InvokeErr> { // invoke, but fail with
+        // InvokeErr::GasExhausted after max_instructions instructions have run. the exec and stack pointers are left alone, so the program
+        // can be picked up again with resume_limited.
+        self.start(at);
+        self.run_loop(None, max_instructions)
+    }
+
+    pub fn invoke_with_args(&mut self, at : i64, args : &[StackArg]) -> Result<InvokeResult, InvokeErr> { // call a function the way
+        // a program would: reserve an 8-byte return slot, push the arguments in order, then a return address. buffers are copied onto the
+        // stack first (in order), and their arguments point at the copies. when the function returns, this gives back
+        // InvokeResult::Returned with whatever it left in the return slot; exiting, aborting and halting work just like with invoke.
+        self.start(at);
+        let mut buffers = Vec::new();
+        for arg in args {
+            if let StackArg::Buffer(data) = arg {
+                buffers.push(self.stack_pointer);
+                self.check_stack(data.len() as u64).map_err(InvokeErr::MemErr)?;
+                self.write_bytes(self.stack_pointer, data).map_err(InvokeErr::MemErr)?;
+                self.stack_pointer += data.len() as i64;
+            }
+        }
+        self.return_slot = self.stack_pointer;
+        self.push_as(0i64).map_err(InvokeErr::MemErr)?;
+        let mut buffers = buffers.into_iter();
+        for arg in args {
+            match *arg {
+                StackArg::Long(v) => self.push_as(v),
+                StackArg::Int(v) => self.push_as(v),
+                StackArg::Short(v) => self.push_as(v),
+                StackArg::Byte(v) => self.push_as(v),
+                StackArg::Buffer(_) => self.push_as(buffers.next().unwrap())
+            }.map_err(InvokeErr::MemErr)?;
+        }
+        self.push_as(HOST_RETURN).map_err(InvokeErr::MemErr)?;
+        self.run_loop(None, u64::MAX)
+    }
+
+    pub fn invoke_sandboxed(&mut self, at : i64, config : SandboxConfig) -> Result<InvokeResult, InvokeErr> { // invoke_limited, under all
+        // the limits in config. the machine's own stack size comes back afterwards. for runs that have to match bit for bit (like a validator
+        // checking someone else's result), start from a freshly mounted or reset machine: statics and uniqueid carry over between runs
+        let stack_size = self.stack_size;
+        self.stack_size = config.stack_size;
+        self.sandbox = Some(config);
+        let result = self.invoke_limited(at, config.max_instructions);
+        self.sandbox = None;
+        self.stack_size = stack_size;
+        result
+    }
+
+    pub fn resume_limited(&mut self, max_instructions : u64) -> Result<InvokeResult, InvokeErr> { // keep going from wherever the exec pointer is
+        self.run_loop(None, max_instructions)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn invoke_timeout(&mut self, at : i64, budget : Duration) -> Result<InvokeResult, InvokeErr> { // invoke, but give up with
+        // InvokeResult::TimedOut once `budget` of wall-clock time has passed. the clock is only sampled at instruction boundaries, so a single
+        // long-running instruction (like an external call) can overshoot the budget.
+        self.start(at);
+        let deadline = Instant::now() + budget;
+        self.run_loop(Some(&|| Instant::now() >= deadline), u64::MAX)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn invoke_catching(&mut self, at : i64) -> Result<InvokeResult, InvokeErr> { // invoke, but a panic in the interpreter (or in an
+        // external) comes back as InvokeErr::InterpreterPanic instead of unwinding into the host. a stopgap until the interpreter stops
+        // panicking on bad input, and it can't do anything under panic = "abort". reset or remount the machine before trusting it again
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.invoke(at))).unwrap_or_else(|payload| {
+            let unwound = core::mem::take(&mut *self.unwound_externals.borrow_mut()); // externals that panicked go back where they were
+            for (addr, f) in unwound {
+                self.rabbits.insert(addr, crate::ExtData::Function(f));
+            }
+            let message = payload.downcast_ref::<&str>().map(|message| String::from(*message))
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            Err(InvokeErr::InterpreterPanic(message))
+        })
+    }
+
+    fn run_loop(&mut self, timed_out : Option<&dyn Fn() -> bool>, mut gas : u64) -> Result<InvokeResult, InvokeErr> { // timed_out checks the
+        // clock (which only std has), so it's only called every CLOCK_SAMPLE_INTERVAL instructions
+        let mut until_clock_check = CLOCK_SAMPLE_INTERVAL;
+        let mut until_interrupt_check = self.interrupt_interval;
         loop {
-            let op = self.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
-            let old_errcode = self.errcode;
-            self.errcode = 0;
-            match op {
-                // pushv[l, i, s, b]
-                0 => { self.push::<u64>()?; }, // why, do you ask, did I choose this pattern?
-                1 => { self.push::<u32>()?; }, // you don't want to know.
-                2 => { self.push::<u16>()?; }, // useful for documentation purposes?
-                3 => { self.push::<u8>()?; },  // no. screw off. pretend I didn't do it this way.
-                // push[l, i, s, b]
-                4 => { self.pushv::<u64>()?; },
-                5 => { self.pushv::<u32>()?; },
-                6 => { self.pushv::<u16>()?; },
-                7 => { self.pushv::<u8>()?; },
-                // swap[l, i, s, b]
-                8 => { self.swap::<u64>()?; },
-                9 => { self.swap::<u32>()?; },
-                10 => { self.swap::<u16>()?; },
-                11 => { self.swap::<u8>()?; },
-                // cpy[l, i, s, b]
-                12 => { self.cpy::<u64>()?; },
-                13 => { self.cpy::<u32>()?; },
-                14 => { self.cpy::<u16>()?; },
-                15 => { self.cpy::<u8>()?; },
-                // cpyv[l, i, s, b]
-                16 => { self.cpyv::<u64>()?; },
-                17 => { self.cpyv::<u32>()?; },
-                18 => { self.cpyv::<u16>()?; },
-                19 => { self.cpyv::<u8>()?; },
-                // pop[l, i, s, b]
-                20 => { self.pop::<u64>()?; },
-                21 => { self.pop::<u32>()?; },
-                22 => { self.pop::<u16>()?; },
-                23 => { self.pop::<u8>()?; },
-                // popm[l, i, s, b]
-                24 => { self.popm::<u64>()?; },
-                25 => { self.popm::<u32>()?; },
-                26 => { self.popm::<u16>()?; },
-                27 => { self.popm::<u8>()?; },
-                
-                // arithmetic
-                // add
-                28 => { self.add::<u64>()?; },
-                29 => { self.add::<u32>()?; },
-                30 => { self.add::<u16>()?; },
-                31 => { self.add::<u8>()?; },
-
-                // sub
-                32 => { self.sub::<u64>()?; },
-                33 => { self.sub::<u32>()?; },
-                34 => { self.sub::<u16>()?; },
-                35 => { self.sub::<u8>()?; },
-
-                // mul
-                36 => { self.mul::<u64>()?; },
-                37 => { self.mul::<u32>()?; },
-                38 => { self.mul::<u16>()?; },
-                39 => { self.mul::<u8>()?; },
-
-                // div
-                40 => { self.div::<u64>()?; },
-                41 => { self.div::<u32>()?; },
-                42 => { self.div::<u16>()?; },
-                43 => { self.div::<u8>()?; },
-
-                // logical operations
-                
-                // cmp[l, i, s, b]
-                44 => { self.cmp::<u64>()?; },
-                45 => { self.cmp::<u32>()?; },
-                46 => { self.cmp::<u16>()?; },
-                47 => { self.cmp::<u8>()?; },
-                
-                // cmpv[l, i, s, b]
-                48 => { self.cmpv::<u64>()?; },
-                49 => { self.cmpv::<u32>()?; },
-                50 => { self.cmpv::<u16>()?; },
-                51 => { self.cmpv::<u8>()?; },
-                
-                52 => { // bnot
-                    let loc = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val = self.get_at_as::<u8>(loc).map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc, !val).map_err(InvokeErr::MemErr)?;
-                    Ok(())
-                },
-                53 => { // not
-                    let loc = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val = self.get_at_as::<u8>(loc).map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc, if val == 0 { 1 } else { 0 }).map_err(InvokeErr::MemErr)?;
-                    Ok(())
-                },
-                54 => { // bor
-                    let loc1 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val1 = self.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
-                    let loc2 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val2 = self.get_at_as::<u8>(loc2).map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc1, val1 | val2).map_err(InvokeErr::MemErr)?;
-                },
-                55 => { // vor
-                    let loc1 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val1 = self.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
-                    let val2 = self.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc1, val1 | val2).map_err(InvokeErr::MemErr)?;
-                },
-                56 => { // band
-                    let loc1 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val1 = self.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
-                    let loc2 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val2 = self.get_at_as::<u8>(loc2).map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc1, val1 & val2).map_err(InvokeErr::MemErr)?;
-                },
-                57 => { // vand
-                    let loc1 = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val1 = self.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
-                    let val2 = self.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
-                    self.setmem(loc1, val1 & val2).map_err(InvokeErr::MemErr)?;
-                },
-                // shift[l, i, s, b]
-                58 => { self.shift::<u64>()?; },
-                59 => { self.shift::<u32>()?; },
-                60 => { self.shift::<u16>()?; },
-                61 => { self.shift::<u8>()?; },
-                62 => { // bnorm
-                    let loc = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    let val : u8 = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
-                    self.setmem::<u8>(loc, if val == 0 { 0 } else { 1 });
-                },
-                63 => { // jmp
-                    let amnt : i64 = self.pop_arg();
-                    self.exec_pointer += amnt;
-                },
-
-                // flow control
-                64 => { // branch
-                    let val = self.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
-                    if val == 0 {
-                        self.exec_pointer = pos;
-                    }
-                },
-                65 => { // call
-                    let addr = self.pop_arg::<u64>().map_err(InvokeErr::MemErr)?;
-                    self.push(self.exec_pointer).map_err(InvokeErr::MemErr)?; // push the return address.
-                    // the stack frame should now look like [return value space] [arguments] [return address].
-                    // the first thing the called function should do upon being invoked is increment the stack
-                    // so it looks like [return value space] [arguments] [return address] [locals]
-                    self.exec_pointer = addr;
-                },
-                66 => { // ret
-                    // the called function should have already decremented the stack so [return address]
-                    // is the highest value on it.
-                    let ret_addr = self.pop_as::<u64>().map_err(InvokeErr::MemErr)?;
-                    self.exec_pointer = ret_addr;
-                },
-                67 => { // invokevirtual
-                    let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-                    let place : i64 = self.get_at_as(loc).map_err(InvokeErr:MemErr)?;
-                    self.push(self.exec_pointer).map_err(InvokeErr::MemErr)?;
-                    self.exec_pointer = place;
-                },
-                68 => {
-                    // TODO: invokeext
-                    // grab a function id from memory,
-                    // check if that function id is mapped into the current machine,
-                    // if it is, setsbm and invoke that function
-                    // if it isn't, throw.
-                },
-                69 => { // setsbm
-                    self.push(self.sbm.0).map_err(InvokeErr::MemErr)?;
-                    self.push(self.sbm.1).map_err(InvokeErr::MemErr)?;
-                    self.sbm = (self.stack_pointer, self.exec_pointer + 9);
-                },
-                70 => { // throw
-                    let code : u8 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-                    self.throw(code)?;
-                },
-                71 => { // checkerr
-                    let target : i64 = self.pop_arg();
-                    if old_errcode != 0 {
-                        self.errcode = old_errcode;
-                        self.exec_pointer = target;
-                    }
-                    self.sbm.1 = self.pop_as(); // pop sbm off stack
-                    self.sbm.0 = self.pop_as();
-                },
-                72 => { // geterr
-                    self.push_as(old_errcode);
+            until_interrupt_check -= 1;
+            if until_interrupt_check == 0 {
+                until_interrupt_check = self.interrupt_interval;
+                if self.interrupt.swap(false, core::sync::atomic::Ordering::Relaxed) {
+                    return Err(InvokeErr::Interrupted);
                 }
-                73 => { // exit
-                    let out = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
-                    return Ok(InvokeResult::Ok(out));
-                },
-                74 => {
-                    let pagesize = self.pop_arg::<u32>().map_err(InvokeErr::MemErr);
-                    self.start_mmu(pagesize);
-                },
-                _ => {
-                    return Err(InvokeErr::BadInstruction);
+            }
+            if let Some(timed_out) = timed_out {
+                until_clock_check -= 1;
+                if until_clock_check == 0 {
+                    until_clock_check = CLOCK_SAMPLE_INTERVAL;
+                    if timed_out() {
+                        return Ok(InvokeResult::TimedOut);
+                    }
                 }
             }
+            if gas == 0 {
+                return Err(InvokeErr::GasExhausted);
+            }
+            gas -= 1;
+            let result = self.step()?;
+            if let Some(result) = self.finished(result)? {
+                return Ok(result);
+            }
+        }
+    }
+
+    pub(crate) fn finished(&mut self, result : StepResult) -> Result<Option<InvokeResult>, InvokeErr> { // how the run ended, if that step ended it
+        Ok(match result {
+            StepResult::Exited(code) => Some(InvokeResult::Ok(code)),
+            StepResult::Halted(result) => Some(result),
+            StepResult::Aborted(code) => Some(InvokeResult::Aborted(code)),
+            StepResult::Returned => Some(InvokeResult::Returned(self.get_at_as(self.return_slot).map_err(InvokeErr::MemErr)?)),
+            StepResult::Continue | StepResult::Yielded => None
+        })
+    }
+
+    pub fn invoke_resumable(&mut self, at : i64) -> Coroutine<'_> { // set up a run like invoke, but hand back a Coroutine to run it a slice
+        // at a time, instead of running it to the end
+        self.start(at);
+        Coroutine { machine : self, finished : false }
+    }
+
+    pub fn run_until_break(&mut self, at : i64) -> Result<BreakOutcome, InvokeErr> { // invoke, but stop before any instruction with a
+        // breakpoint on it (the very first one included), or after one that writes to a watchpoint, with everything left as it is for a
+        // debugger to look at
+        self.start(at);
+        self.run_breakable(None)
+    }
+
+    pub fn resume_until_break(&mut self) -> Result<BreakOutcome, InvokeErr> { // carry on after run_until_break stopped. if it stopped on a
+        // breakpoint, that instruction runs first, so the same breakpoint doesn't fire again straight away
+        let paused_at = self.paused_at.take();
+        self.run_breakable(paused_at)
+    }
+
+    fn run_breakable(&mut self, mut skip : Option<i64>) -> Result<BreakOutcome, InvokeErr> {
+        self.paused_at = None;
+        loop {
+            if skip != Some(self.exec_pointer) && self.breakpoints.contains(&self.exec_pointer) {
+                self.paused_at = Some(self.exec_pointer);
+                return Ok(BreakOutcome::Hit(self.exec_pointer));
+            }
+            skip = None;
+            let at = self.exec_pointer;
+            self.watch_hit = false;
+            let result = self.step()?;
+            if let Some(result) = self.finished(result)? {
+                return Ok(BreakOutcome::Finished(result));
+            }
+            if self.watch_hit {
+                return Ok(BreakOutcome::Watch(at));
+            }
         }
-        Ok(InvokeResult::Ok(0))
+    }
+
+    pub fn add_breakpoint(&mut self, addr : i64) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr : i64) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn add_watchpoint(&mut self, addr : i64, len : usize) { // make run_until_break stop after any instruction that writes to these len
+        // bytes. addr is absolute: the stack moves, so a stack-relative watch wouldn't mean anything
+        assert!(addr >= 0, "watchpoints need an absolute address, not {}", addr);
+        self.watchpoints.push((addr as usize, len));
+    }
+
+    pub fn remove_watchpoint(&mut self, addr : i64) { // every watchpoint starting at addr
+        self.watchpoints.retain(|&(start, _)| start as i64 != addr);
+    }
+
+    pub fn step(&mut self) -> Result<StepResult, InvokeErr> { // execute a single instruction at the exec pointer
+        let result = self.execute_cached();
+        let result = self.catch_fault(result)?;
+        match self.halted.take() {
+            Some(halted) => Ok(StepResult::Halted(halted)),
+            None => Ok(result)
+        }
+    }
+
+    pub(crate) fn catch_fault(&mut self, result : Result<StepResult, InvokeErr>) -> Result<StepResult, InvokeErr> { // memory faults inside
+        // an SBM scope are thrown, so they can be caught, and throws nothing caught end the program. anything that runs instructions has
+        // to send their results through here.
+        match result {
+            Err(InvokeErr::MemErr(MemoryErr::StackOverflow)) if self.at_sandbox_stack_limit() => { // a hard limit: the guest can't catch it
+                self.capture_backtrace();
+                Err(InvokeErr::StackLimitExceeded)
+            },
+            Err(InvokeErr::MemErr(e)) if self.sbm != (0, 0) => {
+                self.throw(ErrorCode::from(e))?;
+                Ok(StepResult::Continue)
+            },
+            Err(InvokeErr::UncaughtThrow(code)) => Ok(StepResult::Aborted(code)), // throw already took the backtrace
+            Ok(StepResult::Exited(code)) => {
+                self.exit_code = Some(code);
+                Ok(StepResult::Exited(code))
+            },
+            Err(e) => {
+                self.capture_backtrace();
+                Err(e)
+            },
+            result => result
+        }
+    }
+
+    pub(crate) fn execute(&mut self) -> Result<StepResult, InvokeErr> {
+        let at = self.exec_pointer;
+        if at < 0 { // code can't run off the stack, and pop_arg counts on it
+            return Err(InvokeErr::MemErr(MemoryErr::SegmentationFault));
+        }
+        let op = self.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
+        if let Some(trace) = &mut self.trace {
+            trace(at, op, self.stack_pointer);
+        }
+        if let Some(stats) = &mut self.opcode_stats {
+            stats[op as usize] += 1;
+        }
+        let old_errcode = self.errcode;
+        self.errcode = 0;
+        match DISPATCH[op as usize] {
+            Some(handler) => handler(self, Fetched { at, errcode : old_errcode }),
+            None => Err(InvokeErr::BadInstruction)
+        }
+    }
+
+    pub(crate) fn instruction_len(op : u8) -> Option<usize> { // total length (opcode byte included) of an instruction, or None if the
+        // opcode isn't one step knows. this has to be kept in sync with DISPATCH!
+        Some(match op {
+            0..=4 | 24..=27 | 52 | 53 | 62 | 63 | 65 | 67 | 71 | 73 | 83 | 126 | 130 | 131 | 160..=171 => 9,
+            5 | 74 => 5,
+            6 => 3,
+            7 | 70 => 2,
+            8..=16 | 28..=48 | 54 | 56 | 64 | 84 | 89 | 91..=110 | 112..=121 | 132..=159 | 187..=198 | 201..=204 => 17,
+            17 | 49 => 13,
+            18 | 50 => 11,
+            19 | 51 | 55 | 57 | 58..=61 | 85..=88 | 90 | 129 | 172..=179 => 10,
+            20..=23 | 66 | 68 | 69 | 72 | 75..=77 | 111 | 122..=125 | 127 | 128 | 180..=186 | 199 | 200 => 1,
+            _ => return None
+        })
+    }
+
+    pub fn set_trace(&mut self, f : impl FnMut(i64, u8, i64) + 'static) { // call f(exec pointer, opcode, stack pointer) before every
+        // instruction. handy for profilers, coverage maps, and step logs.
+        self.trace = Some(Box::new(f));
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace = None;
+    }
+
+    pub fn enable_opcode_stats(&mut self) { // count how many times each opcode runs, for seeing what a compiler's output spends its time on.
+        // the counts start over with every invoke. compiled instructions (see set_insn_cache) can't count themselves, so the cache is
+        // bypassed while this is on
+        self.opcode_stats.get_or_insert_with(|| Box::new([0; 256]));
+    }
+
+    pub fn disable_opcode_stats(&mut self) {
+        self.opcode_stats = None;
+    }
+
+    pub fn opcode_stats(&self) -> [u64; 256] { // how many times each opcode ran in the last run, by opcode. all 0 if counting is off
+        self.opcode_stats.as_deref().copied().unwrap_or([0; 256])
+    }
+
+    pub fn exec_pointer(&self) -> i64 {
+        self.exec_pointer
+    }
+
+    pub fn set_exec_pointer(&mut self, at : i64) { // point the machine at a function (or anywhere else) to step through it
+        self.exec_pointer = at;
+    }
+
+    pub fn stack_pointer(&self) -> i64 {
+        self.stack_pointer
     }
 }
+
+
+impl Coroutine<'_> {
+    pub fn resume(&mut self, budget : u64) -> Resumption { // run until the program yields, finishes, or has run budget instructions. once
+        // it's done anything but yield, there's nothing left to resume
+        assert!(!self.finished, "resumed a coroutine that already finished");
+        let resumption = self.run(budget);
+        self.finished = resumption != Resumption::Yielded;
+        resumption
+    }
+
+    fn run(&mut self, budget : u64) -> Resumption {
+        for _ in 0..budget {
+            let result = match self.machine.step() {
+                Ok(StepResult::Yielded) => return Resumption::Yielded,
+                Ok(result) => self.machine.finished(result),
+                Err(e) => return Resumption::Faulted(e)
+            };
+            match result {
+                Ok(None) => {},
+                Ok(Some(InvokeResult::Ok(code))) => return Resumption::Exited(code),
+                Ok(Some(result)) => return Resumption::Finished(result),
+                Err(e) => return Resumption::Faulted(e)
+            }
+        }
+        Resumption::Yielded
+    }
+
+    pub fn machine(&mut self) -> &mut Machine { // for reading results out, or poking at memory between slices
+        self.machine
+    }
+}
+
+
+pub(crate) type Handler = fn(&mut Machine, Fetched) -> Result<StepResult, InvokeErr>;
+
+
+#[derive(Clone, Copy)]
+pub(crate) struct Fetched { // what execute knew before it ran the handler
+    at : i64, // where the opcode was
+    errcode : u8 // the error code from the last instruction. execute has already cleared it, but checkerr and friends need it
+}
+
+
+macro_rules! op { // a handler that's just a call to one of Machine's instruction methods
+    ($m:ident => $call:expr) => {
+        Some(|$m, _| { $call?; Ok(StepResult::Continue) })
+    };
+}
+
+
+pub(crate) static DISPATCH : [Option<Handler>; 256] = { // what execute runs for each opcode. None is a bad instruction; anything that isn't None
+    // needs an instruction_len, and the other way around
+    let mut t : [Option<Handler>; 256] = [None; 256];
+    // pushv[l, i, s, b]
+    t[0] = op!(m => m.push::<u64>()); // why, do you ask, did I choose this pattern?
+    t[1] = op!(m => m.push::<u32>()); // you don't want to know.
+    t[2] = op!(m => m.push::<u16>()); // useful for documentation purposes?
+    t[3] = op!(m => m.push::<u8>()); // no. screw off. pretend I didn't do it this way.
+    // push[l, i, s, b]
+    t[4] = op!(m => m.pushv::<u64>());
+    t[5] = op!(m => m.pushv::<u32>());
+    t[6] = op!(m => m.pushv::<u16>());
+    t[7] = op!(m => m.pushv::<u8>());
+    // swap[l, i, s, b]
+    t[8] = op!(m => m.swap::<u64>());
+    t[9] = op!(m => m.swap::<u32>());
+    t[10] = op!(m => m.swap::<u16>());
+    t[11] = op!(m => m.swap::<u8>());
+    // cpy[l, i, s, b]
+    t[12] = op!(m => m.cpy::<u64>());
+    t[13] = op!(m => m.cpy::<u32>());
+    t[14] = op!(m => m.cpy::<u16>());
+    t[15] = op!(m => m.cpy::<u8>());
+    // cpyv[l, i, s, b]
+    t[16] = op!(m => m.cpyv::<u64>());
+    t[17] = op!(m => m.cpyv::<u32>());
+    t[18] = op!(m => m.cpyv::<u16>());
+    t[19] = op!(m => m.cpyv::<u8>());
+    // pop[l, i, s, b]
+    t[20] = op!(m => m.pop::<u64>());
+    t[21] = op!(m => m.pop::<u32>());
+    t[22] = op!(m => m.pop::<u16>());
+    t[23] = op!(m => m.pop::<u8>());
+    // popm[l, i, s, b]
+    t[24] = op!(m => m.popm::<u64>());
+    t[25] = op!(m => m.popm::<u32>());
+    t[26] = op!(m => m.popm::<u16>());
+    t[27] = op!(m => m.popm::<u8>());
+    
+    // arithmetic
+    // add
+    t[28] = op!(m => m.add::<u64>());
+    t[29] = op!(m => m.add::<u32>());
+    t[30] = op!(m => m.add::<u16>());
+    t[31] = op!(m => m.add::<u8>());
+
+    // sub
+    t[32] = op!(m => m.sub::<u64>());
+    t[33] = op!(m => m.sub::<u32>());
+    t[34] = op!(m => m.sub::<u16>());
+    t[35] = op!(m => m.sub::<u8>());
+
+    // mul
+    t[36] = op!(m => m.mul::<u64>());
+    t[37] = op!(m => m.mul::<u32>());
+    t[38] = op!(m => m.mul::<u16>());
+    t[39] = op!(m => m.mul::<u8>());
+
+    // div
+    t[40] = op!(m => m.div::<u64>());
+    t[41] = op!(m => m.div::<u32>());
+    t[42] = op!(m => m.div::<u16>());
+    t[43] = op!(m => m.div::<u8>());
+
+    // logical operations
+    
+    // cmp[l, i, s, b]
+    t[44] = op!(m => m.cmp::<u64>());
+    t[45] = op!(m => m.cmp::<u32>());
+    t[46] = op!(m => m.cmp::<u16>());
+    t[47] = op!(m => m.cmp::<u8>());
+    
+    // cmpv[l, i, s, b]
+    t[48] = op!(m => m.cmpv::<u64>());
+    t[49] = op!(m => m.cmpv::<u32>());
+    t[50] = op!(m => m.cmpv::<u16>());
+    t[51] = op!(m => m.cmpv::<u8>());
+
+    t[52] = Some(|m, _| { // bnot
+        let loc = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val = m.get_at_as::<u8>(loc).map_err(InvokeErr::MemErr)?;
+        m.setmem(loc, !val).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[53] = Some(|m, _| { // not
+        let loc = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val = m.get_at_as::<u8>(loc).map_err(InvokeErr::MemErr)?;
+        m.setmem::<u8>(loc, if val == 0 { 1 } else { 0 }).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[54] = Some(|m, _| { // bor
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let loc2 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val2 = m.get_at_as::<u8>(loc2).map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 | val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[55] = Some(|m, _| { // vor
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 = m.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 | val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[56] = Some(|m, _| { // band
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let loc2 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val2 = m.get_at_as::<u8>(loc2).map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 & val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[57] = Some(|m, _| { // vand
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 = m.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 & val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    // shift[l, i, s, b]
+    t[58] = op!(m => m.shift::<u64>());
+    t[59] = op!(m => m.shift::<u32>());
+    t[60] = op!(m => m.shift::<u16>());
+    t[61] = op!(m => m.shift::<u8>());
+    t[62] = Some(|m, _| { // bnorm
+        let loc = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val : u8 = m.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        m.setmem::<u8>(loc, if val == 0 { 0 } else { 1 }).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[63] = Some(|m, _| { // jmp
+        let amnt : i64 = m.pop_arg().map_err(InvokeErr::MemErr)?; // the exec pointer is on the next instruction now, which is
+        // what the offset is relative to. a target past the end of the address space is as bad as one before the start
+        m.exec_pointer = m.exec_pointer.checked_add(amnt).ok_or(InvokeErr::MemErr(MemoryErr::SegmentationFault))?;
+        Ok(StepResult::Continue)
+    });
+
+    // flow control
+    t[64] = Some(|m, _| { // branch
+        let loc = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let pos = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?; // always consumed, so falling through lands on the next instruction
+        let val : u8 = m.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        if val == 0 {
+            m.exec_pointer = pos;
+        }
+        Ok(StepResult::Continue)
+    });
+    t[65] = Some(|m, _| { // call
+        let addr = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        if m.call_ext(addr)? {
+            return Ok(StepResult::Continue);
+        }
+        m.push_as(m.exec_pointer).map_err(InvokeErr::MemErr)?; // push the return address.
+        // the stack frame should now look like [return value space] [arguments] [return address].
+        // the first thing the called function should do upon being invoked is increment the stack
+        // so it looks like [return value space] [arguments] [return address] [locals]
+        m.call_stack.push((addr, m.exec_pointer, m.stack_pointer));
+        m.exec_pointer = addr;
+        Ok(StepResult::Continue)
+    });
+    t[66] = Some(|m, _| { // ret
+        // the called function should have already decremented the stack so [return address]
+        // is the highest value on it.
+        let ret_addr = m.pop_as::<i64>().map_err(InvokeErr::MemErr)?;
+        m.call_stack.pop();
+        m.exec_pointer = ret_addr;
+        if ret_addr == HOST_RETURN {
+            return Ok(StepResult::Returned);
+        }
+        Ok(StepResult::Continue)
+    });
+    t[67] = Some(|m, _| { // invokevirtual
+        let loc : i64 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        let place : i64 = m.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        if m.call_ext(place)? {
+            return Ok(StepResult::Continue);
+        }
+        m.push_as(m.exec_pointer).map_err(InvokeErr::MemErr)?;
+        m.call_stack.push((place, m.exec_pointer, m.stack_pointer));
+        m.exec_pointer = place;
+        Ok(StepResult::Continue)
+    });
+    t[68] = Some(|_, _| {
+        // TODO: invokeext
+        // grab a function id from memory,
+        // check if that function id is mapped into the current machine,
+        // if it is, setsbm and invoke that function
+        // if it isn't, throw.
+        Ok(StepResult::Continue)
+    });
+    t[69] = Some(|m, _| { // setsbm
+        m.push_as(m.sbm.0).map_err(InvokeErr::MemErr)?;
+        m.push_as(m.sbm.1).map_err(InvokeErr::MemErr)?;
+        m.sbm = (m.stack_pointer, m.exec_pointer + 9);
+        Ok(StepResult::Continue)
+    });
+    t[70] = Some(|m, _| { // throw
+        let code : u8 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        m.throw(ErrorCode(code))?;
+        Ok(StepResult::Continue)
+    });
+    t[71] = Some(|m, fetched| { // checkerr
+        let target : i64 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        m.sbm.1 = m.pop_as().map_err(InvokeErr::MemErr)?; // pop sbm off stack first, whether or not we branch
+        m.sbm.0 = m.pop_as().map_err(InvokeErr::MemErr)?;
+        if fetched.errcode != 0 {
+            m.errcode = fetched.errcode;
+            m.exec_pointer = target;
+        }
+        Ok(StepResult::Continue)
+    });
+    t[129] = Some(|m, fetched| { // checkerrcode
+        let target : i64 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        let expected : u8 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        m.sbm.1 = m.pop_as().map_err(InvokeErr::MemErr)?; // pop sbm off stack, so a rethrow goes to the next scope out
+        m.sbm.0 = m.pop_as().map_err(InvokeErr::MemErr)?;
+        if fetched.errcode != 0 {
+            if fetched.errcode == expected {
+                m.errcode = fetched.errcode;
+                m.exec_pointer = target;
+            }
+            else {
+                m.throw(ErrorCode(fetched.errcode))?;
+            }
+        }
+        Ok(StepResult::Continue)
+    });
+    t[72] = Some(|m, fetched| { // geterr
+        m.errcode = fetched.errcode; // still there for the next instruction
+        m.push_as(fetched.errcode).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[73] = Some(|m, _| { // exit
+        let out = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Exited(out))
+    });
+    t[74] = Some(|m, _| { // startmmu
+        let pagesize = m.pop_arg::<u32>().map_err(InvokeErr::MemErr)?;
+        m.start_mmu(pagesize)?;
+        Ok(StepResult::Continue)
+    });
+    t[75] = op!(m => m.alloc());
+    t[76] = op!(m => m.dealloc());
+    t[77] = op!(m => m.realloc());
+    t[83] = Some(|m, _| { // updstck
+        let amount : i64 = m.pop_arg().map_err(InvokeErr::MemErr)?;
+        let top = m.stack_pointer.saturating_add(amount);
+        if top < 0 {
+            return Err(InvokeErr::MemErr(MemoryErr::SegmentationFault));
+        }
+        if top > m.stack_ceiling() {
+            return Err(InvokeErr::MemErr(MemoryErr::StackOverflow));
+        }
+        m.stack_pointer = top;
+        Ok(StepResult::Continue)
+    });
+    t[84] = op!(m => m.scrub());
+    // ashift[l, i, s, b]
+    t[85] = op!(m => m.shift::<i64>());
+    t[86] = op!(m => m.shift::<i32>());
+    t[87] = op!(m => m.shift::<i16>());
+    t[88] = op!(m => m.shift::<i8>());
+    t[89] = Some(|m, _| { // bxor
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let loc2 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val2 = m.get_at_as::<u8>(loc2).map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 ^ val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[90] = Some(|m, _| { // vxor
+        let loc1 = m.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
+        let val1 = m.get_at_as::<u8>(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 = m.pop_arg::<u8>().map_err(InvokeErr::MemErr)?;
+        m.setmem(loc1, val1 ^ val2).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    // urem[l, i, s, b]
+    t[91] = op!(m => m.rem::<u64>());
+    t[92] = op!(m => m.rem::<u32>());
+    t[93] = op!(m => m.rem::<u16>());
+    t[94] = op!(m => m.rem::<u8>());
+    // srem[l, i, s, b]
+    t[95] = op!(m => m.rem::<i64>());
+    t[96] = op!(m => m.rem::<i32>());
+    t[97] = op!(m => m.rem::<i16>());
+    t[98] = op!(m => m.rem::<i8>());
+    // or[l, i, s, b]
+    t[99] = op!(m => m.or::<u64>());
+    t[100] = op!(m => m.or::<u32>());
+    t[101] = op!(m => m.or::<u16>());
+    t[102] = op!(m => m.or::<u8>());
+    // and[l, i, s, b]
+    t[103] = op!(m => m.and::<u64>());
+    t[104] = op!(m => m.and::<u32>());
+    t[105] = op!(m => m.and::<u16>());
+    t[106] = op!(m => m.and::<u8>());
+    // xor[l, i, s, b]
+    t[107] = op!(m => m.xor::<u64>());
+    t[108] = op!(m => m.xor::<u32>());
+    t[109] = op!(m => m.xor::<u16>());
+    t[110] = op!(m => m.xor::<u8>());
+    t[111] = Some(|m, _| { // mmucheck
+        if m.mmu_check().is_err() {
+            m.throw(ErrorCode::MMU_CORRUPTED)?;
+        }
+        Ok(StepResult::Continue)
+    });
+    // fadd[l, i]
+    t[112] = op!(m => m.fadd::<f64>());
+    t[113] = op!(m => m.fadd::<f32>());
+    // fsub[l, i]
+    t[114] = op!(m => m.fsub::<f64>());
+    t[115] = op!(m => m.fsub::<f32>());
+    // fmul[l, i]
+    t[116] = op!(m => m.fmul::<f64>());
+    t[117] = op!(m => m.fmul::<f32>());
+    // fdiv[l, i]
+    t[118] = op!(m => m.fdiv::<f64>());
+    t[119] = op!(m => m.fdiv::<f32>());
+    // fcmp[l, i]
+    t[120] = op!(m => m.fcmp::<f64>());
+    t[121] = op!(m => m.fcmp::<f32>());
+    // cas[l, i, s, b]
+    t[122] = op!(m => m.cas::<u64>());
+    t[123] = op!(m => m.cas::<u32>());
+    t[124] = op!(m => m.cas::<u16>());
+    t[125] = op!(m => m.cas::<u8>());
+    t[126] = op!(m => m.dupblock());
+    t[127] = Some(|m, _| { // uniqueid
+        m.push_as(m.next_id).map_err(InvokeErr::MemErr)?;
+        m.next_id += 1;
+        Ok(StepResult::Continue)
+    });
+    t[128] = Some(|m, fetched| { // curfn
+        let here = fetched.at - m.text_start;
+        match m.function_extents.iter().find(|(start, end)| *start <= here && here < *end) {
+            Some(&(start, end)) => {
+                m.push_as(start).map_err(InvokeErr::MemErr)?;
+                m.push_as(end).map_err(InvokeErr::MemErr)?;
+            },
+            None => m.throw(ErrorCode::BAD_CALL)?
+        }
+        Ok(StepResult::Continue)
+    });
+    t[130] = op!(m => m.dock());
+    t[131] = op!(m => m.loadfun());
+    // scmp[l, i, s, b]
+    t[132] = op!(m => m.cmp::<i64>());
+    t[133] = op!(m => m.cmp::<i32>());
+    t[134] = op!(m => m.cmp::<i16>());
+    t[135] = op!(m => m.cmp::<i8>());
+    // satadd[l, i, s, b]
+    t[136] = op!(m => m.saturate::<u64>(Numerical::saturating_add));
+    t[137] = op!(m => m.saturate::<u32>(Numerical::saturating_add));
+    t[138] = op!(m => m.saturate::<u16>(Numerical::saturating_add));
+    t[139] = op!(m => m.saturate::<u8>(Numerical::saturating_add));
+    // satsub[l, i, s, b]
+    t[140] = op!(m => m.saturate::<u64>(Numerical::saturating_sub));
+    t[141] = op!(m => m.saturate::<u32>(Numerical::saturating_sub));
+    t[142] = op!(m => m.saturate::<u16>(Numerical::saturating_sub));
+    t[143] = op!(m => m.saturate::<u8>(Numerical::saturating_sub));
+    // satmul[l, i, s, b]
+    t[144] = op!(m => m.saturate::<u64>(Numerical::saturating_mul));
+    t[145] = op!(m => m.saturate::<u32>(Numerical::saturating_mul));
+    t[146] = op!(m => m.saturate::<u16>(Numerical::saturating_mul));
+    t[147] = op!(m => m.saturate::<u8>(Numerical::saturating_mul));
+    // ssatadd[l, i, s, b]
+    t[148] = op!(m => m.saturate::<i64>(Numerical::saturating_add));
+    t[149] = op!(m => m.saturate::<i32>(Numerical::saturating_add));
+    t[150] = op!(m => m.saturate::<i16>(Numerical::saturating_add));
+    t[151] = op!(m => m.saturate::<i8>(Numerical::saturating_add));
+    // ssatsub[l, i, s, b]
+    t[152] = op!(m => m.saturate::<i64>(Numerical::saturating_sub));
+    t[153] = op!(m => m.saturate::<i32>(Numerical::saturating_sub));
+    t[154] = op!(m => m.saturate::<i16>(Numerical::saturating_sub));
+    t[155] = op!(m => m.saturate::<i8>(Numerical::saturating_sub));
+    // ssatmul[l, i, s, b]
+    t[156] = op!(m => m.saturate::<i64>(Numerical::saturating_mul));
+    t[157] = op!(m => m.saturate::<i32>(Numerical::saturating_mul));
+    t[158] = op!(m => m.saturate::<i16>(Numerical::saturating_mul));
+    t[159] = op!(m => m.saturate::<i8>(Numerical::saturating_mul));
+    // popcnt[l, i, s, b]
+    t[160] = op!(m => m.count_bits::<u64>(Numerical::count_ones));
+    t[161] = op!(m => m.count_bits::<u32>(Numerical::count_ones));
+    t[162] = op!(m => m.count_bits::<u16>(Numerical::count_ones));
+    t[163] = op!(m => m.count_bits::<u8>(Numerical::count_ones));
+    // clz[l, i, s, b]
+    t[164] = op!(m => m.count_bits::<u64>(Numerical::leading_zeros));
+    t[165] = op!(m => m.count_bits::<u32>(Numerical::leading_zeros));
+    t[166] = op!(m => m.count_bits::<u16>(Numerical::leading_zeros));
+    t[167] = op!(m => m.count_bits::<u8>(Numerical::leading_zeros));
+    // ctz[l, i, s, b]
+    t[168] = op!(m => m.count_bits::<u64>(Numerical::trailing_zeros));
+    t[169] = op!(m => m.count_bits::<u32>(Numerical::trailing_zeros));
+    t[170] = op!(m => m.count_bits::<u16>(Numerical::trailing_zeros));
+    t[171] = op!(m => m.count_bits::<u8>(Numerical::trailing_zeros));
+    // rotl[l, i, s, b]
+    t[172] = op!(m => m.rotate::<u64>(true));
+    t[173] = op!(m => m.rotate::<u32>(true));
+    t[174] = op!(m => m.rotate::<u16>(true));
+    t[175] = op!(m => m.rotate::<u8>(true));
+    // rotr[l, i, s, b]
+    t[176] = op!(m => m.rotate::<u64>(false));
+    t[177] = op!(m => m.rotate::<u32>(false));
+    t[178] = op!(m => m.rotate::<u16>(false));
+    t[179] = op!(m => m.rotate::<u8>(false));
+    t[180] = op!(m => m.memcpy(false));
+    t[181] = op!(m => m.memcpy(true));
+    t[182] = op!(m => m.memset());
+    t[183] = op!(m => m.memcmp());
+    t[184] = op!(m => m.strlen());
+    t[185] = op!(m => m.memstat());
+    t[186] = Some(|_, _| Ok(StepResult::Yielded)); // yield
+    // f2i[l, i], f2u[l, i]
+    t[187] = op!(m => m.convert(|v : f64| v as i64));
+    t[188] = op!(m => m.convert(|v : f32| v as i32));
+    t[189] = op!(m => m.convert(|v : f64| v as u64));
+    t[190] = op!(m => m.convert(|v : f32| v as u32));
+    // i2f[l, i], u2f[l, i]
+    t[191] = op!(m => m.convert(|v : i64| v as f64));
+    t[192] = op!(m => m.convert(|v : i32| v as f32));
+    t[193] = op!(m => m.convert(|v : u64| v as f64));
+    t[194] = op!(m => m.convert(|v : u32| v as f32));
+    // assert[l, i, s, b]
+    t[195] = op!(m => m.assert_nonzero(8));
+    t[196] = op!(m => m.assert_nonzero(4));
+    t[197] = op!(m => m.assert_nonzero(2));
+    t[198] = op!(m => m.assert_nonzero(1));
+    t[199] = Some(|m, _| { // getsbm
+        m.push_as(m.sbm.0).map_err(InvokeErr::MemErr)?;
+        m.push_as(m.sbm.1).map_err(InvokeErr::MemErr)?;
+        Ok(StepResult::Continue)
+    });
+    t[200] = Some(|m, _| { // clearsbm
+        m.sbm = (0, 0);
+        Ok(StepResult::Continue)
+    });
+    // sdiv[l, i, s, b]
+    t[201] = op!(m => m.div::<i64>());
+    t[202] = op!(m => m.div::<i32>());
+    t[203] = op!(m => m.div::<i16>());
+    t[204] = op!(m => m.div::<i8>());
+    t
+};
@@ -1,214 +1,649 @@
-use crate::Image;
+use crate::{ DebugInfo, Image, Relocation, RelocTarget, Section, SourceSpan };
+use crate::error::IrError;
 use chumsky::prelude::*;
 use std::collections::HashMap;
+use std::fmt::Write;
 
 
 #[derive(Debug, Clone)]
 enum Value {
-    String(String),
+    String(Vec<u8>), // already escaped, so it can hold any bytes
     Bytes(Vec<u8>),
     Word(u64),
     StaticAccess(String),
     Number(i64),
     Byte(u8),
-    SignedWord(i64)
+    Short(u16),
+    Int(u32),
+    SignedWord(i64),
+    Float(f32),
+    Double(f64),
+    Label(String) // a reference to a label in the current function. resolved by Operation::dump_into.
+}
+
+
+struct Symbols { // everything a $name can refer to
+    functions : HashMap<String, i64>, // absolute addresses, like everything else here
+    statics : HashMap<String, i64>,
+    externs : Vec<String> // declared with `.name extern`: defined by another image, and filled in by Image::link
 }
 
 
 impl Value {
-    fn cast(&self, tp : &str) -> Value {
-        if tp == "word" {
-            if let Self::Number(n) = self {
-                return Value::Word(*n as u64);
-            }
-            else if let Self::StaticAccess(_) = self {
-                return self.clone(); // static accesses are unsigned words
-            }
-        }
-        if tp == "bytes" {
-            if let Self::String(s) = self {
-                return Value::Bytes(s.as_bytes().to_vec());
-            }
-        }
-        if tp == "byte" {
-            if let Self::Number(n) = self {
-                return Value::Byte(*n as u8);
-            }
-        }
-        if tp == "signedword" {
-            if let Self::Number(n) = self {
-                return Value::SignedWord(*n as i64);
-            }
-            else if let Self::StaticAccess(_) = self {
-                return self.clone(); // static accesses are unsigned words - signed works too!
-            }
+    fn cast(&self, tp : &str) -> Result<Value, String> { // suffixed literals (42b, 5l, 1.5f...) only cast to their own type, but plain
+        // numbers cast to anything they fit in
+        match (tp, self) {
+            ("word", Self::Number(n)) => Ok(Value::Word(*n as u64)),
+            ("word", Self::Word(_) | Self::StaticAccess(_) | Self::Label(_)) => Ok(self.clone()), // static accesses are unsigned words
+            ("signedword", Self::Number(n)) => Ok(Value::SignedWord(*n)),
+            ("signedword", Self::Word(w)) => Ok(Value::SignedWord(*w as i64)),
+            ("signedword", Self::StaticAccess(_) | Self::Label(_)) => Ok(self.clone()), // static accesses are unsigned words - signed works too!
+            ("bytes", Self::String(s)) => Ok(Value::Bytes(s.clone())),
+            ("byte", Self::Number(n)) if fits(*n as i128, 8) => Ok(Value::Byte(*n as u8)),
+            ("short", Self::Number(n)) if fits(*n as i128, 16) => Ok(Value::Short(*n as u16)),
+            ("int", Self::Number(n)) if fits(*n as i128, 32) => Ok(Value::Int(*n as u32)),
+            ("byte" | "short" | "int", Self::Number(n)) => Err(format!("{} doesn't fit in a {}", n, tp)),
+            ("byte", Self::Byte(_)) | ("short", Self::Short(_)) | ("int", Self::Int(_)) => Ok(self.clone()),
+            ("float", Self::Number(n)) => Ok(Value::Float(*n as f32)),
+            ("double", Self::Number(n)) => Ok(Value::Double(*n as f64)),
+            ("float", Self::Float(_)) | ("double", Self::Double(_)) => Ok(self.clone()),
+            _ => Err(format!("can't use {:?} as a {}", self, tp))
         }
-        panic!("improper cast {:?} to {}", self, tp);
     }
 
-    fn dump_into(&self, f_tbl : &HashMap<String, i64>, s_tbl : &HashMap<String, i64>, out : &mut Vec<u8>) {
+    fn dump_into(&self, symbols : &Symbols, out : &mut Vec<u8>, relocs : &mut Vec<(usize, RelocTarget)>) -> Result<(), String> {
+        // every address written gets a (position in out, target) in relocs
         match self {
             Value::Bytes(v) => {
-                out.extend_from_slice(&v);
+                out.extend_from_slice(v);
             },
             Value::Word(v) => {
                 out.extend_from_slice(&v.to_be_bytes());
             }
             Value::StaticAccess(s) => {
-                let ptr = if let Some(p) = s_tbl.get(s) { *p } else {
-                    f_tbl[s]
+                let (ptr, target) = if let Some(p) = symbols.statics.get(s) {
+                    (*p, RelocTarget::Section(Section::Static))
+                } else if let Some(p) = symbols.functions.get(s) {
+                    (*p, RelocTarget::Section(Section::Text))
+                } else if symbols.externs.contains(s) {
+                    (0, RelocTarget::Symbol(s.clone()))
+                } else {
+                    return Err(format!("undefined symbol ${}", s));
                 };
+                relocs.push((out.len(), target));
                 out.extend_from_slice(&ptr.to_be_bytes());
             }
             Value::Byte(b) => {
                 out.extend_from_slice(&b.to_be_bytes());
             }
+            Value::Short(s) => {
+                out.extend_from_slice(&s.to_be_bytes());
+            }
+            Value::Int(i) => {
+                out.extend_from_slice(&i.to_be_bytes());
+            }
             Value::SignedWord(w) => {
                 out.extend_from_slice(&w.to_be_bytes());
             }
+            Value::Float(x) => {
+                out.extend_from_slice(&x.to_be_bytes());
+            }
+            Value::Double(x) => {
+                out.extend_from_slice(&x.to_be_bytes());
+            }
             _ => {
-                panic!("cannot dump {:?} into a vec<u8> as it is an unsupported type (did you perform correct casts?)", self);
+                return Err(format!("can't emit {:?} without casting it first", self));
             }
         }
+        Ok(())
     }
 }
 
 
 #[derive(Debug)]
-struct Operation(String, Vec<Value>);
+struct Operation(String, Vec<Value>, std::ops::Range<usize>); // mnemonic, arguments, and where it is in the source
+
+
+#[derive(Debug)]
+enum Line { // a line in a function: either an operation, or a label (`name:`) marking the spot for `:name` arguments to refer to
+    Op(Operation),
+    Label(String, std::ops::Range<usize>)
+}
+
+
+pub(crate) const OPERATIONS : &[(&str, u8, &[&str])] = &[ // mnemonic, opcode, and the type each argument is cast to, in opcode order (see the table
+    // in lib.rs). the disassembler reads this backwards, taking the first mnemonic it finds for an opcode.
+    // memory
+    ("pushl", 0, &["signedword"]),
+    ("pushi", 1, &["signedword"]),
+    ("pushs", 2, &["signedword"]),
+    ("pushb", 3, &["signedword"]),
+    ("pushvl", 4, &["word"]),
+    ("pushvi", 5, &["int"]),
+    ("pushvs", 6, &["short"]),
+    ("pushvb", 7, &["byte"]),
+    ("swapl", 8, &["signedword", "signedword"]),
+    ("swapi", 9, &["signedword", "signedword"]),
+    ("swaps", 10, &["signedword", "signedword"]),
+    ("swapb", 11, &["signedword", "signedword"]),
+    ("cpyl", 12, &["signedword", "signedword"]),
+    ("cpyi", 13, &["signedword", "signedword"]),
+    ("cpys", 14, &["signedword", "signedword"]),
+    ("cpyb", 15, &["signedword", "signedword"]),
+    ("cpyvl", 16, &["signedword", "word"]),
+    ("cpyvi", 17, &["signedword", "int"]),
+    ("cpyvs", 18, &["signedword", "short"]),
+    ("cpyvb", 19, &["signedword", "byte"]),
+    ("popl", 20, &[]),
+    ("popi", 21, &[]),
+    ("pops", 22, &[]),
+    ("popb", 23, &[]),
+    ("popml", 24, &["signedword"]),
+    ("popmi", 25, &["signedword"]),
+    ("popms", 26, &["signedword"]),
+    ("popmb", 27, &["signedword"]),
+    // int arithmetic
+    ("addl", 28, &["signedword", "signedword"]),
+    ("addi", 29, &["signedword", "signedword"]),
+    ("adds", 30, &["signedword", "signedword"]),
+    ("addb", 31, &["signedword", "signedword"]),
+    ("subl", 32, &["signedword", "signedword"]),
+    ("subi", 33, &["signedword", "signedword"]),
+    ("subs", 34, &["signedword", "signedword"]),
+    ("subb", 35, &["signedword", "signedword"]),
+    ("mull", 36, &["signedword", "signedword"]),
+    ("muli", 37, &["signedword", "signedword"]),
+    ("muls", 38, &["signedword", "signedword"]),
+    ("mulb", 39, &["signedword", "signedword"]),
+    ("divl", 40, &["signedword", "signedword"]),
+    ("divi", 41, &["signedword", "signedword"]),
+    ("divs", 42, &["signedword", "signedword"]),
+    ("divb", 43, &["signedword", "signedword"]),
+    // logic
+    ("cmpl", 44, &["signedword", "signedword"]),
+    ("cmpi", 45, &["signedword", "signedword"]),
+    ("cmps", 46, &["signedword", "signedword"]),
+    ("cmpb", 47, &["signedword", "signedword"]),
+    ("cmpvl", 48, &["signedword", "word"]),
+    ("cmpvi", 49, &["signedword", "int"]),
+    ("cmpvs", 50, &["signedword", "short"]),
+    ("cmpvb", 51, &["signedword", "byte"]),
+    ("bnot", 52, &["signedword"]),
+    ("not", 53, &["signedword"]),
+    ("bor", 54, &["signedword", "signedword"]),
+    ("vor", 55, &["signedword", "byte"]),
+    ("band", 56, &["signedword", "signedword"]),
+    ("vand", 57, &["signedword", "byte"]),
+    ("shiftl", 58, &["signedword", "byte"]),
+    ("shifti", 59, &["signedword", "byte"]),
+    ("shifts", 60, &["signedword", "byte"]),
+    ("shiftb", 61, &["signedword", "byte"]),
+    ("bnorm", 62, &["signedword"]),
+    // flow control
+    ("jmp", 63, &["signedword"]),
+    ("branch", 64, &["signedword", "signedword"]),
+    ("call", 65, &["signedword"]),
+    ("ret", 66, &[]),
+    ("invokevirtual", 67, &["signedword"]),
+    ("invokeext", 68, &[]),
+    ("setsbm", 69, &[]),
+    ("throw", 70, &["byte"]),
+    ("checkerr", 71, &["signedword"]),
+    ("geterr", 72, &[]),
+    // vm commands
+    ("exit", 73, &["signedword"]),
+    ("startmmu", 74, &["int"]),
+    ("alloc", 75, &[]),
+    ("dealloc", 76, &[]),
+    ("realloc", 77, &[]),
+    ("maketbl", 78, &[]),
+    ("pushtbl", 79, &[]),
+    ("gettbl", 80, &[]),
+    ("deltbl", 81, &[]),
+    ("freetbl", 82, &[]),
+    ("updstck", 83, &["signedword"]),
+    // extensions
+    ("scrub", 84, &["signedword", "word"]),
+    ("ashiftl", 85, &["signedword", "byte"]),
+    ("ashifti", 86, &["signedword", "byte"]),
+    ("ashifts", 87, &["signedword", "byte"]),
+    ("ashiftb", 88, &["signedword", "byte"]),
+    ("bxor", 89, &["signedword", "signedword"]),
+    ("vxor", 90, &["signedword", "byte"]),
+    ("ureml", 91, &["signedword", "signedword"]),
+    ("uremi", 92, &["signedword", "signedword"]),
+    ("urems", 93, &["signedword", "signedword"]),
+    ("uremb", 94, &["signedword", "signedword"]),
+    ("sreml", 95, &["signedword", "signedword"]),
+    ("sremi", 96, &["signedword", "signedword"]),
+    ("srems", 97, &["signedword", "signedword"]),
+    ("sremb", 98, &["signedword", "signedword"]),
+    ("orl", 99, &["signedword", "signedword"]),
+    ("ori", 100, &["signedword", "signedword"]),
+    ("ors", 101, &["signedword", "signedword"]),
+    ("orb", 102, &["signedword", "signedword"]),
+    ("andl", 103, &["signedword", "signedword"]),
+    ("andi", 104, &["signedword", "signedword"]),
+    ("ands", 105, &["signedword", "signedword"]),
+    ("andb", 106, &["signedword", "signedword"]),
+    ("xorl", 107, &["signedword", "signedword"]),
+    ("xori", 108, &["signedword", "signedword"]),
+    ("xors", 109, &["signedword", "signedword"]),
+    ("xorb", 110, &["signedword", "signedword"]),
+    ("mmucheck", 111, &[]),
+    ("faddl", 112, &["signedword", "signedword"]),
+    ("faddi", 113, &["signedword", "signedword"]),
+    ("fsubl", 114, &["signedword", "signedword"]),
+    ("fsubi", 115, &["signedword", "signedword"]),
+    ("fmull", 116, &["signedword", "signedword"]),
+    ("fmuli", 117, &["signedword", "signedword"]),
+    ("fdivl", 118, &["signedword", "signedword"]),
+    ("fdivi", 119, &["signedword", "signedword"]),
+    ("fcmpl", 120, &["signedword", "signedword"]),
+    ("fcmpi", 121, &["signedword", "signedword"]),
+    ("casl", 122, &[]),
+    ("casi", 123, &[]),
+    ("cass", 124, &[]),
+    ("casb", 125, &[]),
+    ("dupblock", 126, &["word"]),
+    ("uniqueid", 127, &[]),
+    ("curfn", 128, &[]),
+    ("checkerrcode", 129, &["signedword", "byte"]),
+    ("dock", 130, &["signedword"]),
+    ("loadfun", 131, &["signedword"]),
+    ("scmpl", 132, &["signedword", "signedword"]),
+    ("scmpi", 133, &["signedword", "signedword"]),
+    ("scmps", 134, &["signedword", "signedword"]),
+    ("scmpb", 135, &["signedword", "signedword"]),
+    ("sataddl", 136, &["signedword", "signedword"]),
+    ("sataddi", 137, &["signedword", "signedword"]),
+    ("satadds", 138, &["signedword", "signedword"]),
+    ("sataddb", 139, &["signedword", "signedword"]),
+    ("satsubl", 140, &["signedword", "signedword"]),
+    ("satsubi", 141, &["signedword", "signedword"]),
+    ("satsubs", 142, &["signedword", "signedword"]),
+    ("satsubb", 143, &["signedword", "signedword"]),
+    ("satmull", 144, &["signedword", "signedword"]),
+    ("satmuli", 145, &["signedword", "signedword"]),
+    ("satmuls", 146, &["signedword", "signedword"]),
+    ("satmulb", 147, &["signedword", "signedword"]),
+    ("ssataddl", 148, &["signedword", "signedword"]),
+    ("ssataddi", 149, &["signedword", "signedword"]),
+    ("ssatadds", 150, &["signedword", "signedword"]),
+    ("ssataddb", 151, &["signedword", "signedword"]),
+    ("ssatsubl", 152, &["signedword", "signedword"]),
+    ("ssatsubi", 153, &["signedword", "signedword"]),
+    ("ssatsubs", 154, &["signedword", "signedword"]),
+    ("ssatsubb", 155, &["signedword", "signedword"]),
+    ("ssatmull", 156, &["signedword", "signedword"]),
+    ("ssatmuli", 157, &["signedword", "signedword"]),
+    ("ssatmuls", 158, &["signedword", "signedword"]),
+    ("ssatmulb", 159, &["signedword", "signedword"]),
+    ("popcntl", 160, &["signedword"]),
+    ("popcnti", 161, &["signedword"]),
+    ("popcnts", 162, &["signedword"]),
+    ("popcntb", 163, &["signedword"]),
+    ("clzl", 164, &["signedword"]),
+    ("clzi", 165, &["signedword"]),
+    ("clzs", 166, &["signedword"]),
+    ("clzb", 167, &["signedword"]),
+    ("ctzl", 168, &["signedword"]),
+    ("ctzi", 169, &["signedword"]),
+    ("ctzs", 170, &["signedword"]),
+    ("ctzb", 171, &["signedword"]),
+    ("rotll", 172, &["signedword", "byte"]),
+    ("rotli", 173, &["signedword", "byte"]),
+    ("rotls", 174, &["signedword", "byte"]),
+    ("rotlb", 175, &["signedword", "byte"]),
+    ("rotrl", 176, &["signedword", "byte"]),
+    ("rotri", 177, &["signedword", "byte"]),
+    ("rotrs", 178, &["signedword", "byte"]),
+    ("rotrb", 179, &["signedword", "byte"]),
+    ("memcpy", 180, &[]),
+    ("memmove", 181, &[]),
+    ("memset", 182, &[]),
+    ("memcmp", 183, &[]),
+    ("strlen", 184, &[]),
+    ("memstat", 185, &[]),
+    ("yield", 186, &[]),
+    ("f2il", 187, &["signedword", "signedword"]),
+    ("f2ii", 188, &["signedword", "signedword"]),
+    ("f2ul", 189, &["signedword", "signedword"]),
+    ("f2ui", 190, &["signedword", "signedword"]),
+    ("i2fl", 191, &["signedword", "signedword"]),
+    ("i2fi", 192, &["signedword", "signedword"]),
+    ("u2fl", 193, &["signedword", "signedword"]),
+    ("u2fi", 194, &["signedword", "signedword"]),
+    ("assertl", 195, &["signedword", "word"]),
+    ("asserti", 196, &["signedword", "word"]),
+    ("asserts", 197, &["signedword", "word"]),
+    ("assertb", 198, &["signedword", "word"]),
+    ("getsbm", 199, &[]),
+    ("clearsbm", 200, &[]),
+    ("sdivl", 201, &["signedword", "signedword"]),
+    ("sdivi", 202, &["signedword", "signedword"]),
+    ("sdivs", 203, &["signedword", "signedword"]),
+    ("sdivb", 204, &["signedword", "signedword"])
+];
 
 
 impl Operation {
-    fn dump_into(&self, f_tbl : &HashMap<String, i64>, s_tbl : &HashMap<String, i64>, out : &mut Vec<u8>) {
-        let Operation(name, operations) = self;
-        match name.as_str() {
-            "pushvl" => {
-                out.push(0);
-                operations[0].cast("word").dump_into(f_tbl, s_tbl, out);
-            },
-            "movml" => {
-                out.push(16);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("byte").dump_into(f_tbl, s_tbl, out);
-            },
-            "movrl" => {
-                out.push(20);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("byte").dump_into(f_tbl, s_tbl, out);
-            },
-            "invokevirtual" => {
-                out.push(67);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "popl" => {
-                out.push(8);
-                operations[0].cast("byte").dump_into(f_tbl, s_tbl, out);
-            },
-            "ret" => {
-                out.push(66);
-            },
-            "dock" => {
-                out.push(68);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "loadfun" => {
-                out.push(69);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "swapl" => {
-                out.push(4);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "call" => {
-                out.push(65);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "exit" => {
-                out.push(70);
-                operations[0].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            },
-            "movvl" => {
-                out.push(12);
-                operations[0].cast("word").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("byte").dump_into(f_tbl, s_tbl, out);
-            },
-            "subv" => {
-                out.push(27);
-                operations[0].cast("byte").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("word").dump_into(f_tbl, s_tbl, out);
-            },
-            "branch" => {
-                out.push(64);
-                operations[0].cast("byte").dump_into(f_tbl, s_tbl, out);
-                operations[1].cast("signedword").dump_into(f_tbl, s_tbl, out);
-            }
-            _ => {
-                panic!("invalid instruction {}", name);
-            }
+    fn size(&self) -> usize { // how many bytes this will take up in the text section. unknown instructions take up nothing (they're an error anyway)
+        match OPERATIONS.iter().find(|(mnemonic, _, _)| *mnemonic == self.0) {
+            Some((_, _, args)) => 1 + args.iter().map(|tp| arg_width(tp)).sum::<usize>(),
+            None => 0
         }
     }
+
+    fn dump_into(&self, symbols : &Symbols, labels : &HashMap<String, i64>, base : i64, out : &mut Vec<u8>, relocs : &mut Vec<(usize, RelocTarget)>) -> Result<(), IrError> {
+        // base is the absolute address of out[0], and labels hold absolute addresses.
+        let Operation(name, operations, span) = self;
+        let error = |message : String| IrError { span : span.clone(), message };
+        let (_, opcode, args) = match OPERATIONS.iter().find(|(mnemonic, _, _)| mnemonic == name) {
+            Some(op) => op,
+            None => return Err(error(format!("unknown instruction {}", name)))
+        };
+        if operations.len() != args.len() {
+            return Err(error(format!("{} takes {} arguments, but got {}", name, args.len(), operations.len())));
+        }
+        let next = base + (out.len() + self.size()) as i64; // the address of the instruction after this one
+        out.push(*opcode);
+        for (operation, tp) in operations.iter().zip(args.iter()) {
+            let operation = match operation {
+                Value::Label(label) => match labels.get(label) {
+                    Some(addr) if name == "jmp" => Value::Number(addr - next), // jmp is relative to the next instruction
+                    Some(addr) => { // everything else is absolute
+                        relocs.push((out.len(), RelocTarget::Section(Section::Text)));
+                        Value::Number(*addr)
+                    },
+                    None => return Err(error(format!("undefined label :{}", label)))
+                },
+                _ => operation.clone()
+            };
+            operation.cast(tp).and_then(|value| value.dump_into(symbols, out, relocs)).map_err(error)?;
+        }
+        Ok(())
+    }
 }
 
 
 #[derive(Debug)]
 enum AstNode {
-    StaticDefinition(String, Value, bool), // the last bool is whether or not this should be made public or not (listed in the table at the start of the file)
-    FunctionDefinition(String, Vec<Operation>, bool) // ditto
+    StaticDefinition(String, Value, bool, std::ops::Range<usize>), // the bool is whether or not this should be made public or not (listed in the table at the start of the file)
+    FunctionDefinition(String, Vec<Line>, bool), // ditto
+    Extern(String) // `.name extern`: a function or static that another image exports. $name is left as 0 for Image::link to fill in
+}
+
+
+pub(crate) fn string_char() -> impl Parser<char, Vec<u8>, Error=Simple<char>> + Clone { // one character of a string literal (IR or AVC),
+    // as the bytes it stands for. \xNN is a single raw byte, so strings can hold binary data; everything else (including \u{...}) is UTF-8.
+    // unknown escapes are errors.
+    let hex = filter(|c : &char| c.is_ascii_hexdigit());
+    let byte = just('x').ignore_then(hex.repeated().exactly(2)).collect::<String>().map(|h| vec![u8::from_str_radix(&h, 16).unwrap()]);
+    let unicode = just("u{").ignore_then(hex.repeated().at_least(1).at_most(6)).then_ignore(just('}')).collect::<String>().try_map(|h, span| {
+        match char::from_u32(u32::from_str_radix(&h, 16).unwrap()) {
+            Some(c) => Ok(c.to_string().into_bytes()),
+            None => Err(Simple::custom(span, format!("\\u{{{}}} isn't a unicode character", h)))
+        }
+    });
+    let simple = choice((just('\\'), just('"'), just('n').to('\n'), just('t').to('\t'), just('r').to('\r'), just('0').to('\0'))).map(|c| vec![c as u8]);
+    just('\\').ignore_then(choice((byte, unicode, simple))).or(none_of("\\\"").map(|c : char| c.to_string().into_bytes()))
+}
+
+
+fn fits(n : i128, bits : u32) -> bool { // whether n fits in an int of this many bits, either signed or unsigned
+    n >= -(1 << (bits - 1)) && n < (1 << bits)
+}
+
+
+fn number_literal(negative : bool, int : &str, fraction : Option<String>, suffix : Option<char>) -> Result<Value, String> {
+    // plain numbers are 64-bit and cast to whatever the instruction wants. b, s, i and l suffixes pin the width, and floats need an f (32-bit)
+    // or d (64-bit) suffix.
+    let sign = if negative { "-" } else { "" };
+    if let Some(fraction) = &fraction {
+        let x : f64 = format!("{}{}.{}", sign, int, fraction).parse().map_err(|_| format!("bad float literal {}{}.{}", sign, int, fraction))?;
+        return match suffix {
+            Some('f') => Ok(Value::Float(x as f32)),
+            Some('d') => Ok(Value::Double(x)),
+            _ => Err(format!("float literal {}{}.{} needs an f or d suffix", sign, int, fraction))
+        };
+    }
+    let literal = format!("{}{}{}", sign, int, suffix.map(String::from).unwrap_or_default());
+    let n : i128 = format!("{}{}", sign, int).parse().map_err(|_| format!("{} is too big", literal))?;
+    let (tp, bits) = match suffix {
+        None => return i64::try_from(n).map(Value::Number).map_err(|_| format!("{} doesn't fit in a word", literal)),
+        Some('f') => return Ok(Value::Float(n as f32)),
+        Some('d') => return Ok(Value::Double(n as f64)),
+        Some('b') => ("byte", 8),
+        Some('s') => ("short", 16),
+        Some('i') => ("int", 32),
+        _ => ("word", 64)
+    };
+    if !fits(n, bits) {
+        return Err(format!("{} doesn't fit in a {}", literal, tp));
+    }
+    Ok(match bits {
+        8 => Value::Byte(n as u8),
+        16 => Value::Short(n as u16),
+        32 => Value::Int(n as u32),
+        _ => Value::Word(n as u64)
+    })
 }
 
 
 fn parser() -> impl Parser<char, Vec<AstNode>, Error=Simple<char>> {
-    let esc = just('\\').ignored().then(choice((just('\\'), just('n'), just('0')))).map(|(_, c)| match c {
-        'n' => '\n',
-        '0' => '\0',
-        _ => c
-    }).or(none_of('"'));
-    let string = just('"').ignore_then(esc.repeated()).then_ignore(just('"')).padded().collect::<String>().map(Value::String);
-    let number = just('-').ignored().then(text::int(10)).padded().map(|(_, i)| Value::Number(i.parse::<i64>().unwrap() * -1)).or(text::int(10).padded().map(|n : String| Value::Number(n.parse::<i64>().unwrap())));
+    let string = just('"').ignore_then(string_char().repeated()).then_ignore(just('"')).padded().map(|parts| Value::String(parts.concat()));
+    let number = just('-').or_not().then(text::int(10)).then(just('.').ignore_then(text::digits(10)).or_not()).then(one_of("bsilfd").or_not()).padded()
+        .try_map(|(((minus, int), fraction), suffix), span| number_literal(minus.is_some(), &int, fraction, suffix).map_err(|message| Simple::custom(span, message)));
     let var_access = just('$').then(text::ident()).padded().map(|(_, var)| { Value::StaticAccess(var) });
-    let value = choice((string, number, var_access));
+    let label_access = just(':').ignore_then(text::ident()).padded().map(Value::Label);
+    let value = choice((string, number, var_access, label_access));
     let comment = just(';').padded().then(none_of("\n").repeated());
-    let operation = text::ident().padded().then(value.clone().repeated()).then_ignore(comment.clone().repeated()).map(|(op, values)| {
-        Operation(op, values)
+    let operation = text::ident().padded().then(value.clone().repeated()).then_ignore(comment.clone().repeated()).map_with_span(|(op, values), span| {
+        Operation(op, values, span)
+    });
+    // labels are defined with `name:` and used with `:name`. they have to be tried before operations, or the name would be taken for a mnemonic.
+    let label = text::ident().then_ignore(just(':')).padded().then_ignore(comment.clone().repeated()).map_with_span(Line::Label);
+    let line = label.or(operation.map(Line::Op));
+    // `=name type value`, with an optional `export` at the end to put it in the image's static table
+    let static_assign = just('=').ignored().then(text::ident()).padded().then(text::ident()).padded().then(value.clone()).padded()
+        .then(text::keyword("export").padded().or_not()).try_map(|((((_, name), tp), value), export), span : std::ops::Range<usize>| {
+        value.cast(&tp).map(|value| AstNode::StaticDefinition(name, value, export.is_some(), span.clone())).map_err(|message| Simple::custom(span, message))
     });
-    let static_assign = just('=').ignored().then(text::ident()).padded().then(text::ident()).padded().then(value.clone()).padded().map(|(((_, name), tp), value)| { AstNode::StaticDefinition(name, value.cast(&tp), false) });
-    let fndef = just('.').ignored().then(text::ident()).then_ignore(just(' ').repeated()).then(text::ident().repeated().at_most(1)).padded().then(operation.repeated()).map(|(((_, name), modifier), program)| {
-        AstNode::FunctionDefinition(name, program, if modifier.len() > 0 { modifier[0] == "export" } else { false })
+    let fndef = just('.').ignored().then(text::ident()).then_ignore(just(' ').repeated()).then(text::ident().or_not()).padded().then(line.repeated())
+        .try_map(|(((_, name), modifier), program), span| match modifier.as_deref() {
+        Some("extern") if program.is_empty() => Ok(AstNode::Extern(name)),
+        Some("extern") => Err(Simple::custom(span, format!("extern {} can't have a body", name))),
+        modifier => Ok(AstNode::FunctionDefinition(name, program, modifier == Some("export")))
     });
     choice((static_assign, fndef)).padded().then_ignore(comment.repeated()).padded().repeated().then_ignore(end())
 }
 
 
-pub fn build(program : &str) -> Image {
-    let irast = parser().parse(program).unwrap();
+pub(crate) struct Lines { // turns the character offsets chumsky gives spans in into lines and columns
+    source : Vec<char>,
+    starts : Vec<usize> // the offset every line starts at
+}
+
+
+impl Lines {
+    pub(crate) fn new(source : &str) -> Self {
+        let source : Vec<char> = source.chars().collect();
+        let starts = core::iter::once(0).chain(source.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i + 1)).collect();
+        Self { source, starts }
+    }
+
+    pub(crate) fn span(&self, start : i64, end : i64, offset : usize) -> SourceSpan { // a span for the text bytes start..end, which came
+        // from the source at offset. spans can start with the whitespace a parser padded over, so that gets skipped
+        let offset = (offset..self.source.len()).find(|&i| !self.source[i].is_whitespace()).unwrap_or(offset);
+        let line = self.starts.partition_point(|&start| start <= offset);
+        SourceSpan { start, end, line, column : offset - self.starts[line - 1] + 1 }
+    }
+}
+
+
+pub fn build(program : &str) -> Result<Image, Vec<IrError>> {
+    let irast = parser().parse(program).map_err(|errors| errors.into_iter().map(IrError::from).collect::<Vec<_>>())?;
     let mut public_fn_table = HashMap::new();
-    let public_static_table = HashMap::new();
-    let mut fn_table : HashMap<String, i64> = HashMap::new();
+    let mut public_static_table = HashMap::new();
     let mut text_section = Vec::new();
-    let mut static_table : HashMap<String, i64> = HashMap::new();
+    let mut function_extents = Vec::new();
     let mut static_section = Vec::new();
+    let mut relocations = Vec::new();
+    let externs = irast.iter().filter_map(|statement| if let AstNode::Extern(name) = statement { Some(name.clone()) } else { None }).collect();
+    let mut symbols = Symbols { functions : HashMap::new(), statics : HashMap::new(), externs };
+    let mut relocs = Vec::new();
+    let lines = Lines::new(program);
+    let mut spans = Vec::new();
+    let mut frame_sizes = HashMap::new();
     for statement in &irast { // build a static table and static section
-        if let AstNode::StaticDefinition(name, value, _) = statement {
-            static_table.insert(name.clone(), static_section.len() as i64);
-            value.dump_into(&fn_table, &static_table, &mut static_section);
+        if let AstNode::StaticDefinition(name, value, public, span) = statement {
+            symbols.statics.insert(name.clone(), static_section.len() as i64);
+            if *public {
+                public_static_table.insert(name.clone(), static_section.len() as i64);
+            }
+            value.dump_into(&symbols, &mut static_section, &mut relocs).map_err(|message| vec![IrError { span : span.clone(), message }])?;
         }
     }
+    relocations.extend(relocs.drain(..).map(|(offset, target)| Relocation { section : Section::Static, offset, target }));
+    let mut errors = vec![];
     for statement in &irast {
         if let AstNode::FunctionDefinition(name, program, exposed) = statement {
             if *exposed {
                 public_fn_table.insert(name.clone(), text_section.len() as i64);
             }
-            fn_table.insert(name.clone(), (static_section.len() + text_section.len()) as i64);
-            for op in program {
-                op.dump_into(&fn_table, &static_table, &mut text_section);
+            symbols.functions.insert(name.clone(), (static_section.len() + text_section.len()) as i64);
+            let start = text_section.len() as i64;
+            let mut labels = HashMap::new(); // first pass over the function: find out where its labels are
+            let mut addr = static_section.len() as i64 + start;
+            for line in program {
+                match line {
+                    Line::Op(op) => addr += op.size() as i64,
+                    Line::Label(label, span) => {
+                        if labels.insert(label.clone(), addr).is_some() {
+                            errors.push(IrError { span : span.clone(), message : format!("label {} is defined twice", label) });
+                        }
+                    }
+                }
+            }
+            let (mut reserved, mut frame_size) = (0i64, 0i64); // a simple pass: updstcks in the order they're written, ignoring jumps
+            for line in program {
+                if let Line::Op(op) = line {
+                    if let ("updstck", [Value::Number(amount)]) = (op.0.as_str(), op.1.as_slice()) {
+                        reserved = reserved.saturating_add(*amount);
+                        frame_size = frame_size.max(reserved);
+                    }
+                    let at = text_section.len() as i64;
+                    if let Err(e) = op.dump_into(&symbols, &labels, static_section.len() as i64, &mut text_section, &mut relocs) {
+                        errors.push(e);
+                    }
+                    spans.push(lines.span(at, text_section.len() as i64, op.2.start));
+                }
             }
+            function_extents.push((start, text_section.len() as i64));
+            frame_sizes.insert(name.clone(), frame_size as u64);
         }
     }
-    Image {
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    relocations.extend(relocs.into_iter().map(|(offset, target)| Relocation { section : Section::Text, offset, target }));
+    Ok(Image {
         function_table : public_fn_table,
         static_table : public_static_table,
         static_section,
-        text_section
+        text_section,
+        function_extents,
+        relocations : Some(relocations),
+        debug_info : Some(DebugInfo { spans }),
+        frame_sizes : Some(frame_sizes)
+    })
+}
+
+
+fn arg_width(tp : &str) -> usize {
+    match tp {
+        "byte" => 1,
+        "short" => 2,
+        "int" | "float" => 4,
+        _ => 8
+    }
+}
+
+
+pub(crate) fn encode(mnemonic : &str, args : &[i64], out : &mut Vec<u8>) { // assemble a single instruction from plain numbers, for compilers
+    // (like avc) that have already worked out their addresses. an unknown mnemonic or the wrong number of arguments is a bug in the
+    // compiler, not the program, so those panic.
+    let (_, opcode, types) = OPERATIONS.iter().find(|(m, _, _)| *m == mnemonic).unwrap_or_else(|| panic!("no such instruction {}", mnemonic));
+    assert_eq!(types.len(), args.len(), "wrong number of arguments to {}", mnemonic);
+    out.push(*opcode);
+    for (tp, arg) in types.iter().zip(args) {
+        out.extend_from_slice(&arg.to_be_bytes()[8 - arg_width(tp)..]);
+    }
+}
+
+
+impl Image { // the disassembler: the inverse of build
+    pub fn disassemble(&self) -> String {
+        self.disassemble_range(0, self.text_section.len())
+    }
+
+    pub fn disassemble_function(&self, name : &str) -> Option<String> { // disassemble from the start of a public function to the start of the
+        // next one. private functions don't have table entries, so any that follow this one get included too.
+        let start = *self.function_table.get(name)? as usize;
+        let end = self.function_table.values().map(|offset| *offset as usize).filter(|offset| *offset > start).min().unwrap_or(self.text_section.len());
+        Some(self.disassemble_range(start, end.min(self.text_section.len())))
+    }
+
+    fn disassemble_range(&self, start : usize, end : usize) -> String {
+        let mut symbols = HashMap::new(); // absolute address -> public name
+        for (name, offset) in &self.static_table {
+            symbols.insert(*offset, name);
+        }
+        for (name, offset) in &self.function_table {
+            symbols.insert(self.static_section.len() as i64 + offset, name);
+        }
+        let text = &self.text_section;
+        let mut out = String::new();
+        let mut pos = start;
+        while pos < end {
+            let mut labels : Vec<&String> = self.function_table.iter().filter(|(_, offset)| **offset == pos as i64).map(|(name, _)| name).collect();
+            labels.sort();
+            for label in labels {
+                writeln!(out, ".{} export", label).unwrap();
+            }
+            let op = text[pos];
+            let (mnemonic, _, args) = match OPERATIONS.iter().find(|(_, opcode, _)| *opcode == op) {
+                Some(operation) => operation,
+                None => {
+                    writeln!(out, "    ; unknown opcode {}", op).unwrap();
+                    pos += 1;
+                    continue;
+                }
+            };
+            if pos + 1 + args.iter().map(|tp| arg_width(tp)).sum::<usize>() > text.len() {
+                writeln!(out, "    ; truncated {}", mnemonic).unwrap();
+                break;
+            }
+            write!(out, "    {}", mnemonic).unwrap();
+            pos += 1;
+            for tp in args.iter() {
+                if arg_width(tp) < 8 { // bytes, shorts, and ints are always plain unsigned numbers
+                    let val = text[pos..pos + arg_width(tp)].iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+                    write!(out, " {}", val).unwrap();
+                }
+                else {
+                    let val = i64::from_be_bytes(text[pos..pos + 8].try_into().unwrap());
+                    match symbols.get(&val) {
+                        Some(name) => write!(out, " ${}", name).unwrap(),
+                        None if *tp == "word" => write!(out, " {}", val as u64).unwrap(),
+                        None => write!(out, " {}", val).unwrap()
+                    }
+                }
+                pos += arg_width(tp);
+            }
+            out.push('\n');
+        }
+        out
     }
 }
@@ -38,39 +38,47 @@
     24 -> 27. popm[l, i, s, b]: pop a value from stack to a point in memory
 
     // int arithmetic
-    28 -> 31. add[l, i, s b]: add two points in memory (the result will overwrite the first point)
+    28 -> 31. add[l, i, s, b]: add two points in memory (the result will overwrite the first point)
+        add, sub, and mul wrap around, unless the host turned on overflow checking (Machine::set_overflow_checked); then a result that
+        doesn't fit (as an unsigned value) throws 6 instead, and the first point is left alone.
     32 -> 35. sub[l, i, s, b]: subtract the second point from the first point (same overwrite semantics as add)
     36 -> 39. mul[l, i, s, b]: multiply. same semantics as add,sub
-    40 -> 43. div[l, i, s, b]: divide. same semantics as above
-
-    // note: I haven't yet checked if bitwise division actually does work the same for signed and unsigned values. oops.
-    // may need to expand this to (i)div(v)[l, i, s, b]
+    40 -> 43. div[l, i, s, b]: divide. same semantics as above. dividing by zero throws error code 8.
+        the values are unsigned (-7 div 2 is a huge number, not -3); see sdiv for signed division.
 
     // logical bitwise operations
     44 -> 51. cmp(v)[l, i, s, b]: compare values in memory. Get the second value either (v) from the instruction or from elsewhere in memory.
+        the values are unsigned; see scmp for signed comparisons. cmpv takes the location and then the value, at the instruction's width.
+        there's no signed cmpv, so a negative value compares as a big unsigned one.
         Push the 1-byte result to the stack:
         if they're equal, 0
         if one is greater than two, 1
         if two is greater than one, 2
-    52: bnot: flip all the bits of a byte in memory.
-    53: not: replace a byte in memory with 1 if it is 0, or 0 otherwise
-    54: bor: take a bitwise OR of two bytes in memory and replace the first byte with the result.
-    55: vor: compare a byte in memory with a specified value. same semantics as bor.
-    56: band: take a bitwise AND of two byte in memory and replace the first byte with the result
-    57: vand: compare a byte in memory with a specified value. same semantics as band.
-    58 -> 61: shift[l, i, s, b]: bitshift a value in memory by some specified amount (the amount should be a signed 8-bit int)
-    62: bnorm: if a byte in memory is not 0, set it to 1, otherwise set it to 0.
+    52. bnot: flip all the bits of a byte in memory.
+    53. not: replace a byte in memory with 1 if it is 0, or 0 otherwise
+    54. bor: take a bitwise OR of two bytes in memory and replace the first byte with the result.
+    55. vor: compare a byte in memory with a specified value. same semantics as bor.
+    56. band: take a bitwise AND of two byte in memory and replace the first byte with the result
+    57. vand: compare a byte in memory with a specified value. same semantics as band.
+    58 -> 61. shift[l, i, s, b]: bitshift a value in memory by some specified amount (the amount should be a signed 8-bit int)
+        negative amounts shift left, positive amounts shift right. right shifts are logical (they fill with 0s); see ashift for sign extension.
+        shifting by the width of the value or more produces 0.
+    62. bnorm: if a byte in memory is not 0, set it to 1, otherwise set it to 0.
 
     // flow control
-    63. jmp: Increment or decrement the execution pointer by the signed 64-bit int argument.
-    64. branch: if the value in a specified register is 0, branch to a specified location. else, continue with the next operation.
-        location is an absolute op location
+    63. jmp: Increment or decrement the execution pointer by the signed 64-bit int argument. the offset is measured from the next instruction
+        (the end of the argument), so jmp 0 does nothing and jmp -9 jumps back to itself.
+    64. branch: if the byte at a specified location is 0, branch to a specified location. else, continue with the next operation.
+        accepts a 64-bit address of the byte (resolved like any other operand, so negative addresses are on the stack) and a 64-bit target.
+        the target is an absolute op location
     65. call: call a function: absolute version of jmp, but it pushes a return address to stack first.
         you have to push the arguments to stack *before* `call`ing, and the function must still handle stack allocating its own local variables.
         the first stack push a caller makes should be reserving space for the function's return value, if any.
     66. ret: return from a function. expects the top value on the stack to be the return address - that is, the callee function has to unwind the stack down to the return address
-        before calling ret.
+        before calling ret. returning from a function the host called with Machine::invoke_with_args ends the run, and hands the return value to the host.
     67. invokevirtual: `call`, except it dereferences the argument to a 64-bit value somewhere in memory.
+        if the target of call or invokevirtual is the rabbit address of an external function, the external runs right away instead. it pops
+        its own arguments and pushes its own results, and no return address is pushed.
     68. invokeext: invoke an external function (loaded by way of a table)
         To avoid bad recursions, invokeext ALWAYS sets sbm to 0. Attempting to use invokeext
         without checkerr will lead to undefined behavior.
@@ -84,15 +92,24 @@
          * checkerr <handler_location>
         the default SBM is all 0s.
     70. throw: throw an error. accepts an 8-bit error reason. throw is mostly used by the ABI in situations where a proper error handler would not work.
-        when an error is thrown, the stack and execution pointer are rewound to SBM, and the SBM is reset to the SBM pushed on the top of the stack.
-        The sbm is not popped off the stack; it should be popped off with checkerr.
-        If the SBM is all 0, this will fully abort the vm.
-        error codes:
+        when an error is thrown, the stack and execution pointer are rewound to SBM, which leaves the SBM pushed by setsbm on the top of the stack.
+        The sbm is not popped off the stack; it should be popped off with checkerr (or checkerrcode).
+        If the SBM is all 0, this will fully abort the vm (invoke returns InvokeResult::Aborted with the code).
+        either way, the host can find out which calls were in progress with Machine::last_backtrace.
+        error codes (named as constants on ErrorCode, like ErrorCode::DIVIDE_BY_ZERO):
          0: nerr; no error occurred, why are you geterr'ing?
          1: out-of-bounds memory access.
          2: out-of-bounds function call.
          3: table lookup failure.
          4: table allocation failure.
+         5: stack overflow: a push (including the ones call and setsbm do) would have taken the stack past its ceiling. the ceiling is
+            the end of memory, or the bottom of the heap once the MMU is running, unless the host set a smaller stack size.
+         6: arithmetic overflow. only thrown when overflow checking is on (see add).
+         7: assertion failed (see assert).
+         8: division by zero.
+         9: the MMU page table is corrupted.
+        memory errors from any instruction are thrown too, if there's an SBM to catch them: out-of-bounds accesses as 1, running out of
+        memory as 4, and stack overflows as 5. with no SBM they abort the VM with a memory error instead.
         == Please for the love of all that is holy do not use throw in normal situations. It should only ever be used in cases where proper enumerated
         == error handling is utterly impossible, like if the user attempts to execute an invalid external function pointer.
         == Why does it even exist?
@@ -105,10 +122,11 @@
         == This pattern also allows bytecode operations to fail gracefully; rather than aborting the VM upon an out-of-bounds memory access, for instance,
         == it can be properly handled by some user-defined routine, which (critically!) avoids stack corruption.
 
-        The thrown error code will be saved until the next instruction. The only instruction that will not overwrite the error code is checkerr.
+        The thrown error code will be saved until the next instruction. The only instructions that will not overwrite the error code are checkerr
+        (and checkerrcode) when they jump to a handler, and geterr, so a handler can start with any number of geterrs. anything else clears it.
     71. checkerr: if an error was thrown (error code is nonzero), jump to the specified location. Otherwise, continue to the next instruction.
         checkerr pops the SBM off the stack.
-    72. geterr: push the last thrown error code to stack.
+    72. geterr: push the last thrown error code to stack (1 byte). it doesn't clear the code, so the next instruction can still see it.
 
     // vm commands
     73. exit: exit the VM
@@ -116,12 +134,17 @@
         just by directly editing memory, but using the mmu is better.
         startmmu requires a page size in bytes. larger page sizes means more wasted memory from each alloc
         call, but also means a smaller page table and less likelihood of having to move memory on realloc; choose wisely.
+        the heap gets the top half of the memory above the start of the stack (see mmu.rs for the layout). throws 4 if the MMU is already
+        running or there isn't room for a single page.
     75. alloc: allocate some bytes in VM memory. pops the (64-bit) number of bytes from stack and pushes the pointer.
-        alloc may use (much) more memory than requested based on the page size.
+        alloc may use (much) more memory than requested based on the page size. throws 4 if there isn't enough contiguous free memory, or
+        if it would take the heap past the host's limit (Machine::set_alloc_limit).
     76. dealloc: free some bytes. pops the address from stack. must be page-aligned. you do not have to pass the length.
+        throws 1 if the address isn't one alloc handed out.
     77. realloc: reallocate some bytes. pops the address from stack, copies those pages out of VM memory, deallocates them,
         allocates a new chunk, and copies the pages back into that new chunk. If you choose a smaller value than the original allocation, the reallocation
         will be truncated.
+        the new size (64 bits) must be below the address on the stack. the new pointer is pushed.
     78. maketbl: tables are magical data structures. They look roughly like hashmaps indexed by strings. Table data is runtime-typed along some simple types
         and is dynamic in size. Tables are stored entirely in VM memory, and are accessible from outside the VM; they provide a convenient and safe interface
         between the VM application and the ABI.
@@ -149,23 +172,130 @@
         deltbl will always free the memory in the table. If the data is a function it will not attempt to free the function. If the data is a string,
         it will free the string. If the data is a table, it will call freetbl.
     82. freetbl: delete every item in a table and free the table itself.
-    83. updstck: change the stack pointer by an amount.
+    83. updstck: change the stack pointer by an amount. accepts a signed 64-bit amount to add to it: positive to reserve space for locals,
+        negative to drop them. the space isn't zeroed (see scrub). moving the stack pointer below 0 is an out-of-bounds access, and moving it
+        past the stack ceiling is a stack overflow.
         TODO: move this near push and pop
+    84. scrub: zero a range of the stack. takes a 64-bit address and a 64-bit length. the address is resolved like any other operand, so it's
+        normally a negative (stack-relative) one. stack memory is reused by the next call, so anything sensitive left in a function's locals
+        can be read by whatever runs next; scrub them before `ret`.
+    85 -> 88. ashift[l, i, s, b]: shift, except right shifts are arithmetic (they fill with copies of the sign bit).
+        right shifting by the width of the value or more fills the whole value with the sign bit. left shifts behave exactly like shift.
+    89. bxor: take a bitwise XOR of two bytes in memory and replace the first byte with the result.
+    90. vxor: XOR a byte in memory with a specified value. same semantics as bxor.
+    91 -> 94. urem[l, i, s, b]: unsigned remainder of dividing the first point by the second. same overwrite semantics as div, and also throws
+        error code 8 on a zero divisor.
+    95 -> 98. srem[l, i, s, b]: signed remainder. same as urem, but the values are signed and the remainder truncates like C's `%`: the
+        result has the sign of the dividend (so -7 srem 3 is -1).
+    99 -> 102. or[l, i, s, b]: bitwise OR of two values in memory. the result overwrites the first value, like add.
+        bor, band and bxor are the single-byte versions of or, and, and xor, and are kept around for compatibility.
+    103 -> 106. and[l, i, s, b]: bitwise AND. same semantics as or.
+    107 -> 110. xor[l, i, s, b]: bitwise XOR. same semantics as or.
+    111. mmucheck: check the MMU page table for consistency, throwing 9 if it has been corrupted (it lives in VM memory, so buggy code can
+        scribble over it). does nothing if the MMU isn't running.
+
+    112 -> 113. fadd[l, i]: add two floats in memory, overwriting the first. l is an f64, i is an f32.
+    114 -> 115. fsub[l, i]: float subtract. same semantics as fadd.
+    116 -> 117. fmul[l, i]: float multiply. same semantics as fadd.
+    118 -> 119. fdiv[l, i]: float divide. same semantics as fadd. this follows IEEE 754, so dividing by zero gives an infinity or NaN rather than throwing.
+    120 -> 121. fcmp[l, i]: compare two floats in memory, pushing a 1-byte result like cmp: 0 if they're equal, 1 if the first is greater, 2 if the
+        second is greater, and 3 if they're unordered (either one is NaN).
+    122 -> 125. cas[l, i, s, b]: compare-and-swap. pops a 64-bit address, then an expected value, then a new value (so the new value has to be
+        pushed first). if the value at the address equals the expected value, the new value is written there and 1 is pushed, otherwise memory
+        is left alone and 0 is pushed (1 byte either way). there's only ever one thread in a machine, so this is just a conditional write, but
+        it's the primitive lock-free structures are built on and it'll stay correct if machines ever share memory.
+    126. dupblock: duplicate the top bytes of the stack as a block. takes a 64-bit byte count, and copies that many bytes from the top of the
+        stack to right above it, so the stack grows by the count. useful for passing the same struct by value to two calls. throws 1 if the
+        stack doesn't have that many bytes on it.
+    127. uniqueid: push a 64-bit id that's never been handed out by this machine before. ids come from a counter that goes up by one every time,
+        so they're strictly increasing, and the counter keeps going across invokes (restoring a snapshot rolls it back, though). simpler and more predictable than randomness for tags and ids.
+    128. curfn: push the start and then the end (both 64-bit offsets into the text section, end exclusive) of the function the exec pointer is
+        currently in. the image has to record function extents for this to work (the IR does); throws 2 if the current function isn't known.
+    129. checkerrcode: checkerr, but only for one error code. takes a 64-bit handler location and then an 8-bit error code. like checkerr it
+        always pops the SBM off the stack; then if the pending error matches the code it jumps to the handler, and if some other error is
+        pending it rethrows it to the SBM it just restored (so the next scope out gets to handle it). if there's no error it continues.
+        this is what typed catch clauses are built on.
+    130. dock: load a library the host registered (with Machine::register_lib). takes a 64-bit pointer to the library's name as a
+        null-terminated string, and pushes the library's rabbit address. throws 3 if the host doesn't have a library by that name.
+    131. loadfun: look up a function in a docked library. takes a 64-bit pointer to the symbol name (null-terminated, like dock), pops the
+        library's rabbit address, and pushes a rabbit function pointer that can be `call`ed or `invokevirtual`ed. loading the same symbol
+        from the same library again hands back the same rabbit. throws 3 if the library doesn't have the symbol, or what was popped isn't
+        a library.
+    132 -> 135. scmp[l, i, s, b]: signed compare. same as cmp, but the values are two's complement signed ints, so -5 is less than 3
+        (cmp treats everything as unsigned, and thinks -5 is a huge number).
+    136 -> 139. satadd[l, i, s, b]: saturating add. same semantics as add, but a result that doesn't fit is clamped to the biggest (or
+        smallest) value instead of wrapping around, so 200 + 100 as bytes is 255. never throws, whether or not overflow checking is on.
+    140 -> 143. satsub[l, i, s, b]: saturating subtract. unsigned, so anything below 0 comes out as 0.
+    144 -> 147. satmul[l, i, s, b]: saturating multiply.
+    148 -> 159. ssatadd[l, i, s, b], ssatsub[l, i, s, b], ssatmul[l, i, s, b]: signed versions of satadd, satsub and satmul, which clamp at
+        the signed bounds (so -120 - 50 as bytes is -128).
+    160 -> 163. popcnt[l, i, s, b]: count the 1 bits in a value in memory, and overwrite the value with the count (at the same width).
+    164 -> 167. clz[l, i, s, b]: count leading zeros: how many 0 bits there are above the highest 1. same semantics as popcnt. a value of 0
+        is all leading zeros, so it counts as the full width.
+    168 -> 171. ctz[l, i, s, b]: count trailing zeros: how many 0 bits there are below the lowest 1. same semantics as clz.
+    172 -> 175. rotl[l, i, s, b]: rotate a value in memory left by some amount (a signed 8-bit int, like shift): bits that fall off the top
+        come back in at the bottom. negative amounts rotate right. rotating by the width of the value (or any multiple of it) does nothing.
+    176 -> 179. rotr[l, i, s, b]: rotate right. same semantics as rotl, just the other way.
+    180. memcpy: copy a block of memory. pops the destination address, then the source address, then the (64-bit) length, so they have
+        to be pushed in the opposite order. the addresses are resolved after all three are popped. throws 1 if either range goes outside
+        of memory, before anything is copied. the ranges shouldn't overlap: memcpy copies front to back, so a destination just after the
+        source ends up full of repeats of the start of the source. use memmove for that.
+    181. memmove: memcpy, except overlapping ranges are fine: the destination ends up with exactly what the source had beforehand.
+    182. memset: fill a block of memory with a byte. pops the destination address, then the byte, then the (64-bit) length. throws 1 if
+        the range goes outside of memory.
+    183. memcmp: compare two blocks of memory byte by byte (as unsigned bytes, like C's memcmp). pops the first address, then the second,
+        then the (64-bit) length, and pushes a 1-byte result like cmp: 0 if they're the same, 1 if the first is greater, 2 if the second
+        is greater. throws 1 if either range goes outside of memory.
+    184. strlen: pop the address of a null-terminated string and push its (64-bit) length, not counting the null. throws 1 if there's no
+        null before the end of memory.
+    185. memstat: push three u64s: the stack pointer (from before the push), the stack ceiling (the stack can't grow past it: the start of
+        the heap, or the end of memory, or less if the host set a stack size), and how many bytes of the heap are free, which is 0 if the
+        MMU isn't running. the free bytes are in whole pages, and they might not all be in one piece. throws 5 if there's no room for 24 bytes.
+    186. yield: give control back to the host, if it's running the program as a coroutine (see Machine::invoke_resumable). the host picks
+        it up again from the next instruction. under anything else (invoke and friends), yield does nothing.
+    187 -> 190. f2i[l, i], f2u[l, i]: convert a float to an integer. takes the address of the float, then the address to write the integer
+        to (the same order as cpy). f2il turns an f64 into an i64 and f2ii turns an f32 into an i32; f2ul and f2ui do the same, but unsigned.
+        the fraction is thrown away (so 3.9 becomes 3, and -1.5 becomes -1), anything out of range saturates to the integer's biggest or
+        smallest value, and NaN becomes 0. this is exactly what Rust's `as` does.
+    191 -> 194. i2f[l, i], u2f[l, i]: convert an integer to a float, with the same arguments as f2i. i2fl turns an i64 into an f64 and i2fi an
+        i32 into an f32; u2fl and u2fi read unsigned integers. integers too big to be exact get rounded to the nearest float.
+    195 -> 198. assert[l, i, s, b]: throw 7 (assertion failed) if a value in memory is 0, for self-checking test programs. takes the
+        location, then a 64-bit id for the assertion, which the host can read back with Machine::failed_assertion to see which one failed.
+    199. getsbm: push the current SBM, the stack pointer and then the execution pointer (in the same order setsbm saves the old one), so a
+        program can see where a throw would land. (0, 0) means nothing would catch it. throws 5 if there's no room for 16 bytes.
+    200. clearsbm: set the SBM to (0, 0) without touching the stack, so the next throw aborts the VM. whatever setsbm pushed is still on
+        the stack, and checkerr pops it as usual.
+        getsbm and clearsbm are for programs that do their own unwinding. they go around setsbm and checkerr, so misusing them (like
+        clearing the SBM and then expecting a handler to run) quietly breaks error handling.
+    201 -> 204. sdiv[l, i, s, b]: signed divide. same as div, but the values are signed and the quotient truncates toward zero like C's `/`
+        (so -7 sdiv 2 is -3). dividing the most negative value by -1 wraps back around to the most negative value.
 
-    As yet there is no "native" floating-point support in anyvm.
+    floats are IEEE 754, stored big-endian like everything else.
 
     There are no registers in anyvm. Why is this?
     Registers make sense in actual processors because they're *very, very* fast. RAM, even L1 cache, is *much* slower than processor registers.
     However, because emulated registers would be stored in RAM regardless, registers are entirely pointless for anyvm.
 */
 
-use std::collections::HashMap;
+// the core (machines, images, and running them) only needs alloc, so it builds without std when the std feature is off. the compilers,
+// stdabi, Machine::set_output and invoke_timeout need std.
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+use alloc::{ boxed::Box, string::String, sync::Arc, vec, vec::Vec };
+use core::sync::atomic::AtomicBool;
+
+#[cfg(feature = "std")]
+use std::collections::{ HashMap, HashSet };
+#[cfg(not(feature = "std"))]
+use hashbrown::{ HashMap, HashSet };
+use core::cmp::Ordering;
+use core::fmt::Write;
 
 mod numerical;
 use numerical::*;
+pub use numerical::Storable; // so hosts can name the types peek_stack_as and pop_host_as read
 
 
-use std::fmt::Debug;
 pub mod invoke;
 
 
@@ -173,15 +303,98 @@ pub mod error;
 use error::*;
 
 
+#[cfg(feature = "std")]
 pub mod ir;
+#[cfg(feature = "std")]
 pub mod avc;
 
 
+mod mmu;
+use mmu::Mmu;
+
+
+mod compile;
+use compile::DecodedInsn;
+mod verify;
+mod ext;
+#[cfg(feature = "std")]
+pub mod stdabi;
+mod snapshot;
+pub use snapshot::MachineSnapshot;
+mod serialize;
+mod link;
+
+
+pub const ACCESS_PADDING : i64 = WIDEST_STORABLE as i64; // bytes of padding at the end of memory, past end (see stackaddr for why).
+// it's as wide as the widest thing memory can be read as, so a wider type moves it along
+const RABBIT_START : i64 = 1 << 62; // rabbit addresses count up from here. memory can never grow this far
+const DEFAULT_INTERRUPT_INTERVAL : u32 = 1024; // an atomic load is cheap, but not next to most instructions
+const DUMP_STACK_BYTES : usize = 256; // how much of the stack dump_state shows, from the bottom
+const HOST_RETURN : i64 = RABBIT_START - 1; // the return address invoke_with_args pushes. it isn't memory or a rabbit, so a ret to it can
+                                            // only mean the function is done
+
+
+#[derive(Debug, Clone, Default)]
 pub struct Image {
     function_table : HashMap<String, i64>, // contains offsets into the text section.
     static_table : HashMap<String, i64>, // contains offsets into the static section
     static_section : Vec<u8>,
-    text_section : Vec<u8> // bytecode. contains a bunch of functions crammed together.
+    text_section : Vec<u8>, // bytecode. contains a bunch of functions crammed together.
+    function_extents : Vec<(i64, i64)>, // (start, end) offsets into the text section of every function, public or not. optional: it's only
+                                        // used by curfn, so it can be left empty.
+    relocations : Option<Vec<Relocation>>, // every absolute address baked into the image, so Image::link can move them. None if whatever
+                                           // built the image didn't keep track (AVC, or bytecode by hand), which means it can't be linked
+    debug_info : Option<DebugInfo>, // which line of the source every instruction came from. None for bytecode by hand
+    frame_sizes : Option<HashMap<String, u64>> // function name -> how many bytes it reserves for locals with updstck. advisory: nothing checks
+                                               // it. None for bytecode by hand
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    Static,
+    Text
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RelocTarget {
+    Section(Section), // somewhere in one of the image's own sections
+    Symbol(String) // a public function or static of another image (an IR `.name extern`). 0 until Image::link fills it in
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relocation { // an absolute address in an image
+    pub section : Section, // the section the 8-byte address is written in
+    pub offset : usize, // where in that section it starts
+    pub target : RelocTarget
+}
+
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DebugInfo { // what ir::build and avc::build remember about the source, for Image::source_location
+    pub spans : Vec<SourceSpan> // in the order they were emitted. AVC spans can nest (an if covers its body), IR spans never do
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SourceSpan { // the bytes start..end of the text section were built from the source at line:column (both counted from 1)
+    pub start : i64,
+    pub end : i64,
+    pub line : usize,
+    pub column : usize
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ImageSummary { // the size of everything in an image, from Image::summary
+    pub static_size : usize, // bytes
+    pub text_size : usize,
+    pub functions : usize, // all of them, public or not. 0 if the image has no function extents
+    pub exported_functions : usize,
+    pub exported_statics : usize,
+    pub relocations : Option<usize> // None if the image has no relocation table
 }
 
 
@@ -189,30 +402,181 @@ impl Image {
     pub fn lookup(&self, thing : String) -> i64 {
         self.static_section.len() as i64 + self.function_table.get(&thing).unwrap() // todo: throw an error, rather than panicking
     }
+
+    pub fn lookup_function(&self, name : &str) -> Option<i64> { // lookup, but None instead of a panic if there's no public function by that name
+        Some(self.static_section.len() as i64 + self.function_table.get(name)?)
+    }
+
+    pub fn lookup_static(&self, name : &str) -> Result<i64, LookupErr> { // the address of a public static. statics are mounted at the very
+        // start of memory, so this is just its offset
+        self.static_table.get(name).copied().ok_or_else(|| LookupErr::NoSuchStatic(String::from(name)))
+    }
+
+    pub fn static_section(&self) -> &[u8] {
+        &self.static_section
+    }
+
+    pub fn text_section(&self) -> &[u8] {
+        &self.text_section
+    }
+
+    pub fn exported_functions(&self) -> impl Iterator<Item=(&str, i64)> { // (name, address) of every public function, in no particular
+        // order. addresses are the same as lookup_function's
+        self.function_table.iter().map(|(name, offset)| (name.as_str(), self.static_section.len() as i64 + offset))
+    }
+
+    pub fn summary(&self) -> ImageSummary {
+        ImageSummary {
+            static_size : self.static_section.len(),
+            text_size : self.text_section.len(),
+            functions : self.function_extents.len(),
+            exported_functions : self.function_table.len(),
+            exported_statics : self.static_table.len(),
+            relocations : self.relocations.as_ref().map(Vec::len)
+        }
+    }
+
+    pub fn relocations(&self) -> Option<&[Relocation]> { // where every absolute address in the image is, if whatever built it kept track
+        self.relocations.as_deref()
+    }
+
+    pub fn frame_sizes(&self) -> Option<&HashMap<String, u64>> {
+        self.frame_sizes.as_ref()
+    }
+
+    pub fn max_frame_size(&self) -> Option<u64> { // the biggest frame any one function reserves, for sizing the stack (see
+        // Machine::set_stack_size). a call chain needs the frames of everything in it, plus arguments and return addresses, so this is a
+        // lower bound. None if the image has no frame sizes, or no functions
+        self.frame_sizes.as_ref()?.values().copied().max()
+    }
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    pub fn source_location(&self, text_offset : i64) -> Option<(usize, usize)> { // the (line, column) in the source of the instruction at
+        // this offset into the text section (an address minus the mounted text start). the narrowest span wins, so a fault in an AVC if
+        // body points at the line in the body, not the if
+        self.debug_info.as_ref()?.spans.iter().filter(|span| span.start <= text_offset && text_offset < span.end)
+            .min_by_key(|span| span.end - span.start).map(|span| (span.line, span.column))
+    }
 }
 
 
 pub trait Table {
-    fn lookup(data : &str) -> ExtData;
+    fn lookup(&self, data : &str) -> Option<ExtData>; // what loadfun hands out for a symbol, or None if the table doesn't have it
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame { // one call on the stack, as recorded in Machine::last_backtrace
+    pub function : i64, // the address that was called
+    pub return_addr : i64,
+    pub name : Option<String> // the called function's name in the mounted image's function table, if it's in there
+}
+
+
+pub struct Coroutine<'a> { // a run started by Machine::invoke_resumable, for hosts that take turns between lots of programs. everything
+    // about where the program is lives in the machine, so the coroutine is just a handle on it
+    machine : &'a mut Machine,
+    finished : bool
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SandboxConfig { // limits for invoke_sandboxed. a run under the same config, on the same freshly mounted image, always ends the same way
+    pub max_instructions : u64, // past this, InvokeErr::GasExhausted
+    pub stack_size : Option<u64>, // like set_stack_size, for the length of the run, except that pushing past it is
+                                  // InvokeErr::StackLimitExceeded, which the guest can't catch
+    pub max_alloc : Option<u64>, // the most bytes one alloc or realloc can ask for. more is InvokeErr::AllocLimitExceeded
+    pub allow_externals : bool // externals can do anything, so they're the one thing the VM can't make deterministic. calling one without
+                               // this is InvokeErr::ExternalForbidden
+}
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StackArg<'a> { // an argument for invoke_with_args
+    Long(i64),
+    Int(i32),
+    Short(i16),
+    Byte(i8),
+    Buffer(&'a [u8]) // copied onto the stack below the arguments, and passed as a (long) pointer to the copy
 }
 
 
 pub enum ExtData {
-    Function(Box<dyn FnMut<(&mut Machine)>>),
+    Function(Box<dyn FnMut(&mut Machine)>),
     Table(Box<dyn Table>)
 }
 
 
+type TraceHook = Box<dyn FnMut(i64, u8, i64)>;
+
+
 pub struct Machine {
     memory : Vec<u8>,
     text_start : i64,
     stack_start : i64,
     end : i64,
-    ext_data : Vec<ExtData>,
+    rabbits : HashMap<i64, ExtData>, // the rabbit table: what every live rabbit address does
+    free_rabbits : Vec<i64>, // rabbit addresses that have been freed, and can be handed out again
+    unwound_externals : ext::Unwound, // externals that panicked while they were out of the rabbit table, for invoke_catching to put back
+    ext_names : HashMap<String, i64>, // name -> rabbit address of every registered external
+    libraries : HashMap<String, i64>, // name -> rabbit address of every registered library, for dock
+    loaded_functions : HashMap<(i64, String), i64>, // (library rabbit, symbol) -> the rabbit loadfun handed out for it, so a guest that
+    // loads the same function over and over doesn't fill the rabbit table
+    ext_logs : HashMap<String, Vec<Vec<i64>>>, // arguments of every call to a mocked external
     stack_pointer : i64,
     exec_pointer : i64,
     errcode : u8,
-    sbm : (i64, i64) // (stack, exec): stack break marker
+    sbm : (i64, i64), // (stack, exec): stack break marker
+    rabbit_top : i64,
+    mmu : Option<Mmu>, // None until startmmu
+    function_extents : Vec<(i64, i64)>, // copied from the image by mount
+    function_names : HashMap<i64, String>, // address -> name of every function in the mounted image's function table, for backtraces
+    static_names : HashMap<String, i64>, // the mounted image's static table, for static_addr
+    call_stack : Vec<(i64, i64, i64)>, // (function, return address, stack pointer with the return address pushed) of every call that
+                                       // hasn't returned yet. a shadow of what's on the VM stack, which the program could have scribbled on
+    last_backtrace : Vec<Frame>, // the call stack when the last throw or fault happened. see last_backtrace
+    failed_assertion : Option<u64>, // the id of the last assert that failed. see failed_assertion
+    mounted_statics : Vec<u8>, // the static section as it was mounted, so reset can put it back
+    next_id : u64, // the next id uniqueid will hand out
+    stack_size : Option<u64>, // the most bytes the stack can hold, if the host limited it. see stack_ceiling
+    alloc_limit : Option<u64>, // the most bytes the heap can have allocated, if the host limited it. see set_alloc_limit
+    #[cfg(feature = "std")]
+    output : Box<dyn std::io::Write>, // where externals print to (stdout unless the host changed it)
+    overflow_checked : bool, // whether add, sub and mul throw 6 instead of wrapping. see set_overflow_checked
+    halted : Option<InvokeResult>, // set by Machine::halt, and picked up by step once the instruction is done
+    exit_code : Option<i64>, // what the last run passed to exit, if it got that far. see exit_code
+    return_slot : i64, // where the function invoke_with_args called leaves its return value
+    sandbox : Option<SandboxConfig>, // the limits invoke_sandboxed is running under, if it is
+    trace : Option<TraceHook>, // called by step with (exec pointer, opcode, stack pointer) before every instruction
+    opcode_stats : Option<Box<[u64; 256]>>, // how many times each opcode has run since the last invoke, if enable_opcode_stats turned it on
+    breakpoints : HashSet<i64>, // addresses run_until_break stops at
+    watchpoints : Vec<(usize, usize)>, // (address, length) of every range run_until_break watches for writes
+    watch_hit : bool, // whether the current instruction has written to a watched range
+    paused_at : Option<i64>, // the breakpoint run_until_break last stopped on, for resume_until_break to step past
+    interrupt : Arc<AtomicBool>, // set from anywhere (another thread, usually) to stop the run. see interrupt_handle
+    interrupt_interval : u32, // how many instructions run goes between looks at the interrupt flag
+    insn_cache : Option<Vec<DecodedInsn>> // one entry for every byte of the text section, if set_insn_cache turned it on
+}
+
+
+impl core::fmt::Debug for Machine { // the registers and layout, but not memory (that's what dump_state is for) or the externals
+    // themselves, which are closures
+    fn fmt(&self, f : &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Machine")
+            .field("exec_pointer", &self.exec_pointer)
+            .field("stack_pointer", &self.stack_pointer)
+            .field("text_start", &self.text_start)
+            .field("stack_start", &self.stack_start)
+            .field("end", &self.end)
+            .field("errcode", &self.errcode)
+            .field("sbm", &self.sbm)
+            .field("externals", &self.rabbits.len())
+            .field("mmu_running", &self.mmu.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 
@@ -220,48 +584,220 @@ impl Machine {
     pub fn new(capacity : usize) -> Machine {
         Machine {
             memory : vec![0u8; capacity],
-            end : capacity as i64 - 8, // 8 byte padding at the end. why? to save a tonne of cycles. more below.
+            end : capacity as i64 - ACCESS_PADDING, // padding at the end. why? to save a tonne of cycles. more below.
             stack_start : 0,
             text_start : 0,
-            ext_data : vec![],
+            rabbits : HashMap::new(),
+            free_rabbits : vec![],
+            unwound_externals : Default::default(),
+            ext_names : HashMap::new(),
+            libraries : HashMap::new(),
+            loaded_functions : HashMap::new(),
+            ext_logs : HashMap::new(),
             stack_pointer : 0,
             exec_pointer : 0,
             sbm : (0, 0),
-            errcode : 0
+            errcode : 0,
+            rabbit_top : RABBIT_START, // way past the end of memory, so grow_memory can't run into them
+            mmu : None,
+            function_extents : vec![],
+            function_names : HashMap::new(),
+            static_names : HashMap::new(),
+            call_stack : vec![],
+            last_backtrace : vec![],
+            failed_assertion : None,
+            mounted_statics : vec![],
+            next_id : 0,
+            stack_size : None,
+            alloc_limit : None,
+            #[cfg(feature = "std")]
+            output : Box::new(std::io::stdout()),
+            overflow_checked : false,
+            halted : None,
+            exit_code : None,
+            return_slot : 0,
+            sandbox : None,
+            trace : None,
+            opcode_stats : None,
+            breakpoints : HashSet::new(),
+            watchpoints : vec![],
+            watch_hit : false,
+            paused_at : None,
+            interrupt : Arc::new(AtomicBool::new(false)),
+            interrupt_interval : DEFAULT_INTERRUPT_INTERVAL,
+            insn_cache : None
+        }
+    }
+
+    pub fn from_memory(memory : Vec<u8>, text_start : i64, stack_start : i64) -> Machine { // build a machine around memory that's already
+        // laid out (statics from 0, text from text_start, stack from stack_start), like a dump of a running machine. it's used as is, so
+        // nothing gets zeroed. like mount, reset puts the statics back the way they are here. panics if the layout doesn't fit in memory
+        // (the last ACCESS_PADDING bytes are padding, see new).
+        let end = memory.len() as i64 - ACCESS_PADDING;
+        assert!(0 <= text_start && text_start <= stack_start && stack_start <= end, "bad memory layout: text at {}, stack at {}, end at {}",
+            text_start, stack_start, end);
+        let mut machine = Machine::new(0);
+        machine.mounted_statics = memory[..text_start as usize].to_vec();
+        machine.memory = memory;
+        machine.end = end;
+        machine.text_start = text_start;
+        machine.stack_start = stack_start;
+        machine.stack_pointer = stack_start;
+        machine
+    }
+
+    pub fn mount(&mut self, image : &Image) -> MemResult<()> { // copy an image into the bottom of memory, ready to invoke. fails with
+        // OutOfMemory if it doesn't fit (with ACCESS_PADDING to spare)
+        let size = image.static_section.len() + image.text_section.len();
+        if size.saturating_add(ACCESS_PADDING as usize) > self.memory.len() {
+            return Err(MemoryErr::OutOfMemory);
         }
+        self.mount_at(image, 0).map_err(|_| MemoryErr::OutOfMemory) // without relocating, running out of room is the only way it fails
     }
 
-    pub fn mount(&mut self, image : &Image) {
-        let mut head = self.memory.iter_mut();
-        let mut ss = image.static_section.iter();
-        let mut ts = image.text_section.iter();
-        while let Some(byte) = ss.next() {
-            *head.next().unwrap() = *byte; // TODO: throw OOM rather than panicking
+    pub fn mount_at(&mut self, image : &Image, base : i64) -> Result<(), MountErr> { // mount with the static section at base instead of 0,
+        // leaving the memory below it alone (for guard pages, or memory the host wants for itself). every address in the image is moved up by
+        // base, which takes a relocation table (see Image::link) unless base is 0. functions end up at base + Image::lookup
+        let size = (image.static_section.len() + image.text_section.len()) as i64;
+        if base < 0 || base.checked_add(size).is_none_or(|top| top > self.end) {
+            return Err(MountErr::OutOfMemory);
         }
-        while let Some(byte) = ts.next() {
-            *head.next().unwrap() = *byte;
+        let mut statics = image.static_section.clone();
+        let mut text = image.text_section.clone();
+        if base != 0 {
+            for relocation in image.relocations.as_ref().ok_or(MountErr::NotRelocatable)? {
+                if let RelocTarget::Symbol(name) = &relocation.target {
+                    return Err(MountErr::UnresolvedSymbol(name.clone()));
+                }
+                let bytes = match relocation.section {
+                    Section::Static => &mut statics,
+                    Section::Text => &mut text
+                };
+                if relocation.offset.checked_add(8).is_none_or(|end| end > bytes.len()) {
+                    return Err(MountErr::BadRelocation);
+                }
+                link::patch(bytes, relocation.offset, |addr| addr.wrapping_add(base));
+            }
         }
-        self.text_start = image.static_section.len() as i64;
-        self.stack_start = self.text_start + image.text_section.len() as i64;
+        let base = base as usize;
+        self.memory[base..base + statics.len()].copy_from_slice(&statics);
+        self.memory[base + statics.len()..base + statics.len() + text.len()].copy_from_slice(&text);
+        self.text_start = (base + statics.len()) as i64;
+        self.stack_start = self.text_start + text.len() as i64;
+        self.stack_pointer = self.stack_start;
+        self.function_extents = image.function_extents.clone();
+        self.function_names = image.function_table.iter().map(|(name, offset)| (self.text_start + offset, name.clone())).collect();
+        self.static_names = image.static_table.iter().map(|(name, offset)| (name.clone(), base as i64 + offset)).collect();
+        self.call_stack.clear();
+        self.mounted_statics = statics;
+        self.clear_insn_cache();
+        Ok(())
+    }
+
+    pub fn reset(&mut self) { // get ready to run the mounted image again from scratch, without copying the text section back in. the statics
+        // go back to how they were mounted, and everything above the text section (the stack, the heap and the page table) is zeroed.
+        // externals, the uniqueid counter and the stack size stay as they are.
+        let statics = (self.text_start as usize - self.mounted_statics.len())..self.text_start as usize; // see mount_at
+        self.memory[statics].copy_from_slice(&self.mounted_statics);
+        self.memory[self.stack_start as usize..].fill(0);
+        self.stack_pointer = self.stack_start;
+        self.exec_pointer = 0;
+        self.errcode = 0;
+        self.sbm = (0, 0);
+        self.mmu = None;
+        self.exit_code = None;
+        self.call_stack.clear();
+        self.last_backtrace.clear();
+        self.failed_assertion = None;
+    }
+
+    pub fn usable_memory(&self) -> i64 { // where usable memory ends: the capacity, less ACCESS_PADDING. nothing at or past it can be read
+        // or written, and the MMU puts its page table right below it
+        self.end
+    }
+
+    pub fn exit_code(&self) -> Option<i64> { // the code the program passed to exit. None if it hasn't exited since the last invoke (or
+        // reset), including when it aborted, halted, or is still running
+        self.exit_code
     }
 
-    unsafe fn memory_as_at<'t, T>(&'t mut self, pos : usize) -> MemResult<&'t mut [T]> {
-        if pos < self.memory.len() {
-            Ok(std::mem::transmute::<&mut [u8], &mut [T]>(&mut self.memory[pos..]))
+    pub fn static_addr(&self, name : &str) -> Result<i64, LookupErr> { // Image::lookup_static, on the mounted image. handy for setting up a
+        // global before invoking
+        self.static_names.get(name).copied().ok_or_else(|| LookupErr::NoSuchStatic(String::from(name)))
+    }
+
+    pub fn last_backtrace(&self) -> &[Frame] { // the calls that were in progress when the last throw or fault happened, innermost first.
+        // a throw that gets caught still leaves its backtrace here. empty if nothing has gone wrong since the last invoke (or reset)
+        &self.last_backtrace
+    }
+
+    pub fn failed_assertion(&self) -> Option<u64> { // the id of the last assert that failed (throwing 7), even if the program caught it.
+        // None if none have failed since the last invoke (or reset)
+        self.failed_assertion
+    }
+
+    pub(crate) fn capture_backtrace(&mut self) {
+        self.last_backtrace = self.call_stack.iter().rev().map(|&(function, return_addr, _)| Frame {
+            function,
+            return_addr,
+            name : self.function_names.get(&function).cloned()
+        }).collect();
+    }
+
+    pub fn dump_state(&self) -> String { // a human-readable picture of the registers and the bottom of the stack, for debugging bytecode by
+        // hand. the stack is shown 8 bytes to a row (as far as the stack pointer, up to DUMP_STACK_BYTES), hex on the left and ASCII on the right
+        let mut out = String::new();
+        let _ = writeln!(out, "exec_pointer: {}, stack_pointer: {}", self.exec_pointer, self.stack_pointer); // writing to a String can't fail
+        let _ = writeln!(out, "text_start: {}, stack_start: {}, end: {}", self.text_start, self.stack_start, self.end);
+        let _ = writeln!(out, "errcode: {}, sbm: ({}, {})", self.errcode, self.sbm.0, self.sbm.1);
+        let start = self.stack_start.clamp(0, self.memory.len() as i64) as usize;
+        let used = (self.stack_pointer - self.stack_start).clamp(0, DUMP_STACK_BYTES as i64) as usize;
+        let stack = &self.memory[start..(start + used).min(self.memory.len())];
+        let _ = writeln!(out, "stack ({} bytes in use):", self.stack_pointer - self.stack_start);
+        for (row, bytes) in stack.chunks(8).enumerate() {
+            let _ = write!(out, "{:>8} ", start + row * 8);
+            for i in 0..8 {
+                match bytes.get(i) {
+                    Some(byte) => { let _ = write!(out, " {:02x}", byte); },
+                    None => out.push_str("   ")
+                }
+            }
+            out.push_str("  ");
+            out.extend(bytes.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }));
+            out.push('\n');
         }
-        else {
-            Err(MemoryErr::SegmentationFault)
+        out
+    }
+
+    pub fn remount(&mut self, image : &Image) { // reset the machine and mount a different image, reusing the memory buffer. if the new image
+        // would leave less room for the stack and heap than the old one had, the buffer grows to keep it the same
+        let room = (self.end - self.stack_start) as usize;
+        let needed = image.static_section.len() + image.text_section.len() + room + ACCESS_PADDING as usize;
+        if needed > self.memory.len() {
+            self.memory.resize(needed, 0);
+            self.end = needed as i64 - ACCESS_PADDING;
         }
+        self.memory.fill(0);
+        self.mount(image).unwrap(); // there's room now
+        self.reset();
     }
 
-    fn next_rabbit(&mut self) -> i64 {
+    fn memory_at(&mut self, pos : usize, len : usize) -> MemResult<&mut [u8]> { // len bytes of raw memory. pos is a real index, not a
+        // VM address, so it should already have been through stackaddr
+        self.memory.get_mut(pos..pos.saturating_add(len)).ok_or(MemoryErr::SegmentationFault)
+    }
+
+    fn next_rabbit(&mut self) -> i64 { // freed addresses get reused first
+        if let Some(rabbit) = self.free_rabbits.pop() {
+            return rabbit;
+        }
         self.rabbit_top += 1;
         self.rabbit_top
     }
 
     fn stackaddr(&self, mut addr : i64) -> MemResult<usize> { // note how this doesn't actually check typed alignment,
         // meaning it's possible to dereference capacity - 1 as a u64, and peek into the 7 bytes *afterwards*
-        // (which would cause a panic). This is avoided by simply adding 8 bytes of padding at the end of the memory block.
+        // (which would cause a panic). This is avoided by simply adding ACCESS_PADDING bytes of padding at the end of the memory block.
         // exhaustive checking is *possible*, but ultimately expensive and bug-prone; this system maximizes the speed of accesses
         // without compromising the hypervisor: a hacker *can* read past the end of memory, but won't see anything useful and won't panic the hypervisor.
         if addr < 0 {
@@ -275,50 +811,184 @@ impl Machine {
         }
     }
 
-    fn get_at_as<T : Numerical>(&mut self, pos : i64) -> MemResult<T> {
+    fn stackaddr_abs(&self, addr : i64) -> MemResult<usize> { // stackaddr for addresses that can't be stack-relative, like the exec
+        // pointer. skips the relative adjustment, so there's only the one bounds check
+        debug_assert!(addr >= 0, "stackaddr_abs got a relative address");
+        if addr as u64 >= self.end as u64 {
+            Err(MemoryErr::SegmentationFault)
+        }
+        else {
+            Ok(addr as usize)
+        }
+    }
+
+    fn stackrange(&self, addr : i64, len : usize) -> MemResult<core::ops::Range<usize>> { // stackaddr, but for a whole range of bytes
+        let start = self.stackaddr(addr)?;
+        match start.checked_add(len) {
+            Some(stop) if stop <= self.end as usize => Ok(start..stop),
+            _ => Err(MemoryErr::SegmentationFault)
+        }
+    }
+
+    // host access to VM memory. addresses work exactly like they do for the VM, so negative addresses are relative to the top of the stack.
+    pub fn read_bytes(&self, addr : i64, len : usize) -> MemResult<&[u8]> {
+        let range = self.stackrange(addr, len)?;
+        Ok(&self.memory[range])
+    }
+
+    pub fn write_bytes(&mut self, addr : i64, data : &[u8]) -> MemResult<()> {
+        let range = self.stackrange(addr, data.len())?;
+        self.wrote(range.start, data.len());
+        self.memory[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub(crate) fn wrote(&mut self, start : usize, len : usize) { // note a write to memory, for watchpoints and the instruction cache. cheap
+        // when there aren't any
+        if self.watchpoints.iter().any(|&(addr, watched)| start < addr + watched && addr < start + len) {
+            self.watch_hit = true;
+        }
+        self.invalidate_insn_cache(start, len);
+    }
+
+    pub fn grow_memory(&mut self, additional : usize) -> MemResult<i64> { // add more memory to the end of the machine. returns the new end.
+        // if the MMU is running, the heap gets all of the new memory it can use, and the page table moves to the new end. allocations stay
+        // exactly where they are, so pointers into the heap are still good. fails with OutOfMemory if the host can't provide the memory.
+        let new_len = self.memory.len().checked_add(additional).filter(|len| (*len as i64) < RABBIT_START).ok_or(MemoryErr::OutOfMemory)?;
+        self.memory.try_reserve_exact(additional).map_err(|_| MemoryErr::OutOfMemory)?;
+        self.memory.resize(new_len, 0);
+        let old_end = self.end;
+        self.end = new_len as i64 - ACCESS_PADDING; // keep the padding at the end
+        self.grow_mmu(old_end);
+        Ok(self.end)
+    }
+
+    pub fn read_str(&self, addr : i64) -> Result<&str, InvokeErr> { // read a null-terminated utf-8 string, leaving off the null
+        let start = self.stackaddr(addr).map_err(InvokeErr::MemErr)?;
+        let bytes = &self.memory[start..self.end as usize];
+        core::ffi::CStr::from_bytes_until_nul(bytes).map_err(str_proc_fail)?.to_str().map_err(str_proc_fail)
+    }
+
+    pub fn read_cstr(&self, addr : i64, max_len : usize) -> Result<String, CStrErr> { // read_str for strings the host can't trust: stops
+        // looking for the null after max_len bytes, and hands back the bytes if they aren't utf-8
+        let start = self.stackaddr(addr).map_err(CStrErr::MemErr)?;
+        let bytes = &self.memory[start..self.end as usize];
+        let len = match bytes.iter().take(max_len.saturating_add(1)).position(|byte| *byte == 0) {
+            Some(len) => len,
+            None if bytes.len() > max_len => return Err(CStrErr::Unterminated),
+            None => return Err(CStrErr::MemErr(MemoryErr::SegmentationFault)) // memory ran out first, like strlen
+        };
+        String::from_utf8(bytes[..len].to_vec()).map_err(|e| CStrErr::NotUtf8(e.into_bytes()))
+    }
+
+    pub fn read_u64(&self, addr : i64) -> MemResult<u64> {
+        Ok(u64::from_be_bytes(self.read_bytes(addr, 8)?.try_into().unwrap()))
+    }
+
+    pub fn write_u64(&mut self, addr : i64, val : u64) -> MemResult<()> {
+        self.write_bytes(addr, &val.to_be_bytes())
+    }
+
+    fn get_at_as<T : Storable>(&mut self, pos : i64) -> MemResult<T> {
         let pos = self.stackaddr(pos)?;
-        Ok(unsafe {
-            self.memory_as_at::<T>(pos)?[0].from_be()
-        })
+        Ok(T::from_be_bytes(self.memory_at(pos, T::BYTE_COUNT)?))
     }
 
-    fn setmem<T : Numerical>(&mut self, pos : i64, val : T) -> MemResult<T> {
+    fn setmem<T : Storable>(&mut self, pos : i64, val : T) -> MemResult<T> {
         let pos = self.stackaddr(pos)?;
-        unsafe {
-            self.memory_as_at::<T>(pos)?[0] = val.to_be();
-        }
+        self.wrote(pos, T::BYTE_COUNT);
+        val.to_be_bytes(self.memory_at(pos, T::BYTE_COUNT)?);
         Ok(val)
     }
 
-    fn pop_arg<T : Numerical>(&mut self) -> MemResult<T> { // pop an arg
-        let ret = self.get_at_as(self.stack_pointer);
+    fn pop_arg<T : Storable>(&mut self) -> MemResult<T> { // pop an arg. execute makes sure the exec pointer isn't negative
+        let pos = self.stackaddr_abs(self.exec_pointer)?;
+        let ret = T::from_be_bytes(self.memory_at(pos, T::BYTE_COUNT)?);
         self.exec_pointer += T::BYTE_COUNT as i64;
-        Ok(ret.from_be())
-    }
-
-    fn pop_arg_addr(&mut self) -> MemResult<usize> { // pop an argument and convert it to a stackaddr
-        let arg = self.pop_arg::<i64>()?;
-        self.stackaddr(arg)
+        Ok(ret)
     }
 
-    fn pop_as<T : Numerical>(&mut self) -> MemResult<T> { // pop a thing off stack
+    fn pop_as<T : Storable>(&mut self) -> MemResult<T> { // pop a thing off stack
         let r = self.get_at_as::<T>(-(T::BYTE_COUNT as i64));
         self.stack_pointer -= T::BYTE_COUNT as i64;
         r
     }
 
-    fn push<T : Numerical>(&mut self, thing : T) -> MemResult<()> { // push a thing to stack
-        self.setmem(0, thing);
-        self.stack_pointer += T::BYTE_COUNT as i64;
-        Ok(())
+    pub fn peek_stack_as<T : Storable>(&self, depth : i64) -> MemResult<T> { // read a value off the stack without popping it, for hosts
+        // picking up what a function left behind. depth is a stack-relative address like in bytecode, so -8 is the long on top. it has to
+        // be below the stack pointer
+        if depth > -(T::BYTE_COUNT as i64) {
+            return Err(MemoryErr::SegmentationFault);
+        }
+        Ok(T::from_be_bytes(self.read_bytes(depth, T::BYTE_COUNT)?))
+    }
+
+    pub fn pop_host_as<T : Storable>(&mut self) -> MemResult<T> { // peek_stack_as the value on top, then pop it. fails rather than popping
+        // past the bottom of the stack
+        if self.stack_pointer - (T::BYTE_COUNT as i64) < self.stack_start {
+            return Err(MemoryErr::SegmentationFault);
+        }
+        let value = self.peek_stack_as(-(T::BYTE_COUNT as i64))?;
+        self.stack_pointer -= T::BYTE_COUNT as i64;
+        Ok(value)
+    }
+
+    pub fn set_stack_size(&mut self, bytes : u64) { // limit how big the stack can get, so runaway recursion fails early with a stack overflow
+        // (error 5) instead of eating all of memory
+        self.stack_size = Some(bytes);
+    }
+
+    pub fn set_alloc_limit(&mut self, bytes : u64) { // cap how much of the heap can be allocated at once, so a script can't take all of
+        // memory. alloc and realloc throw 4 rather than go past it. allocations take whole pages, so they count as that much
+        self.alloc_limit = Some(bytes);
+    }
+
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> { // setting this to true stops invoke (and the rest of the invokes) with
+        // InvokeErr::Interrupted within interrupt_interval instructions. the exec and stack pointers are left alone, so resume_limited picks
+        // the program up again. the flag is cleared when it's seen, so it only stops one run
+        self.interrupt.clone()
+    }
+
+    pub fn set_interrupt_interval(&mut self, instructions : u32) { // how often to look at the interrupt flag: more often stops sooner, but
+        // runs slower. 0 is treated as 1
+        self.interrupt_interval = instructions.max(1);
+    }
+
+    pub fn set_overflow_checked(&mut self, checked : bool) { // make add, sub and mul throw 6 (arithmetic overflow) when the result doesn't
+        // fit, instead of wrapping around. they work on unsigned values, so going below 0 counts too. off by default, since it's slower.
+        self.overflow_checked = checked;
+    }
+
+    pub(crate) fn stack_ceiling(&self) -> i64 { // the stack can't grow past this. it always stops at the heap, if there is one
+        let mut ceiling = self.mmu.map_or(self.end, |mmu| mmu.heap_start);
+        if let Some(size) = self.stack_size {
+            ceiling = ceiling.min(self.stack_start.saturating_add(size.min(i64::MAX as u64) as i64));
+        }
+        ceiling
+    }
+
+    fn at_sandbox_stack_limit(&self) -> bool { // whether the stack ceiling is the one invoke_sandboxed set, rather than the heap or the
+        // end of memory
+        self.sandbox.and_then(|sandbox| sandbox.stack_size).is_some_and(|size| {
+            self.stack_ceiling() == self.stack_start.saturating_add(size.min(i64::MAX as u64) as i64)
+        })
+    }
+
+    pub(crate) fn check_stack(&self, bytes : u64) -> MemResult<()> { // make sure pushing this many bytes won't overflow the stack
+        match self.stack_pointer.checked_add_unsigned(bytes) {
+            Some(top) if top <= self.stack_ceiling() => Ok(()),
+            _ => Err(MemoryErr::StackOverflow)
+        }
     }
 
-    fn pop_addr(&mut self) -> MemResult<usize> { // pop an address off stack and run it through stackaddr()
-        let vm_addr = self.pop_as::<i64>()?;
-        self.stackaddr(vm_addr)
+    fn push_as<T : Storable>(&mut self, thing : T) -> MemResult<()> { // push a thing to stack
+        self.check_stack(T::BYTE_COUNT as u64)?;
+        self.setmem(self.stack_pointer, thing)?;
+        self.stack_pointer += T::BYTE_COUNT as i64;
+        Ok(())
     }
 
-    fn swap_as<T : Numerical>(&mut self, one : i64, two : i64) -> MemResult<()> {
+    fn swap_as<T : Storable>(&mut self, one : i64, two : i64) -> MemResult<()> {
         let one_val = self.get_at_as::<T>(one)?;
         let two_val = self.get_at_as::<T>(two)?;
         self.setmem(one, two_val)?;
@@ -326,50 +996,58 @@ impl Machine {
         Ok(())
     }
 
-    fn push<T : Numerical>(&mut self) -> Result<(), InvokeErr> { // get a value from somewhere in memory and push it to stack
+    fn push<T : Storable>(&mut self) -> Result<(), InvokeErr> { // get a value from somewhere in memory and push it to stack
         let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        let val : T = self.get_at_as(loc);
-        self.push(val).map_err(InvokeErr::MemErr)?;
+        let val : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        self.push_as(val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn pushv<T : Numerical>(&mut self) -> Result<(), InvokeErr> { // push a value to the stack
+    fn pushv<T : Storable>(&mut self) -> Result<(), InvokeErr> { // push a value to the stack
         let val : T = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        self.push(val).map_err(InvokeErr::MemErr)?;
+        self.push_as(val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn swap<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn swap<T : Storable>(&mut self) -> Result<(), InvokeErr> {
         let swap_point_one = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
         let swap_point_two = self.pop_arg::<i64>().map_err(InvokeErr::MemErr)?;
         self.swap_as::<T>(swap_point_one, swap_point_two).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn pop<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn pop<T : Storable>(&mut self) -> Result<(), InvokeErr> {
         self.pop_as::<T>().map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn popm<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn popm<T : Storable>(&mut self) -> Result<(), InvokeErr> {
         let val : T = self.pop_as().map_err(InvokeErr::MemErr)?;
         let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         self.setmem(loc, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn cpy<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn cpy<T : Storable>(&mut self) -> Result<(), InvokeErr> {
         let loc_one : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let loc_two : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        let val : T = self.get_at_as(loc_one);
-        self.setmem(loc_two, val);
+        let val : T = self.get_at_as(loc_one).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc_two, val).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn convert<A : Storable, B : Storable>(&mut self, f : fn(A) -> B) -> Result<(), InvokeErr> { // f2i and i2f: cpy, with f in the middle
+        let from : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let to : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val : A = self.get_at_as(from).map_err(InvokeErr::MemErr)?;
+        self.setmem(to, f(val)).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn cpyv<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn cpyv<T : Storable>(&mut self) -> Result<(), InvokeErr> {
         let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val : T = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        self.setmem(loc, val);
+        self.setmem(loc, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
@@ -378,7 +1056,15 @@ impl Machine {
         let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
         let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
-        let val = val1 + val2;
+        let val = if self.overflow_checked {
+            match val1.checked_add(val2) {
+                Some(val) => val,
+                None => return self.throw(ErrorCode::ARITHMETIC_OVERFLOW)
+            }
+        }
+        else {
+            val1.wrapping_add(val2)
+        };
         self.setmem(loc1, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
@@ -388,7 +1074,15 @@ impl Machine {
         let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
         let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
-        let val = val1 - val2;
+        let val = if self.overflow_checked {
+            match val1.checked_sub(val2) {
+                Some(val) => val,
+                None => return self.throw(ErrorCode::ARITHMETIC_OVERFLOW)
+            }
+        }
+        else {
+            val1.wrapping_sub(val2)
+        };
         self.setmem(loc1, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
@@ -398,7 +1092,15 @@ impl Machine {
         let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
         let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
-        let val = val1 * val2;
+        let val = if self.overflow_checked {
+            match val1.checked_mul(val2) {
+                Some(val) => val,
+                None => return self.throw(ErrorCode::ARITHMETIC_OVERFLOW)
+            }
+        }
+        else {
+            val1.wrapping_mul(val2)
+        };
         self.setmem(loc1, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
@@ -408,144 +1110,3032 @@ impl Machine {
         let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
         let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
-        let val = val1 / val2;
+        if val2 == T::ZERO {
+            return self.throw(ErrorCode::DIVIDE_BY_ZERO);
+        }
+        let val = val1.wrapping_div(val2);
         self.setmem(loc1, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn cmp<T : Numerical + TryFrom<i32>>(&mut self) -> Result<(), InvokeErr> where <T as TryFrom<i32>>::Error : Debug {
-        let reg : u8 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        let regv : T = self.getreg_as(reg);
-        if regv < 0.try_into().unwrap() { // this is infallible
-            self.registers[reg as usize] = 1u64.to_be();
-        }
-        else {
-            self.registers[reg as usize] = 0u64.to_be();
-        }
+    fn saturate<T: Numerical>(&mut self, op : fn(T, T) -> T) -> Result<(), InvokeErr> { // the sat family. op is one of Numerical's
+        // saturating_* methods, and T is unsigned for sat and signed for ssat
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, op(val1, val2)).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn shift<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+    fn count_bits<T: Numerical>(&mut self, op : fn(T) -> u32) -> Result<(), InvokeErr> { // popcnt, clz and ctz: overwrite the value
+        // with op's count, at the same width
         let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
         let val : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
-        let amount : i8 = self.pop_arg().map_err(InvokeErr::MemErr)?;
-        if amount < 0 {
-            self.setmem(loc, val << -amount).map_err(InvokeErr::MemErr)?;
-        }
-        else if amount > 0 {
-            self.setmem(loc, val >> amount).map_err(InvokeErr::MemErr)?;
-        }
+        self.setmem(loc, T::from_count(op(val))).map_err(InvokeErr::MemErr)?;
+        Ok(())
     }
 
-    fn throw(&mut self, code : u8) -> Result<(), InvokeErr> {
-        self.errcode = code;
-        if self.sbm.0 != 0 || self.sbm.1 != 0 {
-            self.stack_pointer = self.sbm.0 + 16;
-            self.exec_pointer = self.sbm.1;
-            // doesn't remove the old sbm from stack; this must be done via checkerr.
-        }
-        else {
-            return Err(InvokeErr::UncaughtThrow);
+    fn rem<T: Numerical>(&mut self) -> Result<(), InvokeErr> { // unsigned T for urem, signed T for srem
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        if val2 == T::ZERO {
+            return self.throw(ErrorCode::DIVIDE_BY_ZERO);
         }
+        let val = val1.wrapping_rem(val2);
+        self.setmem(loc1, val).map_err(InvokeErr::MemErr)?;
         Ok(())
     }
 
-    fn start_mmu(&mut self, pagesize : u32) {
-        // start the builtin mmu.
+    fn or<T: Numerical>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 | val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
     }
-}
 
+    fn and<T: Numerical>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 & val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use super::invoke::*;
-    use super::ir;
-    #[test]
-    fn abi_call() { // a simple abi call written in raw bytecode
-        let image = Image {
-            function_table : HashMap::from([("main".to_string(), 0i64)]),
-            static_table : HashMap::new(),
-            static_section : Vec::from(b"\0\0\0\0\0\0\0\0stdabi\0stest\0STDABI TEST\0"), // the 0 space is to store
-                                                                            // the stdabi rabbit
-            text_section : vec![68, 0, 0, 0, 0, 0, 0, 0, 8, // dock, 8: load the stdabi
-                                69, 0, 0, 0, 0, 0, 0, 0, 15, // loadfun, 15: load the symbol "print" from the stdabi
-                                0 , 0, 0, 0, 0, 0, 0, 0, 21, // pushvl, 21
-                                67, 255, 255, 255, 255, 255, 255, 255, 240, // invokevirtual, -16
-                                70] // exit
-        };
-        let mut machine = Machine::new(1024); // create a 1kb machine
-        machine.mount(&image);
-        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+    fn xor<T: Numerical>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 ^ val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
     }
 
-    #[test]
-    fn ir_test() { // uses the IR compiler to run a program equivalent to above (although with an extra function call)
-        let image = ir::build(r#"
-=message bytes "STDABI TEST\0"
-=stdabi bytes "stdabi\0"
-=stest bytes "stest\0"
-=stest_rabbit word 0        ; reserved space for the print function we're loading from
-                            ; outside the VM
-.printout
-    pushvl 0                ; reserve space for the print function's argument
-    movml -24 2             ; move the argument passed to this function into register 2
-    movrl -8 2              ; copy the value of register 2 into the space we allocated above
-    invokevirtual $stest_rabbit
-    popl 2                  ; unwind the local section of the stack
-    ret
-.main export
-    dock $stdabi
-    loadfun $stest
-    swapl -8 $stest_rabbit  ; shove the rabbit function in the $print_rabbit location
-    pushvl $message         ; push the address of the message we're printing to stack
-    call $printout
-    exit 0
-        "#);
-        let mut machine = Machine::new(1024); // these stupid little 1kb machines are unreasonably fun
-        machine.mount(&image);
-        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+    fn cmp<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.push_ordering(val1.cmp(&val2))
     }
 
-    #[test]
-    fn branch_test() {
-        let image = ir::build(r#"
-=test_success bytes "STDABI TEST\0"
-=test_failure bytes "FAILURE\0"
-=stdabi bytes "stdabi\0"
+    fn cmpv<T : Numerical>(&mut self) -> Result<(), InvokeErr> { // cmp, but the second value is in the instruction
+        let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        self.push_ordering(val1.cmp(&val2))
+    }
+
+    fn push_ordering(&mut self, ordering : Ordering) -> Result<(), InvokeErr> { // the 1-byte result of the cmp family
+        let res : u8 = match ordering {
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+            Ordering::Less => 2
+        };
+        self.push_as(res).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn dupblock(&mut self) -> Result<(), InvokeErr> {
+        let count : u64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        if count > (self.stack_pointer - self.stack_start) as u64 {
+            return self.throw(ErrorCode::OUT_OF_BOUNDS);
+        }
+        self.check_stack(count).map_err(InvokeErr::MemErr)?;
+        let count = count as usize;
+        let dest = self.stackrange(self.stack_pointer, count).map_err(InvokeErr::MemErr)?;
+        self.wrote(dest.start, count);
+        self.memory.copy_within(dest.start - count..dest.start, dest.start);
+        self.stack_pointer += count as i64;
+        Ok(())
+    }
+
+    fn cas<T : Numerical>(&mut self) -> Result<(), InvokeErr> {
+        let loc : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let expected : T = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let new : T = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let current : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        if current == expected {
+            self.setmem(loc, new).map_err(InvokeErr::MemErr)?;
+            self.push_as(1u8).map_err(InvokeErr::MemErr)?;
+        }
+        else {
+            self.push_as(0u8).map_err(InvokeErr::MemErr)?;
+        }
+        Ok(())
+    }
+
+    fn fadd<T : NumericalFloat>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 + val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn fsub<T : NumericalFloat>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 - val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn fmul<T : NumericalFloat>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 * val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn fdiv<T : NumericalFloat>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        self.setmem(loc1, val1 / val2).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn fcmp<T : NumericalFloat>(&mut self) -> Result<(), InvokeErr> {
+        let loc1 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let loc2 : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val1 : T = self.get_at_as(loc1).map_err(InvokeErr::MemErr)?;
+        let val2 : T = self.get_at_as(loc2).map_err(InvokeErr::MemErr)?;
+        let res : u8 = match val1.partial_cmp(&val2) {
+            Some(Ordering::Equal) => 0,
+            Some(Ordering::Greater) => 1,
+            Some(Ordering::Less) => 2,
+            None => 3 // NaN
+        };
+        self.push_as(res).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn shift<T : Numerical>(&mut self) -> Result<(), InvokeErr> { // logical for unsigned T, arithmetic for signed T
+        let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        let amount : i8 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let amount_abs = amount.unsigned_abs() as u32; // -128 can't be negated as an i8
+        if amount < 0 {
+            self.setmem(loc, val.shift_left(amount_abs)).map_err(InvokeErr::MemErr)?;
+        }
+        else if amount > 0 {
+            self.setmem(loc, val.shift_right(amount_abs)).map_err(InvokeErr::MemErr)?;
+        }
+        Ok(())
+    }
+
+    fn rotate<T : Numerical>(&mut self, left : bool) -> Result<(), InvokeErr> { // rotl if left, rotr otherwise. like shift, a negative
+        // amount goes the other way
+        let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let val : T = self.get_at_as(loc).map_err(InvokeErr::MemErr)?;
+        let amount : i8 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let amount_abs = amount.unsigned_abs() as u32;
+        let val = if left == (amount >= 0) { val.rotate_left(amount_abs) } else { val.rotate_right(amount_abs) };
+        self.setmem(loc, val).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn scrub(&mut self) -> Result<(), InvokeErr> {
+        let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let len : u64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let start = self.stackaddr(loc).map_err(InvokeErr::MemErr)?;
+        match (start as u64).checked_add(len) {
+            Some(stop) if stop <= self.end as u64 => {
+                self.wrote(start, len as usize);
+                self.memory[start..stop as usize].fill(0);
+                Ok(())
+            },
+            _ => Err(InvokeErr::MemErr(MemoryErr::SegmentationFault))
+        }
+    }
+
+    fn memcpy(&mut self, overlap_safe : bool) -> Result<(), InvokeErr> { // memmove if overlap_safe, memcpy otherwise
+        let dest : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let src : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len : u64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len = usize::try_from(len).map_err(|_| InvokeErr::MemErr(MemoryErr::SegmentationFault))?;
+        let dest = self.stackrange(dest, len).map_err(InvokeErr::MemErr)?;
+        let src = self.stackrange(src, len).map_err(InvokeErr::MemErr)?;
+        self.wrote(dest.start, len);
+        if overlap_safe {
+            self.memory.copy_within(src, dest.start);
+        }
+        else {
+            for (to, from) in dest.zip(src) { // front to back, one byte at a time, so a forward overlap copies bytes it already wrote
+                self.memory[to] = self.memory[from];
+            }
+        }
+        Ok(())
+    }
+
+    fn memcmp(&mut self) -> Result<(), InvokeErr> {
+        let first : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let second : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len : u64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len = usize::try_from(len).map_err(|_| InvokeErr::MemErr(MemoryErr::SegmentationFault))?;
+        let first = self.stackrange(first, len).map_err(InvokeErr::MemErr)?;
+        let second = self.stackrange(second, len).map_err(InvokeErr::MemErr)?;
+        let res : u8 = match self.memory[first].cmp(&self.memory[second]) {
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+            Ordering::Less => 2
+        };
+        self.push_as(res).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn strlen(&mut self) -> Result<(), InvokeErr> {
+        let addr : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let start = self.stackaddr(addr).map_err(InvokeErr::MemErr)?;
+        let len = self.memory[start..self.end as usize].iter().position(|byte| *byte == 0); // stop at end, not in the padding after it
+        let len = len.ok_or(InvokeErr::MemErr(MemoryErr::SegmentationFault))?;
+        self.push_as(len as u64).map_err(InvokeErr::MemErr)?;
+        Ok(())
+    }
+
+    fn assert_nonzero(&mut self, width : usize) -> Result<(), InvokeErr> { // the assert family. throws 7 if the width bytes at the location
+        // are all 0, and remembers the assertion's id for the host
+        let loc : i64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let id : u64 = self.pop_arg().map_err(InvokeErr::MemErr)?;
+        let pos = self.stackaddr(loc).map_err(InvokeErr::MemErr)?;
+        if self.memory_at(pos, width).map_err(InvokeErr::MemErr)?.iter().all(|byte| *byte == 0) {
+            self.failed_assertion = Some(id);
+            return self.throw(ErrorCode::ASSERTION_FAILED);
+        }
+        Ok(())
+    }
+
+    fn memset(&mut self) -> Result<(), InvokeErr> {
+        let dest : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let val : u8 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len : u64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let len = usize::try_from(len).map_err(|_| InvokeErr::MemErr(MemoryErr::SegmentationFault))?;
+        let dest = self.stackrange(dest, len).map_err(InvokeErr::MemErr)?;
+        self.wrote(dest.start, len);
+        self.memory[dest].fill(val);
+        Ok(())
+    }
+
+    fn throw(&mut self, code : ErrorCode) -> Result<(), InvokeErr> {
+        self.errcode = code.0;
+        self.capture_backtrace();
+        if self.sbm.0 != 0 || self.sbm.1 != 0 {
+            self.stack_pointer = self.sbm.0; // the saved sbm is right below this, which is what checkerr expects to pop
+            self.exec_pointer = self.sbm.1;
+            let sbm = self.sbm.0;
+            self.call_stack.retain(|&(_, _, frame)| frame <= sbm); // calls made inside the SBM scope are unwound with it
+            // doesn't remove the old sbm from stack; this must be done via checkerr.
+        }
+        else {
+            return Err(InvokeErr::UncaughtThrow(code.0));
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod smoke { // runs without std too (cargo test --no-default-features), so no compilers: everything is raw bytecode
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn no_std_smoke_test() { // exit_value_test, by hand
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_table : HashMap::from([("answer".to_string(), 0i64)]),
+            static_section : vec![0; 8],
+            text_section : vec![16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04, 0xD2, // cpyvl 0 1234
+                                73, 0, 0, 0, 0, 0, 0, 0x04, 0xD2], // exit 1234
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(1234)));
+        assert_eq!(machine.read_u64(image.lookup_static("answer").unwrap()), Ok(1234));
+        assert_eq!(image.lookup_static("question"), Err(LookupErr::NoSuchStatic("question".to_string())));
+        assert_eq!(machine.static_addr("answer"), Ok(0));
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use super::invoke::*;
+    use super::ir;
+    #[test]
+    fn abi_call() { // a simple abi call written in raw bytecode
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : Vec::from(b"\0\0\0\0\0\0\0\0stdabi\0stest\0STDABI TEST\0"), // the 0 space is to store
+                                                                            // the stdabi rabbit
+            text_section : vec![130, 0, 0, 0, 0, 0, 0, 0, 8, // dock, 8: load the stdabi
+                                131, 0, 0, 0, 0, 0, 0, 0, 15, // loadfun, 15: load the symbol "stest" from the stdabi
+                                4 , 0, 0, 0, 0, 0, 0, 0, 21, // pushvl, 21
+                                67, 255, 255, 255, 255, 255, 255, 255, 240, // invokevirtual, -16
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024); // create a 1kb machine
+        stdabi::register(&mut machine);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+    }
+
+    const IR_TEST : &str = r#"
+=message bytes "STDABI TEST\0"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+=stest_rabbit word 0        ; reserved space for the print function we're loading from
+                            ; outside the VM
+.printout
+    pushl -16               ; copy the argument passed to this function (it's under the return address)
+    invokevirtual $stest_rabbit ; stest pops the copy itself
+    ret
+.main export
+    dock $stdabi
+    loadfun $stest
+    swapl -8 $stest_rabbit  ; shove the rabbit function in the $stest_rabbit location
+    pushvl $message         ; push the address of the message we're printing to stack
+    call $printout
+    exit 0
+"#;
+
+    #[test]
+    fn ir_test() { // uses the IR compiler to run a program equivalent to above (although with an extra function call)
+        let image = ir::build(IR_TEST).unwrap();
+        let mut machine = Machine::new(1024); // these stupid little 1kb machines are unreasonably fun
+        stdabi::register(&mut machine);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+    }
+
+    #[test]
+    fn machine_debug_test() {
+        let image = ir::build(".main export\n    pushvl 1\n    exit 0").unwrap();
+        let mut machine = Machine::new(1 << 20);
+        stdabi::register(&mut machine);
+        machine.mount(&image).unwrap();
+        machine.invoke(image.lookup("main".to_string())).unwrap();
+        let debug = format!("{:?}", machine);
+        assert!(debug.contains(&format!("stack_pointer: {}", machine.stack_start + 8)));
+        assert!(!machine.rabbits.is_empty()); // stdabi
+        assert!(debug.contains(&format!("externals: {}", machine.rabbits.len())));
+        assert!(debug.contains("mmu_running: false"));
+        assert!(debug.len() < 1000); // and not a megabyte of memory
+    }
+
+    #[test]
+    fn image_summary_test() {
+        let image = ir::build(IR_TEST).unwrap();
+        assert_eq!(image.static_section().len(), 33); // 12 + 7 + 6 bytes of strings, and a word
+        assert_eq!(image.static_section()[..12], *b"STDABI TEST\0");
+        assert_eq!(image.text_section().len(), 81); // printout is 19 bytes, main is 62
+        assert_eq!(image.exported_functions().collect::<Vec<_>>(), [("main", 33 + 19)]);
+        assert_eq!(image.summary(), ImageSummary {
+            static_size : 33,
+            text_size : 81,
+            functions : 2,
+            exported_functions : 1,
+            exported_statics : 0,
+            relocations : Some(6)
+        });
+    }
+
+    const BRANCH_TEST : &str = r#"
+=test_success bytes "STDABI TEST\0"
+=test_failure bytes "FAILURE\0"
+=stdabi bytes "stdabi\0"
 =stest bytes "stest\0"
 =stest_rabbit word 0
+=one byte 1
+=zero byte 0
+
+.success
+    pushvl $test_success
+    invokevirtual $stest_rabbit
+    exit 0
+
+.main export
+    dock $stdabi
+    loadfun $stest
+    swapl -8 $stest_rabbit
+    branch $one $success    ; not 0, so this falls through
+    branch $zero $success
+    pushvl $test_failure
+    invokevirtual $stest_rabbit
+    exit 0
+"#;
+
+    #[test]
+    fn branch_test() {
+        let image = ir::build(BRANCH_TEST).unwrap();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        let image = ir::build(r#"
+.main export
+    pushvb 0
+    branch -1 :taken        ; the byte on top of the stack is 0
+    exit 1
+taken:
+    pushvb 7
+    branch -1 :wrong        ; and now it's 7, so this falls through to the next instruction
+    exit 2
+wrong:
+    exit 3
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(2)));
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 2); // branch doesn't pop the byte it looked at
+    }
+
+    #[test]
+    fn dock_test() {
+        let image = ir::build(r#"
+=stdabi bytes "stdabi\0"
+=nowhere bytes "nowhere\0"
+=stest bytes "stest\0"
+=nothing bytes "nothing\0"
+.main export
+    dock $stdabi
+    setsbm
+    loadfun $nothing        ; not in the library
+    checkerr :missing_fun
+    exit 1
+missing_fun:
+    geterr
+    setsbm
+    dock $nowhere           ; not a library
+    checkerr :missing_lib
+    exit 2
+missing_lib:
+    exit 3
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        let lib = stdabi::register(&mut machine);
+        assert_eq!(machine.lib_rabbit("stdabi"), Some(lib));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(3)));
+        machine.free_rabbit(lib);
+        assert_eq!(machine.lib_rabbit("stdabi"), None);
+
+        let image = ir::build(r#"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+.main export
+    dock $stdabi
+    loadfun $stest
+    dock $stdabi
+    loadfun $stest
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+        let stest = machine.read_u64(machine.stack_start).unwrap() as i64;
+        assert_eq!(machine.read_u64(machine.stack_start + 8), Ok(stest as u64)); // the same rabbit both times
+        let rabbits = machine.rabbits.len();
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.rabbits.len(), rabbits); // and loading it again doesn't grow the table
+        assert!(machine.free_rabbit(stest).is_some());
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0))); // once it's freed, the next loadfun makes a new one
+        assert!(matches!(machine.rabbits.get(&(machine.read_u64(machine.stack_start).unwrap() as i64)), Some(ExtData::Function(_))));
+        assert_eq!(machine.rabbits.len(), rabbits);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedSink(std::rc::Rc<std::cell::RefCell<Vec<u8>>>); // a Vec<u8> the test can still read after handing it to set_output
+
+    impl std::io::Write for SharedSink {
+        fn write(&mut self, buf : &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stdabi_test() {
+        let image = ir::build(r#"
+=hello bytes "hello, world\0"
+=message bytes "STDABI TEST\0"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+.main export
+    dock $stdabi
+    loadfun $stest
+    pushvl $hello
+    invokevirtual -16
+    pushvl $message
+    invokevirtual -16
+    exit 0
+        "#).unwrap();
+        let sink = SharedSink::default();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.set_output(Box::new(sink.clone()));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        assert_eq!(sink.0.borrow().as_slice(), b"hello, world\nSTDABI TEST\n");
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        let image = ir::build(BRANCH_TEST).unwrap();
+        let main = image.lookup("main".to_string());
+        let success = image.static_section.len() as i64; // .success is the first function
+        let sink = SharedSink::default();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.set_output(Box::new(sink.clone()));
+        machine.mount(&image).unwrap();
+        machine.add_breakpoint(success);
+        assert_eq!(machine.run_until_break(main), Ok(BreakOutcome::Hit(success)));
+        assert_eq!(machine.exec_pointer(), success);
+        assert!(sink.0.borrow().is_empty()); // it hasn't printed yet
+        assert_eq!(machine.resume_until_break(), Ok(BreakOutcome::Finished(InvokeResult::StdabiTestSuccess)));
+        assert_eq!(sink.0.borrow().as_slice(), b"STDABI TEST\n");
+        machine.add_breakpoint(main);
+        assert_eq!(machine.run_until_break(main), Ok(BreakOutcome::Hit(main))); // before the first instruction runs
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        assert_eq!(machine.resume_until_break(), Ok(BreakOutcome::Hit(success)));
+        machine.remove_breakpoint(success);
+        machine.remove_breakpoint(main);
+        assert_eq!(machine.run_until_break(main), Ok(BreakOutcome::Finished(InvokeResult::StdabiTestSuccess)));
+    }
+
+    #[test]
+    fn interrupt_test() {
+        let image = ir::build(".main export\ntop:\n    jmp :top").unwrap();
+        let main = image.lookup("main".to_string());
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let interrupt = machine.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            interrupt.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        assert_eq!(machine.invoke(main), Err(InvokeErr::Interrupted)); // it would go forever otherwise
+        interrupter.join().unwrap();
+        assert_eq!(machine.exec_pointer(), main); // left alone, so it can carry on
+        assert_eq!(machine.resume_limited(100), Err(InvokeErr::GasExhausted)); // and the flag was cleared
+        machine.set_interrupt_interval(1);
+        machine.interrupt_handle().store(true, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(machine.invoke_limited(main, 100), Err(InvokeErr::Interrupted)); // looked at before the first instruction
+    }
+
+    #[test]
+    fn coroutine_test() {
+        let image = ir::build(r#"
+=turns byte 0 export
+=left byte 3
+=one byte 1
+.main export
+top:
+    addb $turns $one
+    yield
+    subb $left $one
+    branch $left :done
+    jmp :top
+done:
+    exit 7
+        "#).unwrap();
+        let main = image.lookup("main".to_string());
+        let mut machines = [Machine::new(1024), Machine::new(1024)];
+        for machine in &mut machines {
+            machine.mount(&image).unwrap();
+        }
+        let turns = machines[0].static_addr("turns").unwrap();
+        let [a, b] = &mut machines;
+        let mut coroutines = [a.invoke_resumable(main), b.invoke_resumable(main)];
+        for turn in 1..=3 {
+            for coroutine in &mut coroutines { // round robin: each one gets a turn, and they stay in step
+                assert_eq!(coroutine.resume(1000), Resumption::Yielded);
+                assert_eq!(coroutine.machine().read_bytes(turns, 1), Ok(&[turn][..]));
+            }
+        }
+        for coroutine in &mut coroutines {
+            assert_eq!(coroutine.resume(1000), Resumption::Exited(7));
+        }
+        let mut coroutine = machines[0].invoke_resumable(main);
+        assert_eq!(coroutine.resume(1), Resumption::Yielded); // out of budget, partway to the first yield
+        assert_eq!(coroutine.machine().read_bytes(turns, 1), Ok(&[4][..]));
+        assert_eq!(coroutine.resume(1), Resumption::Yielded); // the yield itself
+        assert_eq!(machines[0].invoke(main), Ok(InvokeResult::Ok(7))); // outside of a coroutine, yield does nothing (left wraps around
+                                                                         // to 255 here, so this takes a while)
+        let mut coroutine = machines[1].invoke_resumable(1 << 20); // past the end of memory
+        assert_eq!(coroutine.resume(10), Resumption::Faulted(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        let image = ir::build(r#"
+=counter word 1 export
+=other word 2
+.main export
+    pushvl 5                ; pushes don't touch statics
+    cpyl -8 $other          ; this writes to a different static
+    cpyl -8 $counter        ; this is the one
+    addl $counter $other    ; and so is this
+    exit 0
+        "#).unwrap();
+        let main = image.lookup("main".to_string());
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        machine.add_watchpoint(machine.static_addr("counter").unwrap(), 8);
+        assert_eq!(machine.run_until_break(main), Ok(BreakOutcome::Watch(main + 26))); // the second cpyl
+        assert_eq!(machine.exec_pointer(), main + 43);
+        assert_eq!(machine.read_u64(0), Ok(5));
+        machine.add_breakpoint(main + 43); // breakpoints still work straight after a watch
+        assert_eq!(machine.resume_until_break(), Ok(BreakOutcome::Hit(main + 43)));
+        assert_eq!(machine.resume_until_break(), Ok(BreakOutcome::Watch(main + 43)));
+        assert_eq!(machine.read_u64(0), Ok(10));
+        assert_eq!(machine.resume_until_break(), Ok(BreakOutcome::Finished(InvokeResult::Ok(0))));
+        machine.remove_watchpoint(0);
+        machine.remove_breakpoint(main + 43);
+        machine.reset();
+        assert_eq!(machine.run_until_break(main), Ok(BreakOutcome::Finished(InvokeResult::Ok(0))));
+    }
+
+    #[test]
+    fn output_test() { // anything other than the test string just gets printed, and the program keeps going
+        let image = ir::build(r#"
+=first bytes "first line\0"
+=second bytes "second line\0"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+.main export
+    dock $stdabi
+    loadfun $stest
+    pushvl $first
+    invokevirtual -16
+    pushvl $second
+    invokevirtual -16
+    exit 4
+        "#).unwrap();
+        let sink = SharedSink::default();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.set_output(Box::new(sink.clone()));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(4)));
+        assert_eq!(sink.0.borrow().as_slice(), b"first line\nsecond line\n");
+    }
+
+    const EXIT_VALUE_TEST : &str = r#"
+.main export
+        exit 1234
+"#;
+
+    #[test]
+    fn exit_value_test() {
+        let image = ir::build(EXIT_VALUE_TEST).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(1234)));
+    }
+
+    #[test]
+    fn run_test() {
+        let image = ir::build(EXIT_VALUE_TEST).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.run("main"), Ok(1234));
+        assert_eq!(machine.run("nope"), Err(RunError::NoSuchFunction("nope".to_string())));
+        let image = ir::build(".main export\n    throw 8\n.stuck export\n    jmp -9").unwrap();
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.run("main"), Err(RunError::Aborted(8)));
+        machine.interrupt_handle().store(true, core::sync::atomic::Ordering::Relaxed);
+        assert_eq!(machine.run("stuck"), Err(RunError::Invoke(InvokeErr::Interrupted)));
+        assert_eq!(RunError::Aborted(8).to_string(), "uncaught error 8 (division by zero)");
+    }
+
+    #[test]
+    fn scrub_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![65, 0, 0, 0, 0, 0, 0, 0, 18, // call 18: call the function below
+                                73, 0, 0, 0, 0, 0, 0, 0, 0, // exit 0
+                                4, 0, 0, 0, 0, 222, 173, 190, 239, // pushvl 0xDEADBEEF: a "secret" local
+                                84, 255, 255, 255, 255, 255, 255, 255, 248, 0, 0, 0, 0, 0, 0, 0, 8, // scrub -8 8: wipe it
+                                20, // popl
+                                66], // ret
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        let local = machine.stack_start as usize + 8; // the local sat right above the return address
+        assert_eq!(machine.memory[local..local + 8], [0u8; 8]); // the next call to reuse this stack space sees nothing
+    }
+
+    #[test]
+    fn updstck_test() {
+        let image = ir::build(r#"
+.main export
+    updstck 32              ; four longs of locals
+    cpyvl -32 11            ; the first one
+    cpyvl -8 22             ; the last one
+    updstck -32             ; and drop them again
+    exit 0
+.underflow export
+    updstck -100000
+    exit 1
+.overflow export
+    setsbm
+    updstck 100000
+    checkerr :overflowed
+    exit 1
+overflowed:
+    exit 2
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        assert_eq!(machine.read_u64(machine.stack_start), Ok(11));
+        assert_eq!(machine.read_u64(machine.stack_start + 24), Ok(22));
+        assert_eq!(machine.invoke(image.lookup("underflow".to_string())), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+        assert_eq!(machine.invoke(image.lookup("overflow".to_string())), Ok(InvokeResult::Ok(2))); // thrown as 5, so it can be caught
+    }
+
+    fn run_raw(static_section : Vec<u8>, text_section : Vec<u8>) -> Machine { // run raw bytecode starting at the top of the text section
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section,
+            text_section,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        machine
+    }
+
+    #[test]
+    fn shift_test() {
+        let shift = |op : u8, val : u64, amount : i8| { // shift a u64 stored at address 0 and read it back
+            let machine = run_raw(val.to_be_bytes().to_vec(), vec![op, 0, 0, 0, 0, 0, 0, 0, 0, amount as u8, // shift 0 amount
+                                                                   73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+            u64::from_be_bytes(machine.memory[0..8].try_into().unwrap())
+        };
+        assert_eq!(shift(58, 0xF0, 0), 0xF0);
+        assert_eq!(shift(58, 0xF0, 4), 0xF);
+        assert_eq!(shift(58, 0xF0, -4), 0xF00); // negative amounts shift left
+        assert_eq!(shift(58, u64::MAX, 64), 0); // shifting by the width zeroes rather than panicking
+        assert_eq!(shift(58, u64::MAX, -64), 0);
+        assert_eq!(shift(58, u64::MAX, 127), 0);
+        assert_eq!(shift(58, u64::MAX, -128), 0);
+        assert_eq!(shift(58, (-16i64) as u64, 2), ((-16i64) as u64) >> 2); // shift is always logical
+        assert_eq!(shift(85, (-16i64) as u64, 2), (-4i64) as u64); // ashift sign-extends
+        assert_eq!(shift(85, (-16i64) as u64, 0), (-16i64) as u64);
+        assert_eq!(shift(85, (-16i64) as u64, -2), (-64i64) as u64);
+        assert_eq!(shift(85, (-16i64) as u64, 64), (-1i64) as u64);
+        assert_eq!(shift(85, 16, 64), 0);
+    }
+
+    #[test]
+    fn timeout_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![63, 255, 255, 255, 255, 255, 255, 255, 247], // jmp -9: jump back to this same instruction forever
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let start = std::time::Instant::now();
+        assert_eq!(machine.invoke_timeout(image.lookup("main".to_string()), std::time::Duration::from_millis(20)), Ok(InvokeResult::TimedOut));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(20));
+    }
+
+    #[test]
+    fn gas_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![7, 1, // pushvb 1
+                                63, 255, 255, 255, 255, 255, 255, 255, 247, // jmp -9: loop forever
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0, which we'll never get to on our own
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        assert_eq!(machine.invoke_limited(main, 0), Err(InvokeErr::GasExhausted));
+        assert_eq!(machine.exec_pointer(), main);
+        assert_eq!(machine.invoke_limited(main, 1000), Err(InvokeErr::GasExhausted)); // the push, then 999 jumps
+        assert_eq!(machine.exec_pointer(), main + 2);
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 1); // the push happened exactly once
+        machine.set_exec_pointer(main + 11); // skip the loop
+        assert_eq!(machine.resume_limited(1), Ok(InvokeResult::Ok(0)));
+    }
+
+    fn sum_loop(n : u64) -> Image { // add up n - 1 + n - 2 + ... + 1 into the u64 at address 16. ends by throwing 8 when the counter hits 0.
+        let mut static_section = n.to_be_bytes().to_vec(); // 0: counter
+        static_section.extend(1u64.to_be_bytes()); // 8: one
+        static_section.extend([0; 16]); // 16: total, 24: scratch
+        Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section,
+            text_section : vec![32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // subl 0 8
+                                16, 0, 0, 0, 0, 0, 0, 0, 24, 0, 0, 0, 0, 0, 0, 0, 1, // cpyvl 24 1
+                                40, 0, 0, 0, 0, 0, 0, 0, 24, 0, 0, 0, 0, 0, 0, 0, 0, // divl 24 0: throws once the counter is 0
+                                28, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, // addl 16 0
+                                63, 255, 255, 255, 255, 255, 255, 255, 179], // jmp -77: back to the top
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compile_test() {
+        let image = sum_loop(100);
+        let mut interpreted = Machine::new(1024);
+        interpreted.mount(&image).unwrap();
+        assert_eq!(interpreted.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        let mut compiled = Machine::new(1024);
+        assert_eq!(image.compile_to_closure()(&mut compiled, &[]), Ok(InvokeResult::Aborted(8)));
+        assert_eq!(interpreted.read_u64(16), Ok(4950));
+        assert_eq!(compiled.read_u64(16), Ok(4950));
+        assert_eq!(compiled.exec_pointer(), interpreted.exec_pointer());
+        assert_eq!(compiled.stack_pointer(), interpreted.stack_pointer());
+        let image = ir::build(BRANCH_TEST).unwrap(); // a program that mostly falls back to step
+        let mut interpreted = Machine::new(1024);
+        stdabi::register(&mut interpreted);
+        interpreted.mount(&image).unwrap();
+        assert_eq!(interpreted.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        let mut compiled = Machine::new(1024);
+        stdabi::register(&mut compiled);
+        assert_eq!(image.compile_to_closure()(&mut compiled, &[]), Ok(InvokeResult::StdabiTestSuccess));
+        assert_eq!(compiled.memory, interpreted.memory);
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![73, 0, 0, 0, 0, 0, 0, 0, 42], // exit 42
+            ..Default::default()
+        };
+        let mut run = image.compile_to_closure();
+        let mut machine = Machine::new(1024);
+        assert_eq!(run(&mut machine, &[1, 2]), Ok(InvokeResult::Ok(42)));
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 16); // the arguments were pushed
+        assert_eq!(machine.read_u64(machine.stack_start + 8), Ok(2));
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Ok(42))); // and the closure can be run again
+        let _ = ir::build(".main export\n    jmp 9223372036854775807").unwrap().compile_to_closure(); // its target overflows: left to step
+    }
+
+    #[test]
+    #[ignore] // a benchmark, not a test: cargo test --release compile_benchmark -- --ignored --nocapture
+    fn compile_benchmark() {
+        let image = sum_loop(10_000_000);
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let start = std::time::Instant::now();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        let interpreted = start.elapsed();
+        let mut run = image.compile_to_closure();
+        let start = std::time::Instant::now();
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Aborted(8)));
+        let compiled = start.elapsed();
+        println!("invoke: {:?}, compile_to_closure: {:?}", interpreted, compiled);
+    }
+
+    #[test]
+    #[ignore] // a benchmark, not a test: cargo test --release arithmetic_benchmark -- --ignored --nocapture
+    fn arithmetic_benchmark() {
+        let image = sum_loop(10_000_000);
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let mut best = std::time::Duration::MAX;
+        for _ in 0..5 { // best of 5, to keep the noise down
+            machine.reset();
+            let start = std::time::Instant::now();
+            assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+            best = best.min(start.elapsed());
+        }
+        println!("50M instructions: {:?}", best);
+    }
+
+    #[test]
+    fn negative_exec_test() {
+        let image = sum_loop(10);
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(-8), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault))); // not 8 bytes below the stack pointer
+    }
+
+    #[test]
+    fn insn_cache_test() {
+        let image = sum_loop(1000);
+        let main = image.lookup("main".to_string());
+        let mut plain = Machine::new(1024);
+        plain.mount(&image).unwrap();
+        let mut cached = Machine::new(1024);
+        cached.set_insn_cache(true);
+        cached.mount(&image).unwrap();
+        assert_eq!(cached.invoke(main), plain.invoke(main));
+        assert_eq!(cached.memory, plain.memory);
+        cached.reset();
+        assert_eq!(cached.invoke(main), Ok(InvokeResult::Aborted(8))); // again, with everything already decoded
+        assert_eq!(cached.read_u64(16), plain.read_u64(16));
+        let image = ir::build(r#"
+=new byte 9
+.main export
+    exit 0
+.patch export
+    cpyb $new 9     ; the last byte of main's exit code
+    exit 1
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.set_insn_cache(true);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0))); // main's exit is in the cache now
+        assert_eq!(machine.invoke(image.lookup("patch".to_string())), Ok(InvokeResult::Ok(1)));
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(9))); // so the write had to drop it
+        machine.write_bytes(9, &[3]).unwrap(); // writes from the host count too
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(3)));
+        machine.mount(&image).unwrap(); // and so does mounting over it
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+    }
+
+    #[test]
+    #[ignore] // a benchmark, not a test: cargo test --release insn_cache_benchmark -- --ignored --nocapture
+    fn insn_cache_benchmark() {
+        let image = sum_loop(1_000_000);
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let start = std::time::Instant::now();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        let uncached = start.elapsed();
+        machine.set_insn_cache(true);
+        machine.reset();
+        let start = std::time::Instant::now();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        println!("without the cache: {:?}, with it: {:?}", uncached, start.elapsed());
+    }
+
+    #[test]
+    fn opcode_stats_test() {
+        let image = ir::build(".main export\n    pushvl 1\n    pushvb 2\n    pushvl 3\n    exit 0").unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        machine.invoke(main).unwrap();
+        assert_eq!(machine.opcode_stats(), [0; 256]); // off by default
+        machine.enable_opcode_stats();
+        machine.invoke(main).unwrap();
+        machine.invoke(main).unwrap(); // only the last run counts
+        let stats = machine.opcode_stats();
+        assert_eq!((stats[4], stats[7], stats[73]), (2, 1, 1));
+        assert_eq!(stats.iter().sum::<u64>(), 4);
+        let image = sum_loop(3);
+        machine.mount(&image).unwrap();
+        machine.set_insn_cache(true); // compiled instructions get counted too
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        let stats = machine.opcode_stats();
+        assert_eq!((stats[32], stats[16], stats[40], stats[28], stats[63]), (3, 3, 3, 2, 2));
+        let image = ir::build(".main export\n    pushvl 1\n    pushvl 2\n    addl -16 -8\n    exit 3").unwrap();
+        let mut run = image.compile_to_closure(); // and so do closure runs, which start counting over each time
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Ok(3)));
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Ok(3)));
+        let stats = machine.opcode_stats();
+        assert_eq!((stats[4], stats[28], stats[73]), (2, 1, 1));
+        assert_eq!(stats.iter().sum::<u64>(), 4);
+        machine.disable_opcode_stats();
+        assert_eq!(machine.opcode_stats(), [0; 256]);
+    }
+
+    #[test]
+    fn trace_test() {
+        let image = ir::build(EXIT_VALUE_TEST).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let log = seen.clone();
+        machine.set_trace(move |exec, op, stack| log.borrow_mut().push((exec, op, stack)));
+        let main = image.lookup("main".to_string());
+        let _ = machine.invoke(main);
+        assert_eq!(*seen.borrow(), vec![(main, image.text_section[0], machine.stack_start)]);
+        let image = sum_loop(3);
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = count.clone();
+        machine.set_trace(move |_, _, _| counter.set(counter.get() + 1));
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8)));
+        assert_eq!(count.get(), 13); // two full trips around the loop, then sub, cpyv, and the div that throws
+        machine.clear_trace();
+        machine.mount(&image).unwrap();
+        let _ = machine.invoke(image.lookup("main".to_string()));
+        assert_eq!(count.get(), 13);
+    }
+
+    #[test]
+    fn verify_test() {
+        assert_eq!(sum_loop(3).verify(), Ok(()));
+        let image = |text_section : Vec<u8>| Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0; 8],
+            text_section,
+            ..Default::default()
+        };
+        assert_eq!(image(vec![65, 0, 0, 0, 0, 0, 0, 0, 17, // call 17: the exit below (the static section is 8 bytes)
+                              73, 0, 0, 0, 0, 0, 0, 0, 0]).verify(), Ok(())); // exit 0
+        assert_eq!(image(vec![73, 0, 0, 0, 0, 0, 0, 0, 0, // exit 0
+                              4, 0, 0, 0]).verify(), Err(VerifyErr::Truncated(9))); // pushvl, with half of its argument missing
+        assert_eq!(image(vec![65, 0, 0, 0, 0, 0, 0, 4, 0, // call 1024: way past the end of the image
+                              73, 0, 0, 0, 0, 0, 0, 0, 0]).verify(), Err(VerifyErr::BadTarget(0, 1024)));
+        assert_eq!(image(vec![65, 0, 0, 0, 0, 0, 0, 0, 18, // call 18: the middle of the exit
+                              73, 0, 0, 0, 0, 0, 0, 0, 0]).verify(), Err(VerifyErr::BadTarget(0, 18)));
+        assert_eq!(image(vec![63, 255, 255, 255, 255, 255, 255, 255, 247]).verify(), Ok(())); // jmp -9: itself
+        assert_eq!(image(vec![255]).verify(), Err(VerifyErr::UnknownOpcode(0, 255)));
+        let [a, b, c, d, e, f, g, h] = i64::MAX.to_be_bytes();
+        assert_eq!(image(vec![63, a, b, c, d, e, f, g, h]).verify(), Err(VerifyErr::BadTarget(0, i64::MAX))); // jmp i64::MAX overflows
+        let [a, b, c, d, e, f, g, h] = i64::MIN.to_be_bytes();
+        assert_eq!(image(vec![63, a, b, c, d, e, f, g, h]).verify(), Err(VerifyErr::BadTarget(0, i64::MIN + 17))); // jmp i64::MIN
+        assert_eq!(image(vec![65, a, b, c, d, e, f, g, h]).verify(), Err(VerifyErr::BadTarget(0, i64::MIN))); // call i64::MIN
+    }
+
+    #[test]
+    fn uniqueid_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![127, 127, 127, // uniqueid x3
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        let mut last = None;
+        for _ in 0..2 { // the counter carries on into the second invoke rather than starting over
+            assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+            for slot in 0..3 {
+                let id = machine.read_u64(machine.stack_start + slot * 8).unwrap();
+                assert!(last.is_none_or(|last| id > last));
+                last = Some(id);
+            }
+        }
+    }
+
+    #[test]
+    fn disassemble_test() {
+        let image = ir::build(r#"
+=counter word 3
+.helper
+    pushvl $counter
+    ret
+.main export
+    cpyvb $counter 1
+    pushvs 500
+    call $helper
+    setsbm
+    exit 0
+        "#).unwrap();
+        let main = image.disassemble_function("main").unwrap();
+        let mnemonics : Vec<&str> = main.lines().skip(1).map(|line| line.split_whitespace().next().unwrap()).collect();
+        assert_eq!(mnemonics, ["cpyvb", "pushvs", "call", "setsbm", "exit"]);
+        assert!(main.contains("pushvs 500\n"));
+        assert!(image.disassemble().ends_with(&main)); // .helper comes first
+        assert!(image.disassemble().starts_with("    pushvl 0\n")); // $counter is private, so it comes out as a plain address
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_table : HashMap::from([("answer".to_string(), 0i64)]),
+            static_section : vec![0; 8],
+            text_section : vec![67, 0, 0, 0, 0, 0, 0, 0, 0, // invokevirtual 0: the public static
+                                67, 0, 0, 0, 0, 0, 0, 0, 3, // invokevirtual 3: nothing in particular
+                                255, // not an instruction
+                                66], // ret
+            ..Default::default()
+        };
+        assert_eq!(image.disassemble(), ".main export\n    invokevirtual $answer\n    invokevirtual 3\n    ; unknown opcode 255\n    ret\n");
+    }
+
+    #[test]
+    fn avc_comment_test() {
+        assert_eq!(avc::check(r#"
+// a comment before anything
+long varname = 80 // after a static
+// between top-level items
+long main = ( // inside a parameter list
+    long a, // after a parameter
+    long b, // trailing comma
+) { // before the body
+    // inside the body
+    print( // inside an argument list
+        "Test message!", // after an argument
+        80, // and a trailing comma
+    ) // after a call
+    print() // an empty call
+} // after the body
+export function main // after an export
+// at the very end"#), Ok(()));
+        assert_eq!(avc::check("// nothing but a comment"), Ok(()));
+        assert!(avc::check("long x = 5 ; not a comment").is_err());
+    }
+
+    #[test]
+    fn curfn_test() {
+        let image = ir::build(r#"
+.first
+    ret
+.main export
+    curfn
+    ret
+        "#).unwrap();
+        assert_eq!(image.function_extents, vec![(0, 1), (1, 3)]);
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 9i64)]),
+            function_extents : vec![(0, 9), (9, 19)],
+            static_section : vec![0; 4],
+            text_section : vec![73, 0, 0, 0, 0, 0, 0, 0, 1, // exit 1: some other function
+                                128, // curfn
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(machine.stack_start), Ok(9));
+        assert_eq!(machine.read_u64(machine.stack_start + 8), Ok(19));
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![128], // curfn, without any extents to go on
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(2)));
+    }
+
+    #[test]
+    fn abort_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64), ("fine".to_string(), 2i64)]),
+            text_section : vec![70, 2, // throw 2, with no SBM to catch it
+                                73, 0, 0, 0, 0, 0, 0, 0, 5], // exit 5
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.exit_code(), None);
+        assert_eq!(machine.invoke(image.lookup("fine".to_string())), Ok(InvokeResult::Ok(5)));
+        assert_eq!(machine.exit_code(), Some(5));
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(2)));
+        assert_eq!(machine.exit_code(), None); // aborting isn't exiting
+        assert_eq!(image.compile_to_closure()(&mut machine, &[]), Ok(InvokeResult::Aborted(2)));
+    }
+
+    #[test]
+    fn backtrace_test() {
+        let image = ir::build(r#"
+.inner export
+    pushl 100000            ; way off the end of memory
+    ret
+.middle export
+    call $inner
+    ret
+.outer export
+    call $middle
+    ret
+.main export
+    call $outer
+    exit 0
+.caught export
+    setsbm
+    call $outer
+    checkerr :handler
+    exit 1
+handler:
+    throw 2                 ; nothing left to catch this
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        assert_eq!(machine.invoke(main), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+        let backtrace = machine.last_backtrace();
+        assert_eq!(backtrace.len(), 3);
+        let names : Vec<&str> = backtrace.iter().map(|frame| frame.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["inner", "middle", "outer"]);
+        assert_eq!(backtrace[0].function, image.lookup("inner".to_string()));
+        assert_eq!(backtrace[2].return_addr, main + 9); // right after main's call
+        assert_eq!(machine.invoke(image.lookup("caught".to_string())), Ok(InvokeResult::Aborted(2)));
+        assert!(machine.last_backtrace().is_empty()); // the fault unwound all three calls, so the second throw came from the top
+    }
+
+    #[test]
+    fn ir_error_test() {
+        let errors = ir::build(".main export\n  frobnicate 3").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("frobnicate"));
+        assert_eq!(errors[0].span.start, 15); // the start of the bad line
+        let errors = ir::build(".main export\n  call $nowhere").unwrap_err();
+        assert!(errors[0].message.contains("$nowhere"));
+        let errors = ir::build(".main export\n  ret 5").unwrap_err();
+        assert!(errors[0].message.contains("ret takes 0 arguments"));
+        assert!(ir::build("=thing word \"not a word\"").unwrap_err()[0].message.contains("word"));
+        assert!(!ir::build("?").unwrap_err().is_empty()); // plain old syntax errors
+    }
+
+    #[test]
+    fn rabbit_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0; 8], // the rabbit goes here
+            text_section : vec![67, 0, 0, 0, 0, 0, 0, 0, 0, // invokevirtual 0
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        let rabbit = machine.alloc_rabbit(ExtData::Function(Box::new(|machine : &mut Machine| {
+            machine.push_as(42i64).unwrap();
+        })));
+        assert!(rabbit >= machine.end); // never inside memory
+        machine.write_u64(0, rabbit as u64).unwrap();
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(-8), Ok(42));
+        let other = machine.alloc_rabbit(ExtData::Function(Box::new(|_ : &mut Machine| {})));
+        assert_ne!(other, rabbit);
+        assert!(matches!(machine.free_rabbit(rabbit), Some(ExtData::Function(_))));
+        assert!(machine.free_rabbit(rabbit).is_none());
+        assert_eq!(machine.invoke(main), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault))); // it's just an address past the end now
+        let reused = machine.alloc_rabbit(ExtData::Function(Box::new(|machine : &mut Machine| {
+            machine.push_as(7i64).unwrap();
+        })));
+        assert_eq!(reused, rabbit); // freed addresses get handed out again
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(-8), Ok(7));
+        let named = machine.register_ext("named", |_| {});
+        assert_eq!(machine.ext_rabbit("named"), Some(named));
+        machine.free_rabbit(named);
+        assert_eq!(machine.ext_rabbit("named"), None);
+    }
+
+    #[test]
+    fn invoke_catching_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0; 8], // the rabbit goes here
+            text_section : vec![67, 0, 0, 0, 0, 0, 0, 0, 0, // invokevirtual 0
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let rabbit = machine.register_ext("broken", |_| panic!("broken external"));
+        machine.write_u64(0, rabbit as u64).unwrap();
+        let main = image.lookup("main".to_string());
+        match machine.invoke_catching(main) {
+            Err(InvokeErr::InterpreterPanic(message)) => assert_eq!(message, "broken external"),
+            other => panic!("expected a caught panic, got {:?}", other)
+        }
+        assert_eq!(machine.ext_rabbit("broken"), Some(rabbit)); // the external went back in the rabbit table
+        assert!(matches!(machine.invoke_catching(main), Err(InvokeErr::InterpreterPanic(_)))); // so it runs (and panics) again
+        assert!(matches!(machine.free_rabbit(rabbit), Some(ExtData::Function(_))));
+        let rabbit = machine.register_ext("fixed", |_| {});
+        machine.write_u64(0, rabbit as u64).unwrap();
+        assert_eq!(machine.invoke_catching(main), Ok(InvokeResult::Ok(0))); // the machine still works afterwards
+    }
+
+    #[test]
+    fn mock_ext_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0; 8], // the rabbit goes here
+            text_section : vec![4, 0, 0, 0, 0, 0, 0, 0, 1, // pushvl 1
+                                4, 0, 0, 0, 0, 0, 0, 0, 2, // pushvl 2
+                                67, 0, 0, 0, 0, 0, 0, 0, 0, // invokevirtual 0
+                                4, 0, 0, 0, 0, 0, 0, 0, 3, // pushvl 3
+                                4, 0, 0, 0, 0, 0, 0, 0, 4, // pushvl 4
+                                67, 0, 0, 0, 0, 0, 0, 0, 0, // invokevirtual 0
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let rabbit = machine.mock_ext("add", 2, vec![100, 200]);
+        assert_eq!(machine.ext_rabbit("add"), Some(rabbit));
+        machine.write_u64(0, rabbit as u64).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.ext_call_log("add"), [vec![1, 2], vec![3, 4]]);
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 16); // just the two responses
+        assert_eq!(machine.read_u64(machine.stack_start), Ok(100));
+        assert_eq!(machine.read_u64(machine.stack_start + 8), Ok(200));
+        assert!(machine.ext_call_log("nothing").is_empty());
+    }
+
+    #[test]
+    fn jmp_test() {
+        let image = ir::build(r#"
+.main export
+    jmp 9                   ; over the exit
+    exit 1
+    jmp 0                   ; goes nowhere
+    exit 2
+.spin export
+    jmp -9                  ; back to itself, forever
+.far export
+    jmp 9223372036854775807 ; overflows
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(2)));
+        let spin = image.lookup("spin".to_string());
+        assert_eq!(machine.invoke_limited(spin, 100), Err(InvokeErr::GasExhausted));
+        assert_eq!(machine.exec_pointer(), spin);
+        let far = image.lookup("far".to_string());
+        assert_eq!(machine.invoke(far), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+        machine.set_insn_cache(true);
+        assert_eq!(machine.invoke(far), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+    }
+
+    #[test]
+    fn ir_static_export_test() {
+        let image = ir::build(r#"
+=hidden word 1
+=counter word 5 export  ; public
+=name bytes "x\0" export
+.main export
+    exit 0
+        "#).unwrap();
+        assert_eq!(image.static_table, HashMap::from([("counter".to_string(), 8i64), ("name".to_string(), 16i64)]));
+        assert_eq!(image.lookup_static("counter"), Ok(8));
+        assert!(image.lookup_static("hidden").is_err());
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.read_u64(machine.static_addr("counter").unwrap()), Ok(5));
+        assert!(ir::build("=x word 1 exported").is_err());
+    }
+
+    #[test]
+    fn label_test() {
+        let image = ir::build(r#"
+=counter word 3
+=one word 1
+.main export
+    branch 15 :top  ; the low byte of $one, so never taken, but it shows labels can be forward references
+top:                ; count down forever
+    subl $counter $one
+    jmp :top
+        "#).unwrap();
+        assert_eq!(image.text_section[9..17], 33i64.to_be_bytes()); // branch is absolute: 16 bytes of static, then the 17 byte branch
+        assert_eq!(image.text_section[35..43], (-26i64).to_be_bytes()); // jmp is relative to the next instruction: back over itself and the sub
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke_limited(image.lookup("main".to_string()), 7), Err(InvokeErr::GasExhausted)); // the branch, then 3 times around
+        assert_eq!(machine.read_u64(0), Ok(0));
+        assert!(ir::build(".main export\n  jmp :nowhere").unwrap_err()[0].message.contains(":nowhere"));
+        assert!(ir::build(".main export\nhere:\n  ret\nhere:").unwrap_err()[0].message.contains("twice"));
+    }
+
+    #[test]
+    fn ir_opcode_test() {
+        let assemble = |line : &str| ir::build(&format!("=thing word 0\n.main export\n    {}", line)).unwrap().text_section;
+        assert_eq!(assemble("pushl $thing"), [0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(assemble("pushvi 7"), [5, 0, 0, 0, 7]);
+        assert_eq!(assemble("pushvs 258"), [6, 1, 2]);
+        assert_eq!(assemble("swapl -8 $thing")[0], 8);
+        assert_eq!(assemble("swapb -8 $thing"), [11, 255, 255, 255, 255, 255, 255, 255, 248, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(assemble("cpyvi -4 9"), [17, 255, 255, 255, 255, 255, 255, 255, 252, 0, 0, 0, 9]);
+        assert_eq!(assemble("pops"), [22]);
+        assert_eq!(assemble("popml $thing"), [24, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(assemble("addi $thing -8")[0], 29);
+        assert_eq!(assemble("subs $thing -8")[0], 34);
+        assert_eq!(assemble("mulb $thing -8")[0], 39);
+        assert_eq!(assemble("divl $thing -8")[0], 40);
+        assert_eq!(assemble("cmpl $thing -8")[0], 44);
+        assert_eq!(assemble("vand $thing 15"), [57, 0, 0, 0, 0, 0, 0, 0, 0, 15]);
+        assert_eq!(assemble("shifti $thing -3")[9], 253);
+        assert_eq!(assemble("xorl $thing -8")[0], 107);
+        assert_eq!(assemble("branch 3 $thing"), [64, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(assemble("setsbm"), [69]);
+        assert_eq!(assemble("checkerr $thing"), [71, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(assemble("throw 2"), [70, 2]);
+        assert_eq!(assemble("geterr"), [72]);
+        assert_eq!(assemble("exit 1"), [73, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(assemble("startmmu 64"), [74, 0, 0, 0, 64]);
+        assert_eq!(assemble("alloc"), [75]);
+        assert_eq!(assemble("dealloc"), [76]);
+        assert_eq!(assemble("maketbl"), [78]);
+        assert_eq!(assemble("sremb $thing -1")[0], 98);
+        assert_eq!(assemble("fcmpi $thing -4")[0], 121);
+        assert!(ir::build(".main export\n    popl 2").unwrap_err()[0].message.contains("popl takes 0 arguments"));
+        let image = ir::build(".main export\n    pushvi 70000\n    exit 0").unwrap(); // and they actually run
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        let stack = machine.stack_start as usize;
+        assert_eq!(machine.memory[stack..stack + 4], 70000u32.to_be_bytes());
+    }
+
+    fn documented_opcodes() -> HashMap<String, u8> { // every mnemonic in the opcode table at the top of this file, expanded out:
+        // `12 -> 19. cpy(v)[l, i, s, b]` is cpyl, cpyi, cpys, cpyb, cpyvl, cpyvi, cpyvs and cpyvb
+        let mut opcodes = HashMap::new();
+        let table = include_str!("lib.rs").split("*/").nth(1).unwrap();
+        for line in table.lines() {
+            let Some((range, rest)) = line.strip_prefix("    ").and_then(|line| line.split_once(". ")) else { continue };
+            let (first, last) = range.split_once(" -> ").unwrap_or((range, range));
+            let (Ok(first), Ok(last)) = (first.parse::<u8>(), last.parse::<u8>()) else { continue };
+            let names = rest.split_once(": ").unwrap().0;
+            let mut expanded = vec![];
+            for family in names.split("], ") {
+                let Some((base, widths)) = family.trim_end_matches(']').split_once('[') else {
+                    expanded.push(family.to_string());
+                    continue;
+                };
+                let bases = match base.strip_suffix("(v)") {
+                    Some(base) => vec![base.to_string(), format!("{}v", base)],
+                    None => vec![base.to_string()]
+                };
+                for base in bases {
+                    expanded.extend(widths.split(", ").map(|width| format!("{}{}", base, width)));
+                }
+            }
+            assert_eq!(expanded.len(), (last - first) as usize + 1, "{} doesn't cover {}", names, range);
+            opcodes.extend(expanded.into_iter().zip(first..=last));
+        }
+        opcodes
+    }
+
+    #[test]
+    fn ir_mnemonic_test() { // every IR mnemonic assembles to the opcode lib.rs documents for it, at the length step expects
+        let documented = documented_opcodes();
+        for (mnemonic, opcode, args) in ir::OPERATIONS {
+            assert_eq!(documented.get(*mnemonic), Some(opcode), "{}", mnemonic);
+            let line = format!(".main export\n    {} {}", mnemonic, vec!["0"; args.len()].join(" "));
+            let text = ir::build(&line).unwrap().text_section;
+            assert_eq!(text[0], *opcode, "{}", mnemonic);
+            if let Some(len) = Machine::instruction_len(*opcode) { // the table opcodes aren't in yet
+                assert_eq!(text.len(), len, "{}", mnemonic);
+            }
+        }
+        assert_eq!(documented["cpyvb"], 19);
+        assert_eq!(documented["ssatmulb"], 159);
+        assert_eq!(documented.len(), 205); // everything up to sdivb
+    }
+
+    #[test]
+    fn cmpv_test() {
+        let cmpv = |width : &str, tp : &str, value : i64, immediate : &str| { // compare a static holding value with immediate
+            let image = ir::build(&format!("=x {} {} export\n.main export\n    cmpv{} $x {}\n    popmb $x\n    exit 0", tp, value, width, immediate)).unwrap();
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+            assert_eq!(machine.stack_pointer(), machine.stack_start); // the result was the only thing pushed
+            machine.read_bytes(0, 1).unwrap()[0]
+        };
+        for (width, tp) in [("l", "word"), ("i", "int"), ("s", "short"), ("b", "byte")] {
+            assert_eq!(cmpv(width, tp, 100, "100"), 0);
+            assert_eq!(cmpv(width, tp, 101, "100"), 1);
+            assert_eq!(cmpv(width, tp, 99, "100"), 2);
+        }
+        assert_eq!(cmpv("l", "word", 1, "-1"), 2); // unsigned: -1 is u64::MAX
+        assert_eq!(cmpv("l", "word", -1, "1"), 1);
+        assert_eq!(cmpv("l", "word", -1, "-1"), 0);
+        assert_eq!(cmpv("b", "byte", 200, "100"), 1); // only the low byte is compared, unsigned
+        assert_eq!(ir::build(".main export\n    cmpvi -4 7").unwrap().text_section, [49, 255, 255, 255, 255, 255, 255, 255, 252, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn assert_test() {
+        let image = ir::build(r#"
+=yes word 256 export
+=half short 256 export
+=no byte 0 export
+.pass export
+    assertl $yes 1
+    asserts $half 2
+    assertb $half 3         ; big-endian, so this is the 1
+    exit 0
+.fail export
+    assertl $yes 4
+    assertb $no 5
+    exit 0
+.check
+    assertb $no 6
+    ret
+.caught export
+    setsbm
+    call $check
+    checkerr :handler
+    exit 1
+handler:
+    exit 2
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("pass".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.failed_assertion(), None);
+        assert_eq!(machine.invoke(image.lookup("fail".to_string())), Ok(InvokeResult::Aborted(7)));
+        assert_eq!(machine.failed_assertion(), Some(5));
+        assert_eq!(machine.invoke(image.lookup("caught".to_string())), Ok(InvokeResult::Ok(2))); // catchable like any other throw
+        assert_eq!(machine.failed_assertion(), Some(6));
+        assert_eq!(describe_error_code(7), "assertion failed");
+    }
+
+    #[test]
+    fn sbm_introspection_test() {
+        let image = ir::build(r#"
+.look
+    getsbm
+    exit 0
+.peek export
+    setsbm
+    call $look
+    checkerr :peek_handler
+peek_handler:
+    exit 1
+.clear
+    clearsbm
+    throw 3
+.cleared export
+    setsbm
+    call $clear
+    checkerr :cleared_handler
+    exit 1
+cleared_handler:
+    exit 2
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("peek".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.sbm.0, machine.stack_start + 16); // just above the (0, 0) setsbm saved
+        let top = machine.stack_pointer();
+        assert_eq!(machine.read_u64(top - 16), Ok(machine.sbm.0 as u64));
+        assert_eq!(machine.read_u64(top - 8), Ok(machine.sbm.1 as u64));
+        assert_eq!(machine.read_bytes(machine.sbm.1, 1), Ok(&[71][..])); // the checkerr after the call
+        assert_eq!(machine.invoke(image.lookup("cleared".to_string())), Ok(InvokeResult::Aborted(3))); // the handler never runs
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 24); // the saved sbm and the return address are still there
+        assert_eq!(machine.sbm, (0, 0));
+    }
+
+    #[test]
+    fn ir_swap_test() { // swapl really exchanges the two longs, rather than pushing (ir_test and branch_test count on this too)
+        let image = ir::build(r#"
+=x word 5 export
+=y byte 1 export
+.main export
+    pushvl 9
+    swapl -8 $x
+    pushvb 7
+    swapb $y -1
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(machine.static_addr("x").unwrap()), Ok(9));
+        assert_eq!(machine.read_bytes(machine.static_addr("y").unwrap(), 1), Ok(&[7][..]));
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 9); // nothing extra got pushed
+        assert_eq!(machine.read_u64(machine.stack_start), Ok(5));
+        assert_eq!(machine.read_bytes(machine.stack_start + 8, 1), Ok(&[1][..]));
+    }
+
+    #[test]
+    fn ir_literal_test() {
+        let image = ir::build(r#"
+=a byte 200b
+=b byte -1b
+=c short 258s
+=d int 1000i
+=e word 5l
+=f signedword -2l
+=g float 1.5f
+=h double -2.0d
+=i byte 7
+        "#).unwrap();
+        let mut expected = vec![200, 255, 1, 2, 0, 0, 3, 232];
+        expected.extend(5u64.to_be_bytes());
+        expected.extend((-2i64).to_be_bytes());
+        expected.extend(1.5f32.to_be_bytes());
+        expected.extend((-2.0f64).to_be_bytes());
+        expected.push(7);
+        assert_eq!(image.static_section, expected);
+        assert_eq!(ir::build(".main export\n    pushvs 7s").unwrap().text_section, [6, 0, 7]);
+        assert!(ir::build("=x byte 300b").unwrap_err()[0].message.contains("300b doesn't fit in a byte"));
+        assert!(ir::build("=x byte 300").unwrap_err()[0].message.contains("300 doesn't fit in a byte")); // no more silent truncation
+        assert!(ir::build("=x short 70000s").is_err());
+        assert!(ir::build("=x int 42b").is_err()); // suffixed literals only cast to their own width
+        assert!(ir::build("=x double 3.14").unwrap_err()[0].message.contains("suffix"));
+        assert!(ir::build(".main export\n    pushvb 256").is_err());
+    }
+
+    #[test]
+    fn string_escape_test() {
+        assert_eq!(ir::build(r#"=data bytes "\x00\xff\x7e""#).unwrap().static_section, [0x00, 0xff, 0x7e]);
+        assert_eq!(ir::build(r#"=data bytes "a\t\r\n\\\"\0\u{e9}\u{1F600}""#).unwrap().static_section,
+                   [b"a\t\r\n\\\"\0".as_slice(), "\u{e9}\u{1F600}".as_bytes()].concat());
+        assert!(ir::build(r#"=data bytes "\u{D800}""#).unwrap_err()[0].message.contains("unicode")); // a lone surrogate
+        assert!(ir::build(r#"=data bytes "\xf""#).is_err());
+        assert!(avc::check(r#"long data = "\x00\xff\u{e9}\t""#).is_ok());
+    }
+
+    #[test]
+    fn memory_fault_test() {
+        assert_eq!(ErrorCode::from(MemoryErr::SegmentationFault), ErrorCode(1));
+        assert_eq!(MemoryErr::try_from(ErrorCode::from(MemoryErr::OutOfMemory)), Ok(MemoryErr::OutOfMemory));
+        assert_eq!(MemoryErr::try_from(ErrorCode::DIVIDE_BY_ZERO), Err(ErrorCode(8)));
+        assert_eq!(describe_error_code(ErrorCode::DIVIDE_BY_ZERO.0), "division by zero");
+        let run = |text_section : Vec<u8>| {
+            let image = Image {
+                function_table : HashMap::from([("main".to_string(), 0i64)]),
+                text_section,
+                ..Default::default()
+            };
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            let result = machine.invoke(image.lookup("main".to_string()));
+            (result, machine)
+        };
+        let (result, machine) = run(vec![69, // 0: setsbm
+                                         0, 0, 0, 0, 0, 0, 1, 0, 0, // 1: pushl 65536: way out of bounds
+                                         71, 0, 0, 0, 0, 0, 0, 0, 28, // 10: checkerr 28
+                                         73, 0, 0, 0, 0, 0, 0, 0, 1, // 19: exit 1
+                                         72, // 28: geterr
+                                         73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(result, Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.memory[machine.stack_start as usize], 1);
+        let (result, _) = run(vec![0, 0, 0, 0, 0, 0, 1, 0, 0, // pushl 65536, with no sbm
+                                   73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(result, Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+    }
+
+    #[test]
+    fn stack_overflow_test() {
+        let image = avc::build(r#"
+long depth;
+export depth
+
+fn recurse() {
+    depth = depth + 1;
+    recurse()
+}
+
+fn main() {
+    recurse()
+}
+        "#).unwrap();
+        let mut machine = Machine::new(4096);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        assert_eq!(machine.invoke(main), Err(InvokeErr::MemErr(MemoryErr::StackOverflow))); // not a segfault
+        assert!(machine.stack_pointer() <= machine.end);
+        let mut machine = Machine::new(4096);
+        machine.mount(&image).unwrap();
+        machine.set_stack_size(80); // every call takes 8 bytes, and adding 1 to depth takes another 16 on top
+        assert_eq!(machine.invoke(main), Err(InvokeErr::MemErr(MemoryErr::StackOverflow)));
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 80);
+        assert_eq!(machine.read_u64(image.static_table["depth"]), Ok(8));
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![69, // 0: setsbm
+                                4, 0, 0, 0, 0, 0, 0, 0, 1, // 1: pushvl 1: there's only room for the sbm
+                                71, 0, 0, 0, 0, 0, 0, 0, 28, // 10: checkerr 28
+                                73, 0, 0, 0, 0, 0, 0, 0, 1, // 19: exit 1
+                                72, // 28: geterr
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        machine.set_stack_size(16);
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.memory[machine.stack_start as usize], 5); // caught like any other memory error
+        assert_eq!(describe_error_code(5), "stack overflow");
+    }
+
+    #[test]
+    fn catch_fault_test() {
+        for op in [12, 28] { // cpyl goes through step, but addl gets compiled by compile_to_closure
+            let mut text = vec![69, // 0: setsbm
+                                65, 0, 0, 0, 0, 0, 0, 0, 38, // 1: call 38
+                                71, 0, 0, 0, 0, 0, 0, 0, 28, // 10: checkerr 28
+                                73, 0, 0, 0, 0, 0, 0, 0, 1, // 19: exit 1
+                                72, // 28: geterr
+                                73, 0, 0, 0, 0, 0, 0, 0, 0, // 29: exit 0
+                                op, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]; // 38: op 65536 0: way out of bounds
+            text.push(66); // 55: ret
+            let image = Image {
+                function_table : HashMap::from([("main".to_string(), 0i64)]),
+                text_section : text,
+                ..Default::default()
+            };
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+            assert_eq!(machine.memory[machine.stack_start as usize], 1); // geterr: out-of-bounds memory access
+            assert_eq!(machine.stack_pointer(), machine.stack_start + 1);
+            let mut compiled = Machine::new(1024);
+            assert_eq!(image.compile_to_closure()(&mut compiled, &[]), Ok(InvokeResult::Ok(0)));
+            assert_eq!(compiled.memory, machine.memory);
+        }
+    }
+
+    #[test]
+    fn checkerr_test() {
+        let image = ir::build(r#"
+.fail
+    throw 3
+.body
+    setsbm
+    call $fail
+    checkerr :first_caught
+    exit 1
+first_caught:
+    call $fail              ; no scope of its own, so this goes to main's
+    exit 2
+.main export
+    setsbm
+    call $body
+    checkerr :outer_caught
+    exit 3
+outer_caught:
+    exit 4
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        // the first throw is caught by body's checkerr, which has to put main's sbm back so the second throw lands in main
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(4)));
+        assert_eq!(machine.sbm, (0, 0)); // both scopes got popped
+        assert_eq!(machine.stack_pointer, machine.stack_start);
+    }
+
+    #[test]
+    fn geterr_test() {
+        let image = ir::build(r#"
+.fail
+    throw 7
+.main export
+    setsbm
+    call $fail
+    checkerr :caught
+    exit 1
+caught:
+    geterr                  ; checkerr kept the code for this
+    geterr                  ; and so did the first geterr
+    pushvb 0                ; but anything else clears it
+    geterr
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_bytes(machine.stack_start, 4), Ok(&[7, 7, 0, 0][..]));
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 4);
+    }
+
+    #[test]
+    fn checkerrcode_test() {
+        let run = |code : u8| { // an outer scope that catches anything around an inner scope that only catches 1, then throw code
+            let mut text = vec![69, 63]; // 0: setsbm; jmp to the inner scope
+            text.extend(18i64.to_be_bytes());
+            text.push(71); // 10: checkerr to the outer handler
+            text.extend(68i64.to_be_bytes());
+            text.push(73); // 19: exit 1, nothing was thrown
+            text.extend(1i64.to_be_bytes());
+            text.extend([69, 63]); // 28: setsbm; jmp to the throw
+            text.extend(19i64.to_be_bytes());
+            text.push(129); // 38: checkerrcode to the inner handler, only for 1
+            text.extend(59i64.to_be_bytes());
+            text.push(1);
+            text.push(73); // 48: exit 2, nothing was thrown
+            text.extend(2i64.to_be_bytes());
+            text.extend([70, code]); // 57: throw
+            text.push(73); // 59: inner handler, exit 10
+            text.extend(10i64.to_be_bytes());
+            text.push(73); // 68: outer handler, exit 20
+            text.extend(20i64.to_be_bytes());
+            let image = Image {
+                function_table : HashMap::from([("main".to_string(), 0i64)]),
+                text_section : text,
+                ..Default::default()
+            };
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            let result = machine.invoke(image.lookup("main".to_string()));
+            (result, machine)
+        };
+        let (result, machine) = run(1);
+        assert_eq!(result, Ok(InvokeResult::Ok(10)));
+        assert_eq!(machine.stack_pointer, machine.stack_start + 16); // the outer scope's sbm is still on the stack
+        let (result, machine) = run(2);
+        assert_eq!(result, Ok(InvokeResult::Ok(20)));
+        assert_eq!(machine.sbm, (0, 0)); // both scopes got popped
+        assert_eq!(machine.stack_pointer, machine.stack_start);
+    }
+
+    #[test]
+    fn xor_test() {
+        let machine = run_raw(vec![0b10110010], vec![90, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, // vxor 0 0xFF
+                                                     90, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, // vxor 0 0xFF
+                                                     73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(machine.memory[0], 0b10110010); // xoring with the same thing twice is a no-op
+        let machine = run_raw(vec![0b10110010, 0xFF], vec![89, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, // bxor 0 1
+                                                           73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(machine.memory[0], 0b01001101);
+        assert_eq!(machine.memory[1], 0xFF);
+    }
+
+    #[test]
+    fn not_test() {
+        let machine = run_raw(vec![0, 7, 0xAA], vec![53, 0, 0, 0, 0, 0, 0, 0, 0, // not 0
+                                                     53, 0, 0, 0, 0, 0, 0, 0, 1, // not 1
+                                                     73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(machine.memory[..3], [1, 0, 0xAA]); // only the one byte gets written
+    }
+
+    #[test]
+    fn overflow_test() {
+        let text = vec![31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, // addb 0 1
+                        73, 0, 0, 0, 0, 0, 0, 0, 0]; // exit 0
+        let machine = run_raw(vec![255, 1], text.clone());
+        assert_eq!(machine.memory[0], 0); // wraps by default
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![255, 1],
+            text_section : text,
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.set_overflow_checked(true);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(6)));
+        assert_eq!(machine.memory[0], 255);
+        let mut run = image.compile_to_closure(); // compiled arithmetic has to notice too
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Aborted(6)));
+        machine.set_overflow_checked(false);
+        assert_eq!(run(&mut machine, &[]), Ok(InvokeResult::Ok(0)));
+        assert_eq!(describe_error_code(6), "arithmetic overflow");
+    }
+
+    #[test]
+    fn saturate_test() {
+        let image = ir::build(r#"
+=a byte 200
+=b byte 100
+=c byte -120
+=d byte 50
+=e byte 100
+=f byte 200
+=g word 4294967296
+.main export
+    satsubb $e $f
+    ssatsubb $c $d
+    satmull $g $g
+    sataddb $a $b
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.set_overflow_checked(true); // doesn't matter to sat
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.memory[..6], [255, 100, -128i8 as u8, 50, 0, 200]);
+        assert_eq!(machine.read_u64(6), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn bit_count_test() {
+        let image = ir::build(r#"
+=a byte 255
+=b short 1
+=c short 1
+=d word 0
+=e int 40
+.main export
+    popcntb $a
+    clzs $b
+    ctzs $c
+    clzl $d
+    ctzi $e
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.memory[0], 8);
+        assert_eq!(machine.read_bytes(1, 4), Ok(&[0, 15, 0, 0][..]));
+        assert_eq!(machine.read_u64(5), Ok(64)); // 0 is all leading zeros
+        assert_eq!(machine.read_bytes(13, 4), Ok(&[0, 0, 0, 3][..]));
+    }
+
+    #[test]
+    fn storable_round_trip_test() { // every width goes in and comes out the same, touching only its own bytes
+        fn round_trip<T : Storable + core::fmt::Debug>(machine : &mut Machine, val : T, bytes : &[u8]) {
+            machine.memory[..16].fill(0xaa);
+            machine.setmem(4, val).unwrap();
+            assert_eq!(&machine.memory[4..4 + T::BYTE_COUNT], bytes);
+            assert!(machine.memory[..4].iter().chain(&machine.memory[4 + T::BYTE_COUNT..16]).all(|byte| *byte == 0xaa));
+            assert_eq!(machine.get_at_as::<T>(4), Ok(val));
+        }
+        let mut machine = Machine::new(64);
+        round_trip(&mut machine, 0x0102030405060708u64, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        round_trip(&mut machine, 0x01020304u32, &[1, 2, 3, 4]);
+        round_trip(&mut machine, 0x0102u16, &[1, 2]);
+        round_trip(&mut machine, 0x01u8, &[1]);
+        round_trip(&mut machine, -2i64, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+        round_trip(&mut machine, -2i32, &[0xff, 0xff, 0xff, 0xfe]);
+        round_trip(&mut machine, -2i16, &[0xff, 0xfe]);
+        round_trip(&mut machine, -2i8, &[0xfe]);
+        round_trip(&mut machine, -1.5f64, &(-1.5f64).to_bits().to_be_bytes());
+        round_trip(&mut machine, -1.5f32, &(-1.5f32).to_bits().to_be_bytes());
+        assert_eq!(<u8 as Numerical>::from_count(8), 8);
+        assert_eq!(<i8 as Numerical>::from_count(64), 64);
+    }
+
+    #[test]
+    #[ignore] // a benchmark, not a test: cargo test --release access_benchmark -- --ignored --nocapture
+    fn access_benchmark() {
+        let mut machine = Machine::new(1024);
+        let start = std::time::Instant::now();
+        let mut total = 0u64;
+        for i in 0..10_000_000i64 {
+            let addr = i % 512;
+            machine.setmem(addr, i as u32).unwrap();
+            total = total.wrapping_add(machine.get_at_as::<u64>(addr).unwrap());
+        }
+        println!("10M writes and reads: {:?} (checksum {})", start.elapsed(), total);
+    }
+
+    #[test]
+    fn rotate_test() {
+        let image = ir::build(r#"
+=a byte 129         ; 0b10000001
+=b byte 129
+=c byte 129
+=d word 1
+.main export
+    rotlb $a 1
+    rotlb $b 8              ; the full width: nothing happens
+    rotrb $c -1             ; same as rotlb 1
+    rotrl $d 1
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.memory[..3], [0b00000011, 0b10000001, 0b00000011]);
+        assert_eq!(machine.read_u64(3), Ok(1 << 63));
+    }
+
+    #[test]
+    fn memcpy_test() {
+        let image = ir::build(r#"
+=a bytes "ab"
+=a_tail bytes "cdef\0\0"
+=b bytes "ab"
+=b_tail bytes "cdef\0\0"
+=c bytes "abcdef"
+.main export
+    pushvl 6
+    pushvl $a
+    pushvl $a_tail
+    memmove                 ; copy forward over itself
+    pushvl 6
+    pushvl $b
+    pushvl $b_tail
+    memcpy                  ; same thing, but memcpy doesn't handle the overlap
+    pushvl 3
+    pushvb 120
+    pushvl $c
+    memset
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_bytes(0, 8), Ok(&b"ababcdef"[..]));
+        assert_eq!(machine.read_bytes(8, 8), Ok(&b"abababab"[..]));
+        assert_eq!(machine.read_bytes(16, 6), Ok(&b"xxxdef"[..]));
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        let image = ir::build(r#"
+=c bytes "abcdef"
+.main export
+    pushvl 2000             ; runs off the end of memory
+    pushvb 120
+    pushvl $c
+    memset
+    exit 0
+        "#).unwrap();
+        machine.remount(&image);
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Err(InvokeErr::MemErr(MemoryErr::SegmentationFault)));
+        assert_eq!(machine.read_bytes(0, 6), Ok(&b"abcdef"[..])); // nothing got written
+    }
+
+    #[test]
+    fn memcmp_test() {
+        let image = ir::build(r#"
+=abc bytes "abc"
+=abd bytes "abd"
+=abc2 bytes "abc"
+.main export
+    pushvl 3
+    pushvl $abd
+    pushvl $abc
+    memcmp                  ; "abc" vs "abd"
+    pushvl 3
+    pushvl $abc2
+    pushvl $abc
+    memcmp                  ; equal
+    pushvl 3
+    pushvl $abc
+    pushvl $abd
+    memcmp
+    pushvl 2
+    pushvl $abd
+    pushvl $abc
+    memcmp                  ; only the "ab"s
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_bytes(machine.stack_start, 4), Ok(&[2, 0, 1, 0][..]));
+    }
+
+    #[test]
+    fn strlen_test() {
+        let image = ir::build(r#"
+=message bytes "STDABI TEST\0"
+=ptr word 0                 ; filled in by the host
+=length word 0
+.measure
+    pushl $ptr
+    strlen
+    popml $length
+    ret
+.main export
+    pushvl $message
+    strlen
+    popml $length
+    setsbm
+    call $measure
+    checkerr :caught
+    exit 0
+caught:
+    exit 1
+        "#).unwrap();
+        let mut machine = Machine::new(256);
+        machine.mount(&image).unwrap();
+        let (ptr, length) = (12, 20); // the ir doesn't make statics public, but they're right after the message
+        machine.write_u64(ptr, 0).unwrap(); // the message
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(length), Ok(11));
+        let unterminated = machine.end - 16;
+        machine.write_bytes(unterminated, &[b'a'; 16]).unwrap(); // right up to the end of memory
+        machine.write_u64(ptr, unterminated as u64).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(1)));
+    }
+
+    #[test]
+    fn read_cstr_test() {
+        let mut machine = Machine::new(64);
+        machine.write_bytes(0, "héllo\0".as_bytes()).unwrap();
+        assert_eq!(machine.read_cstr(0, 64), Ok("héllo".to_string()));
+        assert_eq!(machine.read_cstr(0, 6), Ok("héllo".to_string())); // six bytes, since é is two
+        assert_eq!(machine.read_cstr(0, 5), Err(CStrErr::Unterminated));
+        machine.write_bytes(16, &[b'a', 0xff, b'b', 0]).unwrap();
+        assert_eq!(machine.read_cstr(16, 64), Err(CStrErr::NotUtf8(vec![b'a', 0xff, b'b'])));
+        assert_eq!(machine.read_str(16), Err(InvokeErr::StringProcessingError));
+        let unterminated = machine.end - 8;
+        machine.write_bytes(unterminated, &[b'a'; 8]).unwrap(); // right up to the end of memory
+        assert_eq!(machine.read_cstr(unterminated, 64), Err(CStrErr::MemErr(MemoryErr::SegmentationFault)));
+        assert_eq!(machine.read_cstr(unterminated, 4), Err(CStrErr::Unterminated));
+        assert_eq!(machine.read_cstr(machine.end, 64), Err(CStrErr::MemErr(MemoryErr::SegmentationFault)));
+    }
+
+    #[test]
+    fn scmp_test() {
+        let mut statics = (-5i64).to_be_bytes().to_vec();
+        statics.extend(3i64.to_be_bytes());
+        let machine = run_raw(statics, vec![44, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // cmpl 0 8
+                                            132, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // scmpl 0 8
+                                            135, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 15, // scmpb 7 15
+                                            132, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, // scmpl 8 0
+                                            73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        let start = machine.stack_start as usize;
+        assert_eq!(machine.memory[start..start + 4], [1, 2, 2, 1]); // unsigned, -5 looks huge. signed, it's less than 3, in any width
+    }
+
+    #[test]
+    fn rem_test() {
+        let rem = |op : u8, a : i64, b : i64| { // a rem b, as 64 bit values at addresses 0 and 8
+            let mut statics = a.to_be_bytes().to_vec();
+            statics.extend(b.to_be_bytes());
+            let machine = run_raw(statics, vec![op, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // [u, s]reml 0 8
+                                                73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+            i64::from_be_bytes(machine.memory[0..8].try_into().unwrap())
+        };
+        assert_eq!(rem(95, -7, 3), -1); // srem takes the sign of the dividend
+        assert_eq!(rem(95, 7, -3), 1);
+        assert_eq!(rem(95, i64::MIN, -1), 0);
+        assert_eq!(rem(91, -7, 3) as u64, (-7i64 as u64) % 3); // urem treats the same bits as unsigned
+        assert_eq!(rem(91, 7, 3), 1);
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0, 7, 0, 0],
+            text_section : vec![93, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, // urems 0 2
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(8))); // division by zero
+    }
+
+    #[test]
+    fn sdiv_test() {
+        let div = |op : u8, a : i64, b : i64| { // a div b, as 64 bit values at addresses 0 and 8
+            let mut statics = a.to_be_bytes().to_vec();
+            statics.extend(b.to_be_bytes());
+            let machine = run_raw(statics, vec![op, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // [s]divl 0 8
+                                                73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+            i64::from_be_bytes(machine.memory[0..8].try_into().unwrap())
+        };
+        assert_eq!(div(201, -7, 2), -3); // sdiv truncates toward zero
+        assert_eq!(div(201, 7, -2), -3);
+        assert_eq!(div(201, -8, -2), 4);
+        assert_eq!(div(201, i64::MIN, -1), i64::MIN);
+        assert_eq!(div(40, -7, 2) as u64, (-7i64 as u64) / 2); // div treats the same bits as unsigned
+        let image = ir::build(r#"
+=a byte -7 export
+=b byte 2 export
+=z byte 0 export
+.main export
+    sdivb $a $b
+    exit 0
+.zero export
+    sdivb $a $z
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_bytes(image.lookup_static("a").unwrap(), 1).unwrap()[0] as i8, -3);
+        assert_eq!(machine.invoke(image.lookup("zero".to_string())), Ok(InvokeResult::Aborted(8)));
+    }
+
+    #[test]
+    fn bitwise_test() {
+        let image = ir::build(r#"
+=value word 1311768467463790320     ; 0x123456789ABCDEF0
+=mask word 4294967295               ; 0x00000000FFFFFFFF
+.main export
+    andl $value $mask
+        "#).unwrap();
+        let mut text = image.text_section.clone();
+        text.extend([73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        let machine = run_raw(image.static_section.clone(), text);
+        assert_eq!(u64::from_be_bytes(machine.memory[0..8].try_into().unwrap()), 0x9ABCDEF0);
+        let machine = run_raw(vec![0b1100, 0b1010], vec![102, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, // orb 0 1
+                                                         73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(machine.memory[0], 0b1110);
+        let machine = run_raw(vec![0, 0, 0xFF, 0x0F, 0, 0, 0x0F, 0x0F], vec![108, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, // xori 0 4
+                                                                             73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(machine.memory[0..4], [0, 0, 0xF0, 0]);
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![1, 2, 3, 4, 5, 6, 7, 8],
+            text_section : vec![16, 0, 0, 0, 0, 0, 0, 0, 0, 0xDE, 0xAD, 0xBE, 0xEF, 0xDE, 0xAD, 0xBE, 0xEF, // cpyvl 0 0xDEADBEEFDEADBEEF
+                                4, 0, 0, 0, 0, 0, 0, 0, 42, // pushvl 42
+                                127, // uniqueid
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        machine.set_exec_pointer(main);
+        let snap = machine.snapshot();
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(0), Ok(0xDEADBEEFDEADBEEF));
+        let id = machine.read_u64(machine.stack_pointer() - 8).unwrap();
+        machine.restore(&snap);
+        assert_eq!(machine.memory, snap.memory()); // byte-identical, scribbles and stack included
+        assert_eq!(machine.read_bytes(0, 8), Ok(&[1, 2, 3, 4, 5, 6, 7, 8][..]));
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        assert_eq!(machine.exec_pointer(), main);
+        assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0))); // a replay does exactly the same thing
+        assert_eq!(machine.read_u64(machine.stack_pointer() - 8), Ok(id));
+    }
+
+    #[test]
+    fn from_memory_test() {
+        let mut memory = vec![0u8; 256];
+        memory[..8].copy_from_slice(&42u64.to_be_bytes()); // a static
+        memory[8..26].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, // pushl 0: copy the static onto the stack
+                                       73, 0, 0, 0, 0, 0, 0, 0, 7]); // exit 7
+        memory[34] = 0xAB; // just above where the push lands
+        let mut machine = Machine::from_memory(memory, 8, 26);
+        assert_eq!(machine.invoke(8), Ok(InvokeResult::Ok(7)));
+        assert_eq!(machine.read_u64(26), Ok(42));
+        assert_eq!(machine.memory[34], 0xAB); // nothing got zeroed
+        machine.setmem::<u64>(0, 3).unwrap();
+        machine.reset(); // the statics come back as they were handed over
+        assert_eq!(machine.read_u64(0), Ok(42));
+        let copy = Machine::from_memory(machine.snapshot().memory().to_vec(), 8, 26);
+        assert_eq!(copy.memory, machine.memory);
+    }
+
+    #[test]
+    fn image_bytes_test() {
+        let image = ir::build(BRANCH_TEST).unwrap();
+        let bytes = image.to_bytes();
+        let loaded = Image::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert_eq!(loaded.text_section, image.text_section);
+        assert_eq!(loaded.relocations, image.relocations);
+        assert_eq!(loaded.debug_info, image.debug_info);
+        assert_eq!(loaded.frame_sizes, image.frame_sizes);
+        assert_eq!(loaded.lookup_function("main"), image.lookup_function("main"));
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.mount(&loaded).unwrap();
+        assert_eq!(machine.invoke(loaded.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        assert_eq!(Image::from_bytes(b"ELF").unwrap_err(), ImageErr::BadMagic);
+        assert_eq!(Image::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), ImageErr::Truncated);
+        assert_eq!(Image::from_bytes(&[&bytes[..], &[0]].concat()).unwrap_err(), ImageErr::TrailingData);
+    }
+
+    #[test]
+    fn source_location_test() {
+        let image = ir::build(r#"
+=total word 0
+.main export
+    pushvl 3
+    addl -8 $total  ; fine
+    cpyl 100000 $total ; way past the end of memory
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        machine.exec_pointer = image.lookup("main".to_string());
+        let faulted_at = loop { // the fault leaves the exec pointer partway through the instruction, so keep track of where it started
+            let at = machine.exec_pointer;
+            match machine.step() {
+                Ok(StepResult::Continue) => {},
+                Err(InvokeErr::MemErr(_)) => break at,
+                result => panic!("expected a fault, got {:?}", result)
+            }
+        };
+        assert_eq!(image.source_location(faulted_at - machine.text_start), Some((6, 5)));
+        assert_eq!(image.source_location(0), Some((4, 5))); // pushvl 3
+        assert_eq!(image.source_location(image.text_section.len() as i64), None);
+        let avc = avc::build("fn main() {\n    long x = 1;\n    if x {\n        @exit(3)\n    }\n}").unwrap();
+        let main = avc.function_table["main"];
+        let exit = (main as usize..avc.text_section.len()).find(|&offset| avc.text_section[offset..].starts_with(&[73, 0, 0, 0, 0, 0, 0, 0, 3]))
+            .unwrap() as i64; // exit 3
+        assert_eq!(avc.source_location(exit), Some((4, 9))); // the line in the if body, not the if
+        assert_eq!(avc.source_location(exit - 1), Some((3, 8))); // the end of the condition
+        let hand_built = Image { debug_info : None, ..image.clone() };
+        assert_eq!(hand_built.source_location(0), None);
+    }
+
+    #[test]
+    fn relocation_test() {
+        let image = ir::build(r#"
+=a word 1
+=b word 2
+=a_ptr word $a
+.f
+    ret
+.main export
+    addl $a $b
+    call $f
+    jmp :end   ; relative, so it doesn't need relocating
+end:
+    cpyl $a_ptr 8
+    exit 0
+        "#).unwrap();
+        let relocation = |section, offset, target| Relocation { section, offset, target : RelocTarget::Section(target) };
+        assert_eq!(image.relocations(), Some(&[
+            relocation(Section::Static, 16, Section::Static), // =a_ptr word $a
+            relocation(Section::Text, 2, Section::Static), // addl $a
+            relocation(Section::Text, 10, Section::Static), // $b
+            relocation(Section::Text, 19, Section::Text), // call $f
+            relocation(Section::Text, 37, Section::Static) // cpyl $a_ptr
+        ][..]));
+        let word = |bytes : &[u8], offset : usize| i64::from_be_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        assert_eq!(word(&image.static_section, 16), 0);
+        assert_eq!(word(&image.text_section, 10), 8);
+        assert_eq!(word(&image.text_section, 19), 24); // f is the first thing after the statics
+        assert_eq!(word(&image.text_section, 37), 16);
+        assert_eq!(avc::build("fn main() { @exit(0) }").unwrap().relocations(), None);
+    }
+
+    #[test]
+    fn sandbox_test() {
+        let config = || SandboxConfig { max_instructions : 1000, stack_size : Some(256), max_alloc : Some(1024), allow_externals : false };
+        let run = |program : &str, config : SandboxConfig| { // a new machine every time, like two hosts would have
+            let image = ir::build(program).unwrap();
+            let mut machine = Machine::new(8192);
+            stdabi::register(&mut machine);
+            machine.set_stack_size(4096);
+            machine.mount(&image).unwrap();
+            let result = machine.invoke_sandboxed(image.lookup("main".to_string()), config);
+            assert_eq!(machine.stack_size, Some(4096)); // put back afterwards
+            (result, machine.snapshot().memory().to_vec())
+        };
+        let programs = [
+            (".main export\n    startmmu 128\n    pushvl 1000\n    alloc\n    pushvl 7\n    mull -8 -16\n    exit 3", Ok(InvokeResult::Ok(3))),
+            (".main export\ntop:\n    jmp :top", Err(InvokeErr::GasExhausted)),
+            (".main export\ntop:\n    pushvl 1\n    jmp :top", Err(InvokeErr::StackLimitExceeded)),
+            (".grow\ntop:\n    pushvl 1\n    jmp :top\n.main export\n    setsbm\n    call $grow\n    checkerr :caught\n    exit 0\ncaught:\n    exit 5",
+             Err(InvokeErr::StackLimitExceeded)), // no handler gets to ignore it
+            (".main export\n    startmmu 128\n    pushvl 1025\n    alloc\n    exit 0", Err(InvokeErr::AllocLimitExceeded)),
+            (r#"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+.main export
+    dock $stdabi
+    loadfun $stest
+    invokevirtual -8
+    exit 0
+            "#, Err(InvokeErr::ExternalForbidden))
+        ];
+        for (program, expected) in programs {
+            let (result, memory) = run(program, config());
+            assert_eq!(result, expected);
+            assert_eq!(run(program, config()), (result, memory)); // and exactly the same again
+        }
+        let (result, _) = run(".main export\ntop:\n    pushvl 1\n    jmp :top", SandboxConfig { max_instructions : 1200, stack_size : None, ..config() });
+        assert_eq!(result, Err(InvokeErr::GasExhausted)); // 600 longs: past the machine's own stack size, which isn't used
+    }
+
+    #[test]
+    fn usable_memory_test() {
+        assert_eq!(ACCESS_PADDING, 8); // u64, i64 and f64
+        for capacity in [8, 64, 1000, 65536] {
+            let machine = Machine::new(capacity);
+            assert_eq!(machine.end, capacity as i64 - ACCESS_PADDING);
+            assert_eq!(machine.usable_memory(), machine.end);
+        }
+        let mut machine = Machine::new(64);
+        assert_eq!(machine.read_u64(56 - ACCESS_PADDING), Ok(0)); // the last long that fits
+        assert_eq!(machine.read_u64(57 - ACCESS_PADDING), Err(MemoryErr::SegmentationFault));
+        machine.grow_memory(100).unwrap();
+        assert_eq!(machine.usable_memory(), 164 - ACCESS_PADDING);
+        let machine = Machine::from_memory(vec![0; 32], 0, 0);
+        assert_eq!(machine.usable_memory(), 32 - ACCESS_PADDING);
+    }
+
+    #[test]
+    fn peek_stack_test() {
+        let image = ir::build(r#"
+.square
+    cpyl -16 -24        ; stack: return slot, argument, return address
+    mull -24 -16
+    ret
+.main export
+    pushvl 0            ; the return slot
+    pushvl 7
+    call $square
+    popl
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.peek_stack_as::<u64>(-8), Ok(49));
+        assert_eq!(machine.peek_stack_as::<u8>(-1), Ok(49)); // big-endian, so the low byte is on top
+        assert_eq!(machine.peek_stack_as::<u64>(-4), Err(MemoryErr::SegmentationFault)); // that would read past the top of the stack
+        assert_eq!(machine.pop_host_as::<u64>(), Ok(49));
+        assert_eq!(machine.stack_pointer, machine.stack_start);
+        assert_eq!(machine.pop_host_as::<u8>(), Err(MemoryErr::SegmentationFault));
+        assert_eq!(machine.stack_pointer, machine.stack_start);
+    }
+
+    #[test]
+    fn mount_at_test() {
+        let image = ir::build(BRANCH_TEST).unwrap();
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.write_u64(0, 0xDEAD).unwrap(); // the host's own memory, below the image
+        assert_eq!(machine.mount_at(&image, 128), Ok(()));
+        assert_eq!(machine.text_start, 128 + image.static_section.len() as i64);
+        assert_eq!(machine.invoke(128 + image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        assert_eq!(machine.read_u64(0), Ok(0xDEAD));
+        machine.reset();
+        assert_eq!(machine.invoke(128 + image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        assert_eq!(machine.read_u64(0), Ok(0xDEAD));
+        let size = (image.static_section.len() + image.text_section.len()) as i64;
+        assert_eq!(machine.mount_at(&image, 1024 - 8 - size), Ok(()));
+        assert_eq!(machine.mount_at(&image, 1024 - 7 - size), Err(MountErr::OutOfMemory));
+        assert_eq!(machine.mount_at(&image, -8), Err(MountErr::OutOfMemory));
+        let avc = avc::build("fn main() { @exit(0) }").unwrap();
+        assert_eq!(machine.mount_at(&avc, 8), Err(MountErr::NotRelocatable));
+        assert_eq!(machine.mount_at(&avc, 0), Ok(()));
+        let unlinked = ir::build(".f extern\n.main export\n    call $f").unwrap();
+        assert_eq!(machine.mount_at(&unlinked, 8), Err(MountErr::UnresolvedSymbol("f".to_string())));
+    }
 
-.success
-    pushvl $test_success
-    invokevirtual $stest_rabbit
+    #[test]
+    fn link_test() {
+        let math = ir::build(r#"
+=scale word 3 export
+.total extern
+.helper
+    mull $total $scale
+    ret
+.triple export
+    call $helper
+    ret
+        "#).unwrap();
+        let main = ir::build(r#"
+=total word 14 export
+=scale_ptr word $scale export  ; an extern in the static section
+.scale extern
+.triple extern
+.main export
+    call $triple
     exit 0
+        "#).unwrap();
+        let image = Image::link(vec![math.clone(), main.clone()]).unwrap();
+        assert_eq!(image.static_section.len(), math.static_section.len() + main.static_section.len());
+        assert_eq!(image.lookup_static("total"), Ok(8));
+        assert_eq!(image.verify(), Ok(()));
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(machine.static_addr("total").unwrap()), Ok(42));
+        assert_eq!(machine.read_u64(machine.static_addr("scale_ptr").unwrap()), Ok(0)); // $scale
+        let relinked = Image::link(vec![ir::build(".pad\n    ret").unwrap(), image]).unwrap(); // the result can be moved again
+        let mut machine = Machine::new(1024);
+        machine.mount(&relinked).unwrap();
+        assert_eq!(machine.invoke(relinked.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(machine.static_addr("total").unwrap()), Ok(42));
+        assert_eq!(Image::link(vec![main.clone()]).unwrap_err(), LinkErr::UndefinedSymbol("scale".to_string()));
+        assert_eq!(Image::link(vec![math.clone(), main.clone(), math.clone()]).unwrap_err(), LinkErr::DuplicateSymbol("scale".to_string()));
+        assert_eq!(Image::link(vec![math, avc::build("fn main() { @exit(0) }").unwrap()]).unwrap_err(), LinkErr::NotRelocatable(1));
+        assert!(ir::build(".x extern\n    ret").is_err());
+    }
 
+    #[test]
+    fn dump_state_test() {
+        let image = ir::build(r#"
 .main export
-    dock $stdabi
-    loadfun $stest
-    swapl -8 $stest_rabbit
-    movvl 1 3
-    subv 3 1
-    branch 3 $success
-    pushvl $test_failure
-    invokevirtual $stest_rabbit
+    pushvl 4702394921427289928 ; "ABCDEFGH"
+    pushvb 33
     exit 0
-"#);
+        "#).unwrap();
         let mut machine = Machine::new(1024);
-        machine.mount(&image);
-        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        let dump = machine.dump_state();
+        assert!(dump.contains(&format!("exec_pointer: {}", machine.exec_pointer())));
+        assert!(dump.contains(&format!("stack_pointer: {}", machine.stack_start + 9)));
+        assert!(dump.contains(&format!("stack_start: {}", machine.stack_start)));
+        assert!(dump.contains(&format!("end: {}", machine.end)));
+        assert!(dump.contains("41 42 43 44 45 46 47 48  ABCDEFGH"));
+        assert!(dump.contains(" 21")); // the byte on its own row
+        assert_eq!(dump.lines().count(), 6); // three lines of registers, the heading, and two rows
     }
 
     #[test]
-    fn exit_value_test() {
+    #[should_panic]
+    fn from_memory_bad_layout_test() {
+        Machine::from_memory(vec![0u8; 64], 8, 60); // the stack would start in the padding
+    }
+
+    #[test]
+    fn static_addr_test() {
+        let image = avc::build(r#"
+long pad = 1;
+long config = 5;
+long hidden = 2;
+
+fn main() {
+    @exit(config)
+}
+
+export config
+        "#).unwrap();
+        assert_eq!(image.lookup_static("config"), Ok(8));
+        assert_eq!(image.lookup_static("hidden"), Err(LookupErr::NoSuchStatic("hidden".to_string())));
+        let mut machine = Machine::new(1024);
+        assert!(machine.static_addr("config").is_err()); // nothing's mounted yet
+        machine.mount(&image).unwrap();
+        let config = machine.static_addr("config").unwrap();
+        machine.setmem::<u64>(config, 77).unwrap(); // the host changes the setting before running
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(77)));
+    }
+
+    #[test]
+    fn reset_test() {
+        let image = avc::build(r#"
+long counter = 10;
+
+fn main() {
+    counter = counter + 1;
+    @exit(counter)
+}
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        for _ in 0..2 {
+            assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(11))); // the counter starts from 10 both times
+            machine.reset();
+            assert_eq!(machine.stack_pointer(), machine.stack_start);
+            assert!(machine.memory[machine.stack_start as usize..].iter().all(|byte| *byte == 0));
+        }
+        let mmu_image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            static_section : vec![0; 2000],
+            text_section : vec![74, 0, 0, 0, 64, // startmmu 64: throws if the MMU is already running
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let room = machine.end - machine.stack_start;
+        machine.remount(&mmu_image); // too big for 1024 bytes, so memory grows
+        assert_eq!(machine.end - machine.stack_start, room);
+        assert_eq!(machine.memory.len(), 2014 + room as usize + 8);
+        let main = mmu_image.lookup("main".to_string());
+        for _ in 0..2 {
+            assert_eq!(machine.invoke(main), Ok(InvokeResult::Ok(0)));
+            assert!(machine.mmu.is_some());
+            machine.reset();
+            assert!(machine.mmu.is_none());
+        }
+    }
+
+    #[test]
+    fn grow_memory_test() {
+        let image = Image {
+            function_table : HashMap::from([("fill".to_string(), 0i64), ("alloc".to_string(), 24i64)]),
+            text_section : vec![74, 0, 0, 0, 64, // 0: startmmu 64: 6 pages fit
+                                4, 0, 0, 0, 0, 0, 0, 1, 0x80, // pushvl 384: all of them
+                                75, // alloc
+                                73, 0, 0, 0, 0, 0, 0, 0, 0, // exit 0
+                                4, 0, 0, 0, 0, 0, 0, 0, 64, // 24: pushvl 64
+                                75, // alloc
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("fill".to_string())), Ok(InvokeResult::Ok(0)));
+        let mmu = machine.mmu.unwrap();
+        let first = machine.read_u64(-8).unwrap() as i64;
+        assert_eq!(first, mmu.heap_start);
+        assert_eq!(mmu.page_count, 6);
+        machine.write_bytes(first, b"still here").unwrap();
+        assert_eq!(machine.invoke(image.lookup("alloc".to_string())), Ok(InvokeResult::Aborted(4))); // the heap is full
+        assert_eq!(machine.grow_memory(1024), Ok(2040));
+        assert_eq!(machine.memory.len(), 2048);
+        let grown = machine.mmu.unwrap();
+        assert_eq!(grown.heap_start, mmu.heap_start);
+        assert!(grown.page_count > mmu.page_count);
+        assert!(grown.table_start > mmu.table_start);
+        assert_eq!(machine.mmu_check(), Ok(()));
+        assert_eq!(machine.invoke(image.lookup("alloc".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(-8), Ok((mmu.heap_start + 6 * 64) as u64)); // the first new page
+        assert_eq!(machine.read_bytes(first, 10), Ok(&b"still here"[..])); // the old allocation didn't move
+        assert_eq!(machine.mmu_check(), Ok(()));
+        let mut machine = Machine::new(64);
+        assert_eq!(machine.grow_memory(64), Ok(120)); // without the MMU it's just more memory
+        assert_eq!(machine.write_u64(100, 1), Ok(()));
+        assert_eq!(machine.grow_memory(usize::MAX), Err(MemoryErr::OutOfMemory));
+    }
+
+    #[test]
+    fn mmu_test() {
+        let machine = run_raw(vec![], vec![74, 0, 0, 0, 64, // startmmu 64
+                                           4, 0, 0, 0, 0, 0, 0, 0, 100, // pushvl 100
+                                           75, // alloc: two pages
+                                           4, 0, 0, 0, 0, 0, 0, 0, 10, // pushvl 10
+                                           75, // alloc: one page
+                                           4, 0, 0, 0, 0, 0, 0, 0, 1, // pushvl 1
+                                           75, // alloc: one page
+                                           73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        let stack = machine.stack_start as usize;
+        let ptrs : Vec<i64> = (0..3).map(|i| i64::from_be_bytes(machine.memory[stack + i * 8..stack + i * 8 + 8].try_into().unwrap())).collect();
+        let mmu = machine.mmu.unwrap();
+        assert_eq!(ptrs, vec![mmu.heap_start, mmu.heap_start + 128, mmu.heap_start + 192]);
+        assert_eq!(machine.mmu_check(), Ok(()));
+        let table = mmu.table_start as usize;
+        let bitmap = table + 12;
+        let runs = bitmap + (mmu.page_count as usize).div_ceil(8);
+
+        let mut scribbled = machine.memory.clone();
+        scribbled[table + 11] = 7; // used page count
+        let mut broken = Machine::new(1024);
+        broken.memory = scribbled;
+        broken.mmu = Some(mmu);
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::UsedCountMismatch { recorded : 7, counted : 4 }));
+        broken.memory = machine.memory.clone();
+        broken.memory[bitmap] |= 0b100000; // page 5 was never allocated
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::OrphanPage(5)));
+        broken.memory = machine.memory.clone();
+        broken.memory[bitmap] &= !0b10; // page 1 belongs to the first allocation
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::FreePageInRun(1)));
+        broken.memory = machine.memory.clone();
+        broken.memory[runs + 4 + 3] = 1; // an allocation starting inside the first one
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::RunOverlap(1)));
+        broken.memory = machine.memory.clone();
+        broken.memory[runs + 3] = 255;
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::RunOutOfRange(0)));
+        broken.memory = machine.memory.clone();
+        broken.memory[table + 3] = 32; // page size
+        assert_eq!(broken.mmu_check(), Err(MmuCorruption::BadHeader));
+
+        // the guest can check for itself, too
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64), ("check".to_string(), 24i64)]),
+            text_section : vec![74, 0, 0, 0, 64, // startmmu 64
+                                4, 0, 0, 0, 0, 0, 0, 0, 100, // pushvl 100
+                                75, // alloc
+                                73, 0, 0, 0, 0, 0, 0, 0, 0, // exit 0
+                                111, // .check: mmucheck
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.invoke(image.lookup("check".to_string())), Ok(InvokeResult::Ok(0)));
+        let table = machine.mmu.unwrap().table_start as usize;
+        machine.memory[table + 11] = 0; // lose track of the allocation
+        assert_eq!(machine.invoke(image.lookup("check".to_string())), Ok(InvokeResult::Aborted(9)));
+
+        // and freeing with a corrupted count doesn't take the host down
         let image = ir::build(r#"
+=ptr word 0 export
 .main export
-        exit 1234
-"#);
+    startmmu 64
+    pushvl 1
+    alloc
+    cpyl -8 $ptr
+    exit 0
+.free export
+    pushl $ptr
+    dealloc
+    exit 0
+        "#).unwrap();
         let mut machine = Machine::new(1024);
-        machine.mount(&image);
-        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(1234)));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        let table = machine.mmu.unwrap().table_start as usize;
+        machine.memory[table + 11] = 0; // a stray store over the in-use count
+        assert_eq!(machine.invoke(image.lookup("free".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.mmu_check(), Err(MmuCorruption::UsedCountMismatch { recorded : u32::MAX, counted : 0 }));
+    }
+
+    #[test]
+    fn alloc_test() {
+        let machine = run_raw(vec![], vec![74, 0, 0, 0, 64, // startmmu 64
+                                           4, 0, 0, 0, 0, 0, 0, 0, 100, // pushvl 100
+                                           75, // alloc: pages 0 and 1
+                                           76, // dealloc them
+                                           4, 0, 0, 0, 0, 0, 0, 0, 10, // pushvl 10
+                                           75, // alloc: reuses page 0
+                                           4, 0, 0, 0, 0, 0, 0, 0, 200, // pushvl 200
+                                           0, 255, 255, 255, 255, 255, 255, 255, 240, // pushl -16: the pointer
+                                           77, // realloc to pages 0 -> 3
+                                           73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        let mmu = machine.mmu.unwrap();
+        let stack = machine.stack_start as usize;
+        assert_eq!(machine.memory[stack..stack + 8], mmu.heap_start.to_be_bytes());
+        assert_eq!(machine.memory[stack + 8..stack + 16], mmu.heap_start.to_be_bytes());
+        assert_eq!(machine.memory[mmu.table_start as usize + 8..mmu.table_start as usize + 12], 4u32.to_be_bytes());
+        assert_eq!(machine.mmu_check(), Ok(()));
+    }
+
+    #[test]
+    fn memstat_test() {
+        let image = ir::build(r#"
+=no_heap word 1 export
+=before word 0 export
+.main export
+    memstat
+    popml $no_heap  ; the free bytes, before there's a heap
+    popl
+    popl
+    startmmu 64
+    memstat
+    popml $before
+    popl
+    popl
+    pushvl 100
+    alloc           ; two pages
+    pushvl 1
+    alloc           ; and one more
+    memstat
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(8192);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(machine.static_addr("no_heap").unwrap()), Ok(0));
+        let before = machine.read_u64(machine.static_addr("before").unwrap()).unwrap();
+        let free = machine.pop_host_as::<u64>().unwrap();
+        let ceiling = machine.pop_host_as::<i64>().unwrap();
+        let stack_pointer = machine.pop_host_as::<i64>().unwrap();
+        assert_eq!(before - free, 192);
+        assert_eq!(ceiling, machine.mmu.unwrap().heap_start);
+        assert_eq!(stack_pointer, machine.stack_pointer);
+        let mut machine = Machine::new(1024);
+        machine.set_stack_size(16); // not enough for all three
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Err(InvokeErr::MemErr(MemoryErr::StackOverflow)));
+    }
+
+    #[test]
+    fn alloc_limit_test() {
+        let run = |program : &str| {
+            let image = ir::build(program).unwrap();
+            let mut machine = Machine::new(8192);
+            machine.set_alloc_limit(256); // 4 pages
+            machine.mount(&image).unwrap();
+            (machine.invoke(image.lookup("main".to_string())), machine)
+        };
+        let (result, machine) = run(r#"
+.main export
+    startmmu 64
+    pushvl 128
+    alloc           ; half the budget
+    pushvl 128
+    alloc           ; and the other half
+    dealloc         ; give that back
+    pushvl 100
+    alloc           ; so this fits
+    exit 0
+        "#);
+        assert_eq!(result, Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.mmu.unwrap().live_bytes, 256);
+        let (result, _) = run(r#"
+.main export
+    startmmu 64
+    pushvl 128
+    alloc
+    pushvl 128
+    alloc
+    pushvl 1        ; even one byte takes a page
+    alloc
+    exit 0
+        "#);
+        assert_eq!(result, Ok(InvokeResult::Aborted(4)));
+        let (result, machine) = run(r#"
+.main export
+    startmmu 64
+    pushvl 64
+    alloc
+    pushvl 192
+    pushl -16
+    realloc         ; the old page is freed first, so this just fits
+    pushvl 64
+    alloc
+    pushvl 256
+    pushl -24
+    realloc         ; but this doesn't
+    exit 0
+        "#);
+        assert_eq!(result, Ok(InvokeResult::Aborted(4)));
+        assert_eq!(machine.mmu.unwrap().live_bytes, 256); // the failed realloc kept what it had
+    }
+
+    #[test]
+    fn float_convert_test() {
+        let image = ir::build(r#"
+=a double 3.9d
+=b double -1.5d
+=c double 100000000000000000000d
+=d double -1.5d
+=e float 3.9f
+=f word 0
+=g int -7i
+=h word -1          ; u64::MAX, read unsigned
+=truncated word 0 export
+=negative word 0 export
+=huge word 0 export
+=unsigned word 0 export
+=small int 0 export
+=nan int 0 export
+=to_double double 0d export
+=to_float float 0f export
+=from_unsigned double 0d export
+.main export
+    f2il $a $truncated
+    f2il $b $negative
+    f2il $c $huge
+    f2ul $d $unsigned   ; below 0, so it's 0
+    f2ii $e $small
+    fdivl $f $f         ; 0 / 0
+    f2ii $f $nan        ; only the high 4 bytes of the NaN, which is still a NaN as an f32
+    i2fl $b $to_double  ; the bits of -1.5 as an i64
+    i2fi $g $to_float
+    u2fl $h $from_unsigned
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        let read = |name : &str, len : usize| machine.read_bytes(machine.static_addr(name).unwrap(), len).unwrap().to_vec();
+        let long = |name : &str| i64::from_be_bytes(read(name, 8).try_into().unwrap());
+        let int = |name : &str| i32::from_be_bytes(read(name, 4).try_into().unwrap());
+        assert_eq!(long("truncated"), 3);
+        assert_eq!(long("negative"), -1);
+        assert_eq!(long("huge"), i64::MAX);
+        assert_eq!(long("unsigned"), 0);
+        assert_eq!(int("small"), 3);
+        assert_eq!(int("nan"), 0);
+        assert_eq!(f64::from_bits(long("to_double") as u64), (-1.5f64).to_bits() as i64 as f64);
+        assert_eq!(f32::from_bits(int("to_float") as u32), -7.0);
+        assert_eq!(f64::from_bits(long("from_unsigned") as u64), u64::MAX as f64);
+    }
+
+    #[test]
+    fn float_test() {
+        let float_op = |op : u8, a : f64, b : f64| -> Machine { // run a two-operand float instruction on a and b (at 0 and 8)
+            let mut statics = a.to_bits().to_be_bytes().to_vec();
+            statics.extend(b.to_bits().to_be_bytes());
+            run_raw(statics, vec![op, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, // op 0 8
+                                  73, 0, 0, 0, 0, 0, 0, 0, 0]) // exit 0
+        };
+        let result = |machine : &Machine| f64::from_bits(u64::from_be_bytes(machine.memory[0..8].try_into().unwrap()));
+        assert_eq!(result(&float_op(112, 0.1, 0.2)), 0.1 + 0.2); // faddl
+        assert_eq!(result(&float_op(114, 0.5, 0.25)), 0.25); // fsubl
+        assert_eq!(result(&float_op(116, 1.5, -2.0)), -3.0); // fmull
+        assert_eq!(result(&float_op(118, 1.0, 0.0)), f64::INFINITY); // fdivl
+        let stack_top = |machine : &Machine| machine.memory[machine.stack_start as usize];
+        assert_eq!(stack_top(&float_op(120, 0.1 + 0.2, 0.3)), 1); // fcmpl: 0.1 + 0.2 is a tiny bit bigger than 0.3
+        assert_eq!(stack_top(&float_op(120, 1.0, 1.0)), 0);
+        assert_eq!(stack_top(&float_op(120, -1.0, 1.0)), 2);
+        assert_eq!(stack_top(&float_op(120, f64::NAN, 1.0)), 3); // NaN is unordered with everything,
+        assert_eq!(stack_top(&float_op(120, f64::NAN, f64::NAN)), 3); // including itself
+
+        let mut statics = 0.1f32.to_bits().to_be_bytes().to_vec();
+        statics.extend(0.2f32.to_bits().to_be_bytes());
+        let machine = run_raw(statics, vec![113, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, // faddi 0 4
+                                            73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        assert_eq!(f32::from_bits(u32::from_be_bytes(machine.memory[0..4].try_into().unwrap())), 0.1f32 + 0.2f32);
+    }
+
+    #[test]
+    fn dupblock_test() {
+        let image = ir::build(r#"
+.main export
+    dupblock 24
+        "#).unwrap();
+        let mut text = vec![4, 1, 2, 3, 4, 5, 6, 7, 8, // pushvl: a 24 byte struct
+                            4, 9, 10, 11, 12, 13, 14, 15, 16,
+                            4, 17, 18, 19, 20, 21, 22, 23, 24];
+        text.extend(image.text_section.clone());
+        text.extend([73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+        let machine = run_raw(vec![], text);
+        let stack = machine.stack_start as usize;
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 48);
+        assert_eq!(machine.memory[stack..stack + 24], (1..=24).collect::<Vec<u8>>());
+        assert_eq!(machine.memory[stack..stack + 24], machine.memory[stack + 24..stack + 48]);
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![7, 1, // pushvb 1
+                                126, 0, 0, 0, 0, 0, 0, 0, 2], // dupblock 2: there's only one byte on the stack
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Aborted(1)));
+    }
+
+    #[test]
+    fn host_memory_test() {
+        let image = Image {
+            function_table : HashMap::from([("reverse".to_string(), 0i64)]),
+            static_section : vec![0; 5], // room for a 5 byte string
+            text_section : vec![11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, // swapb 0 4
+                                11, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 3, // swapb 1 3
+                                73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        machine.write_bytes(0, b"hello").unwrap();
+        assert_eq!(machine.invoke(image.lookup("reverse".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_bytes(0, 5), Ok(&b"olleh"[..]));
+
+        machine.write_u64(100, 0x0123456789ABCDEF).unwrap();
+        assert_eq!(machine.read_bytes(100, 2), Ok(&[0x01, 0x23][..])); // big-endian, like the VM
+        assert_eq!(machine.read_u64(100), Ok(0x0123456789ABCDEF));
+        machine.stack_pointer = 108;
+        assert_eq!(machine.read_u64(-8), Ok(0x0123456789ABCDEF)); // relative to the top of the stack
+        assert_eq!(machine.read_bytes(machine.end - 4, 8), Err(MemoryErr::SegmentationFault));
+        assert_eq!(machine.write_bytes(-200, b"no"), Err(MemoryErr::SegmentationFault));
+    }
+
+    #[test]
+    fn unaligned_test() { // values live at byte granularity, so odd addresses are normal. under miri, this would flag the old
+        // transmute-based access for making misaligned references
+        let mut machine = Machine::new(64);
+        machine.write_bytes(1, &[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]).unwrap();
+        assert_eq!(machine.get_at_as::<u64>(1), Ok(0x0123456789ABCDEF));
+        assert_eq!(machine.get_at_as::<u32>(3), Ok(0x456789AB));
+        machine.setmem::<f64>(13, 1.5).unwrap();
+        assert_eq!(machine.get_at_as::<f64>(13), Ok(1.5));
+        assert_eq!(machine.read_bytes(13, 8), Ok(&1.5f64.to_be_bytes()[..]));
+        machine.setmem::<i16>(7, -2).unwrap();
+        assert_eq!(machine.read_bytes(7, 2), Ok(&[0xFF, 0xFE][..]));
+        assert_eq!(machine.get_at_as::<u64>(60), Err(MemoryErr::SegmentationFault)); // would run off the end of memory
+    }
+
+    #[test]
+    fn cas_test() {
+        let cas = |current : u32, expected : u32| { // try to swap current at address 0 for 99 and return (result, value now at address 0)
+            let mut text = vec![5, 0, 0, 0, 99]; // pushvi 99: the new value
+            text.push(5);
+            text.extend(expected.to_be_bytes()); // pushvi expected
+            text.extend([4, 0, 0, 0, 0, 0, 0, 0, 0, // pushvl 0: the address
+                         123, // casi
+                         73, 0, 0, 0, 0, 0, 0, 0, 0]); // exit 0
+            let machine = run_raw(current.to_be_bytes().to_vec(), text);
+            (machine.memory[machine.stack_start as usize], u32::from_be_bytes(machine.memory[0..4].try_into().unwrap()))
+        };
+        assert_eq!(cas(5, 5), (1, 99));
+        assert_eq!(cas(5, 6), (0, 5));
+        let image = ir::build(r#"
+=lock word 5
+=swapped byte 7
+=failed byte 7
+=flag byte 0
+.main export
+    pushvl 9                ; the new value
+    pushvl 5                ; what it should be now, which it is
+    pushvl $lock
+    casl
+    popmb $swapped
+    pushvl 3
+    pushvl 5                ; it's 9 now, so this doesn't swap
+    pushvl $lock
+    casl
+    popmb $failed
+    pushvb 1
+    pushvb 0
+    pushvl $flag
+    casb
+    exit 0
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(0)));
+        assert_eq!(machine.read_u64(0), Ok(9));
+        assert_eq!(machine.read_bytes(8, 3), Ok(&[1, 0, 1][..])); // swapped, failed, flag
+        assert_eq!(machine.peek_stack_as::<u8>(-1), Ok(1)); // casb's result
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 1);
+    }
+
+    #[test]
+    fn step_test() {
+        let image = ir::build(r#"
+=padding word 0
+.success
+    exit 0
+.main export
+    branch 0 $success
+    exit 1
+"#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let main = image.lookup("main".to_string());
+        machine.set_exec_pointer(main);
+        assert_eq!(machine.exec_pointer(), main);
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        assert_eq!(machine.step(), Ok(StepResult::Continue));
+        assert_eq!(machine.exec_pointer(), 8); // .success, right after the padding
+        machine.set_exec_pointer(8);
+        machine.memory[8] = 73; // turn `exit 0` into `exit 7`
+        machine.memory[16] = 7;
+        assert_eq!(machine.step(), Ok(StepResult::Exited(7)));
+    }
+
+    #[test]
+    fn error_display_test() {
+        let message = InvokeErr::UncaughtThrow(1).to_string();
+        assert!(message.contains("out-of-bounds memory access"));
+        assert!(message.contains('1'));
+        let err : Box<dyn std::error::Error> = Box::new(InvokeErr::MemErr(MemoryErr::SegmentationFault));
+        assert_eq!(err.source().unwrap().to_string(), MemoryErr::SegmentationFault.to_string());
+        assert!(MmuCorruption::OrphanPage(12).to_string().contains("12"));
     }
 
     #[test]
@@ -568,9 +4158,359 @@ fn main() {
     do_print();
     @exit();
 }
-        "#);
+        "#).unwrap();
+        let mut names : Vec<&String> = image.function_table.keys().collect();
+        names.sort();
+        assert_eq!(names, ["do_print", "getstr", "main"]);
+        assert_eq!(image.function_extents.len(), 3);
+        assert!(image.static_section.windows(12).any(|s| s == b"STDABI TEST\0"));
+        let mut machine = Machine::new(1024);
+        stdabi::register(&mut machine);
+        machine.set_output(Box::new(SharedSink::default()));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::StdabiTestSuccess));
+    }
+
+    #[test]
+    fn avc_call_test() {
+        let image = avc::build(r#"
+long counter;
+long hook; // filled in by the host
+export hook
+export counter
+
+fn set(long value) {
+    counter = value;
+}
+
+fn main() {
+    set(5)
+    hook(counter, 2);
+    forward(hook(3, 4))
+    @exit(7);
+}
+
+fn forward(long x) {
+    set(x)
+}
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let rabbit = machine.mock_ext("hook", 2, vec![100, 200]);
+        machine.write_u64(image.static_table["hook"], rabbit as u64).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(7)));
+        assert_eq!(machine.ext_call_log("hook"), [vec![5, 2], vec![3, 4]]);
+        assert_eq!(machine.read_u64(image.static_table["counter"]), Ok(200)); // forward got hook's result, and set it
+        assert_eq!(machine.stack_pointer(), machine.stack_start); // everything got cleaned up
+        let errors = avc::build("fn main() { nothing(1) }").unwrap_err();
+        assert!(errors[0].message.contains("undefined function nothing"));
+        assert_eq!(errors[0].span.start, 12);
+        assert!(avc::build("fn main() { @exit(1, 2) }").unwrap_err()[0].message.contains("@exit"));
+        assert!(avc::build("export function nope").is_err());
+        assert!(avc::build("widget x = 5").unwrap_err()[0].message.contains("unknown type widget"));
+    }
+
+    #[test]
+    fn avc_control_flow_test() {
+        let run = |program : &str| {
+            let image = avc::build(program).unwrap();
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            let result = machine.invoke(image.lookup("main".to_string()));
+            assert_eq!(machine.stack_pointer(), machine.stack_start);
+            result
+        };
+        assert_eq!(run(r#"
+long total;
+long i = 1;
+
+fn main() {
+    while (i < 11) {
+        total = total + i;
+        i = i + 1;
+    }
+    @exit(total)
+}
+        "#), Ok(InvokeResult::Ok(55)));
+        assert_eq!(run(r#"
+long count;
+long i;
+long j;
+
+fn main() {
+    while i < 3 {
+        j = 0;
+        while j < 4 {
+            count = count + 1;
+            j = j + 1
+        }
+        i = i + 1
+    }
+    @exit(count)
+}
+        "#), Ok(InvokeResult::Ok(12))); // nested loops get their own labels
+        assert_eq!(run(r#"
+long x = 5;
+long result;
+
+fn main() {
+    if x == 5 {
+        result = result + 1
+    }
+    if x > 5 {
+        result = result + 10
+    } else {
+        result = result + 100
+    }
+    if (x + 1 > 5) == 1 {
+        result = result + 1000
+    }
+    @exit(result)
+}
+        "#), Ok(InvokeResult::Ok(1101)));
+    }
+
+    #[test]
+    fn avc_arithmetic_test() {
+        let eval = |expression : &str| {
+            let image = avc::build(&format!("long x = 7; fn main() {{ @exit({}) }}", expression)).unwrap();
+            let mut machine = Machine::new(1024);
+            machine.mount(&image).unwrap();
+            machine.invoke(image.lookup("main".to_string()))
+        };
+        assert_eq!(eval("2 + 3 * 4"), Ok(InvokeResult::Ok(14)));
+        assert_eq!(eval("10 - 2 - 3"), Ok(InvokeResult::Ok(5))); // left-associative
+        assert_eq!(eval("(2 + 3) * 4"), Ok(InvokeResult::Ok(20)));
+        assert_eq!(eval("x * x / 2 % 5"), Ok(InvokeResult::Ok(4))); // ((49 / 2) % 5)
+        assert_eq!(eval("1 + 1 == 2"), Ok(InvokeResult::Ok(1)));
+        assert_eq!(eval("x != 7"), Ok(InvokeResult::Ok(0)));
+        assert_eq!(eval("(x <= 7) + (x >= 8) * 2 + (x < 7) * 4 + (x > 6) * 8"), Ok(InvokeResult::Ok(9)));
+        assert_eq!(eval("x / 0"), Ok(InvokeResult::Aborted(8)));
+        assert_eq!(eval("9223372036854775807 - 9223372036854775806"), Ok(InvokeResult::Ok(1)));
+        let errors = avc::build("fn main() { @exit(99999999999999999999) }").unwrap_err();
+        assert_eq!(errors[0].message, "99999999999999999999 doesn't fit in a long");
+        assert_eq!(errors[0].span, 18..38);
+    }
+
+    #[test]
+    fn avc_local_test() {
+        let image = avc::build(r#"
+long result;
+
+fn swap(long bias) {
+    long a = 3 + bias;
+    long b = 4;
+    a = a + b;
+    b = a - b;
+    a = a - b;
+    result = a * 10 + b;
+}
+
+fn main() {
+    long i;
+    while i < 3 {
+        long doubled = i * 2; // reset on every pass
+        i = i + 1;
+        swap(doubled)
+    }
+    @exit(result)
+}
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(47))); // the last swap had a bias of 4
+        assert_eq!(machine.stack_pointer(), machine.stack_start + 16); // swap dropped its locals, but main exited with i and doubled still live
+        let errors = avc::build("fn f(long x) { long y; long y = 2; long x }").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].message.contains("y is already declared"));
+        assert!(avc::build("fn f() { y = 1 }").unwrap_err()[0].message.contains("undefined variable y"));
+        assert!(avc::build("fn main() { @exit(1) } fn main() { @exit(2) }").unwrap_err()[0].message.contains("main is already defined"));
+        assert!(avc::build("long a = 1; long a = 2;").unwrap_err()[0].message.contains("a is already defined"));
+        assert!(avc::build("long main = 1; fn main() { }").unwrap_err()[0].message.contains("main is already defined"));
+    }
+
+    #[test]
+    fn frame_size_test() {
+        let image = avc::build("fn f() { long a; long b = 2; long c = a + b }\nfn g() { }").unwrap();
+        assert_eq!(image.frame_sizes().unwrap()["f"], 24);
+        assert_eq!(image.frame_sizes().unwrap()["g"], 0);
+        assert_eq!(image.max_frame_size(), Some(24));
+        let image = ir::build(".f\n    updstck 16\n    updstck 8\n    updstck -24\n    updstck 4\n    ret\n.g\n    ret").unwrap();
+        assert_eq!(image.frame_sizes().unwrap()["f"], 24); // the peak, not the sum
+        assert_eq!(image.max_frame_size(), Some(24));
+        assert_eq!(Image::from_bytes(&image.to_bytes()).unwrap().frame_sizes, image.frame_sizes);
+    }
+
+    #[test]
+    fn avc_type_test() {
+        let image = avc::build(r#"
+char letter = 65;
+char other;
+
+fn second(&char s) {
+    other = *(s + 1) + letter - 65; // both chars, so only a byte gets written
+}
+
+fn main() {
+    second("hi");
+    @exit(other)
+}
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(b'i' as i64)));
+        let image = avc::build("&char s = \"abc\"; &char t = \"abc\"; fn main() { @exit(*(s + 1) + (s == t) * 1000) }").unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(1000 + b'b' as i64))); // the same string, shared
+        let message = |program : &str| avc::build(program).unwrap_err()[0].message.clone();
+        assert_eq!(message("fn f(long a, long b) { } fn main() { f(1) }"), "f takes 2 arguments, but got 1");
+        assert_eq!(message("fn main() { long x = \"oops\" }"), "can't assign a string (a &char) to x, which is a long");
+        assert_eq!(message("fn f(&long p) { } fn main() { long x; f(x) }"), "argument p of f is a &long, but x is a long");
+        assert_eq!(message("fn main() { long x; long y = *x }"), "x isn't a reference (it's a long), so it can't be dereferenced");
+        assert_eq!(message("fn main() { &char s = \"a\"; &long p = s }"), "can't assign s (a &char) to p, which is a &long");
+        assert!(message("fn main() { long x = \"a\" * 2 }").contains("arithmetic on a string"));
+        assert!(message("char c = 300").contains("doesn't fit"));
+    }
+
+    #[test]
+    fn avc_return_test() {
+        let image = avc::build(r#"
+fn square(long x) -> long {
+    long result = x * x;
+    result
+}
+
+fn first_over(long limit) -> long {
+    long i = 1;
+    while 1 {
+        if square(i) > limit {
+            return i
+        }
+        i = i + 1
+    }
+}
+
+fn low_byte(long x) -> char {
+    x
+}
+
+fn nothing() {
+    return;
+    @exit(99)
+}
+
+fn square_plus_one(long x) -> long {
+    square(x) + 1
+}
+
+fn main() {
+    nothing();
+    square(2) == 4; // a statement that starts with a call, and goes on
+    @exit(first_over(50) * 1000 + low_byte(258) + square(square(2)) - 16 + square_plus_one(3) - 10)
+}
+        "#).unwrap();
         let mut machine = Machine::new(2048);
-        machine.mount(&image);
-        let output = machine.invoke(image.lookup("main".to_string()));
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(8002))); // 8 * 8 > 50, and 258 is 0x102
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+        let message = |program : &str| avc::build(program).unwrap_err()[0].message.clone();
+        assert_eq!(message("fn f() -> &char { 5 }"), "can't assign 5 (a long) to the return value, which is a &char");
+        assert_eq!(message("fn f() { return 5 }"), "this function doesn't return anything, so it can't return a value");
+        assert_eq!(message("fn f() -> long { return }"), "this function has to return a long");
+    }
+
+    #[test]
+    fn avc_mutual_recursion_test() {
+        let image = avc::build(r#"
+fn is_even(long n) -> long {
+    if n == 0 {
+        return 1
+    }
+    is_odd(n - 1)
+}
+
+fn is_odd(long n) -> long { // declared after is_even calls it
+    if n == 0 {
+        return 0
+    }
+    is_even(n - 1)
+}
+
+fn is_odd_again(long n) -> long {
+    if n == 0 {
+        return 0
+    }
+    is_even(n - 1) + 0 // a tail that starts with a call
+}
+
+fn main() {
+    @exit(is_even(10) * 1000 + is_odd(7) * 100 + is_even(3) * 10 + is_odd(0) + is_odd_again(5) * 10000)
+}
+        "#).unwrap();
+        let mut machine = Machine::new(4096);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(image.lookup("main".to_string())), Ok(InvokeResult::Ok(11100)));
+        assert_eq!(machine.stack_pointer(), machine.stack_start);
+    }
+
+    #[test]
+    fn invoke_with_args_test() {
+        let image = avc::build(r#"
+fn add(long a, long b) -> long {
+    a + b
+}
+
+fn second(&char s, long bias) -> long {
+    *(s + 1) + bias
+}
+
+fn bail(long code) -> long {
+    @exit(code)
+}
+
+export add
+export second
+export bail
+        "#).unwrap();
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        let add = image.lookup("add".to_string());
+        assert_eq!(machine.invoke_with_args(add, &[StackArg::Long(3), StackArg::Long(4)]), Ok(InvokeResult::Returned(7)));
+        assert_eq!(machine.read_u64(machine.stack_start), Ok(7)); // the return slot is at the bottom of the stack
+        let second = image.lookup("second".to_string());
+        assert_eq!(machine.invoke_with_args(second, &[StackArg::Buffer(b"AB\0"), StackArg::Long(1)]), Ok(InvokeResult::Returned(67)));
+        assert_eq!(machine.read_bytes(machine.stack_start, 3), Ok(&b"AB\0"[..])); // the copy is below the return slot
+        let bail = image.lookup("bail".to_string());
+        assert_eq!(machine.invoke_with_args(bail, &[StackArg::Long(5)]), Ok(InvokeResult::Ok(5))); // exiting still exits
+    }
+
+    #[test]
+    fn dispatch_table_test() {
+        for op in 0..=255u8 { // every opcode instruction_len knows has a handler, and nothing else does
+            assert_eq!(DISPATCH[op as usize].is_some(), Machine::instruction_len(op).is_some(), "opcode {}", op);
+        }
+        let image = Image {
+            function_table : HashMap::from([("main".to_string(), 0i64)]),
+            text_section : vec![78], // maketbl: tables aren't in yet
+            ..Default::default()
+        };
+        let mut machine = Machine::new(1024);
+        machine.mount(&image).unwrap();
+        assert_eq!(machine.invoke(0), Err(InvokeErr::BadInstruction));
+    }
+
+    #[test]
+    fn mount_oom_test() {
+        let image = Image {
+            function_table : HashMap::new(),
+            static_section : vec![0; 600],
+            text_section : vec![73, 0, 0, 0, 0, 0, 0, 0, 0], // exit 0
+            ..Default::default()
+        };
+        let mut machine = Machine::new(512);
+        assert_eq!(machine.mount(&image), Err(MemoryErr::OutOfMemory));
+        let mut machine = Machine::new(1024);
+        assert_eq!(machine.mount(&image), Ok(()));
     }
 }
@@ -0,0 +1,108 @@
+// Image::link: merging separately built images into one. the static sections all go first, one after the other, then the text sections,
+// so nearly every address baked into an image moves. the relocation tables say where those addresses are, so they can be fixed up, and
+// extern symbols (left as 0 by the IR) are filled in from the public tables of all the images.
+use crate::{ DebugInfo, Image, Relocation, RelocTarget, Section, SourceSpan };
+use crate::error::LinkErr;
+use alloc::{ string::String, vec::Vec };
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+
+
+fn export(symbols : &mut HashMap<String, (usize, Section, i64)>, name : &str, image : usize, section : Section, addr : i64) -> Result<(), LinkErr> {
+    match symbols.get(name) {
+        Some((other, _, _)) if *other != image => Err(LinkErr::DuplicateSymbol(String::from(name))),
+        Some(_) => Ok(()), // a static and a function with the same name in one image: $name means the static, same as in the IR
+        None => {
+            symbols.insert(String::from(name), (image, section, addr));
+            Ok(())
+        }
+    }
+}
+
+
+pub(crate) fn patch(bytes : &mut [u8], offset : usize, f : impl FnOnce(i64) -> i64) { // the offset has already been checked
+    let slot : &mut [u8; 8] = (&mut bytes[offset..offset + 8]).try_into().unwrap();
+    *slot = f(i64::from_be_bytes(*slot)).to_be_bytes();
+}
+
+
+impl Image {
+    pub fn link(images : Vec<Image>) -> Result<Image, LinkErr> { // images can only be linked if they have relocation tables (ir::build makes
+        // them). the result has one too, so it can be linked again. it has debug info if any of the images did, but the lines are in
+        // whichever source each part came from. frame sizes are merged the same way
+        let static_len : i64 = images.iter().map(|image| image.static_section.len() as i64).sum();
+        let mut linked = Image {
+            function_table : HashMap::new(),
+            static_table : HashMap::new(),
+            static_section : Vec::new(),
+            text_section : Vec::new(),
+            function_extents : Vec::new(),
+            relocations : None,
+            debug_info : None,
+            frame_sizes : None
+        };
+        let mut relocations = Vec::new();
+        let mut symbols = HashMap::new(); // every export, with the image it came from and its new address
+        let mut externs = Vec::new(); // (section, offset, name) of every address that's waiting for a symbol
+        for (index, mut image) in images.into_iter().enumerate() {
+            let image_relocations = image.relocations.take().ok_or(LinkErr::NotRelocatable(index))?;
+            let static_base = linked.static_section.len() as i64;
+            let text_base = linked.text_section.len() as i64;
+            let text_shift = static_len + text_base - image.static_section.len() as i64; // text addresses assumed only this image's statics came first
+            for (name, offset) in &image.static_table {
+                export(&mut symbols, name, index, Section::Static, static_base + offset)?;
+                linked.static_table.insert(name.clone(), static_base + offset);
+            }
+            for (name, offset) in &image.function_table {
+                export(&mut symbols, name, index, Section::Text, static_len + text_base + offset)?;
+                linked.function_table.insert(name.clone(), text_base + offset);
+            }
+            if let Some(debug_info) = image.debug_info.take() {
+                let spans = debug_info.spans.into_iter().map(|span| SourceSpan { start : span.start + text_base, end : span.end + text_base, ..span });
+                linked.debug_info.get_or_insert_with(DebugInfo::default).spans.extend(spans);
+            }
+            if let Some(frame_sizes) = image.frame_sizes.take() { // private functions in different images can share a name, so keep the bigger
+                let linked_sizes = linked.frame_sizes.get_or_insert_with(HashMap::new);
+                for (name, size) in frame_sizes {
+                    let entry = linked_sizes.entry(name).or_insert(0);
+                    *entry = (*entry).max(size);
+                }
+            }
+            linked.function_extents.extend(image.function_extents.iter().map(|(start, end)| (start + text_base, end + text_base)));
+            for relocation in image_relocations {
+                let (bytes, base) = match relocation.section {
+                    Section::Static => (&mut image.static_section, static_base),
+                    Section::Text => (&mut image.text_section, text_base)
+                };
+                if relocation.offset.checked_add(8).is_none_or(|end| end > bytes.len()) {
+                    return Err(LinkErr::BadRelocation(index));
+                }
+                let offset = relocation.offset + base as usize;
+                match relocation.target {
+                    RelocTarget::Section(Section::Static) => patch(bytes, relocation.offset, |addr| addr.wrapping_add(static_base)),
+                    RelocTarget::Section(Section::Text) => patch(bytes, relocation.offset, |addr| addr.wrapping_add(text_shift)),
+                    RelocTarget::Symbol(name) => {
+                        externs.push((relocation.section, offset, name));
+                        continue;
+                    }
+                }
+                relocations.push(Relocation { section : relocation.section, offset, target : relocation.target });
+            }
+            linked.static_section.append(&mut image.static_section);
+            linked.text_section.append(&mut image.text_section);
+        }
+        for (section, offset, name) in externs {
+            let Some(&(_, target, addr)) = symbols.get(&name) else { return Err(LinkErr::UndefinedSymbol(name)) };
+            let bytes = match section {
+                Section::Static => &mut linked.static_section,
+                Section::Text => &mut linked.text_section
+            };
+            patch(bytes, offset, |_| addr);
+            relocations.push(Relocation { section, offset, target : RelocTarget::Section(target) });
+        }
+        linked.relocations = Some(relocations);
+        Ok(linked)
+    }
+}
@@ -0,0 +1,102 @@
+// the anyvm command line tool: assemble, run, and disassemble programs.
+//  anyvm run [--avc] [--memory BYTES] FILE     build FILE (IR, or AVC with --avc; .avm files are loaded as they are) and run its main.
+//                                              prints the exit code, and exits with it
+//  anyvm build [--avc] FILE -o OUT             build FILE and save the image to OUT (normally a .avm file)
+//  anyvm disasm FILE                           print the bytecode in a .avm file as IR
+// stdabi is registered in every machine, so programs can print with stest.
+use anyvm::{ Image, Machine, avc, ir, stdabi };
+use anyvm::error::*;
+use std::process::exit;
+
+
+const DEFAULT_MEMORY : usize = 65536;
+const USAGE : &str = "usage: anyvm run [--avc] [--memory BYTES] FILE | anyvm build [--avc] FILE -o OUT | anyvm disasm FILE";
+
+
+struct Options {
+    avc : bool,
+    memory : usize,
+    output : Option<String>,
+    file : String
+}
+
+
+fn fail(message : impl std::fmt::Display) -> ! {
+    eprintln!("anyvm: {}", message);
+    exit(1);
+}
+
+
+fn parse_options(args : &[String]) -> Options {
+    let mut options = Options { avc : false, memory : DEFAULT_MEMORY, output : None, file : String::new() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--avc" => options.avc = true,
+            "--memory" => options.memory = args.next().and_then(|bytes| bytes.parse().ok()).unwrap_or_else(|| fail("--memory needs a number of bytes")),
+            "-o" => options.output = Some(args.next().unwrap_or_else(|| fail("-o needs a file")).clone()),
+            _ if options.file.is_empty() => options.file = arg.clone(),
+            _ => fail(USAGE)
+        }
+    }
+    if options.file.is_empty() {
+        fail(USAGE);
+    }
+    options
+}
+
+
+fn load(options : &Options) -> Image { // build the file, or load it if it's already an image
+    if options.file.ends_with(".avm") {
+        let data = std::fs::read(&options.file).unwrap_or_else(|e| fail(format!("can't read {}: {}", options.file, e)));
+        let image = Image::from_bytes(&data).unwrap_or_else(|e| fail(format!("{}: {}", options.file, e)));
+        if let Err(e) = image.verify() {
+            fail(format!("{}: {}", options.file, e));
+        }
+        return image;
+    }
+    let source = std::fs::read_to_string(&options.file).unwrap_or_else(|e| fail(format!("can't read {}: {}", options.file, e)));
+    let built = if options.avc { avc::build(&source) } else { ir::build(&source) };
+    built.unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("{}: {}", options.file, error);
+        }
+        exit(1);
+    })
+}
+
+
+fn run(options : &Options) -> ! {
+    let image = load(options);
+    let main = image.lookup_function("main").unwrap_or_else(|| fail(format!("{} doesn't export a main function", options.file)));
+    let mut machine = Machine::new(options.memory);
+    stdabi::register(&mut machine);
+    machine.mount(&image).unwrap_or_else(|e| fail(format!("can't mount {}: {}", options.file, e)));
+    match machine.invoke(main) {
+        Ok(InvokeResult::Ok(code)) => {
+            println!("exited with {}", code);
+            exit(code as i32);
+        },
+        Ok(InvokeResult::Aborted(code)) => fail(format!("uncaught error {} ({})", code, describe_error_code(code))),
+        Ok(InvokeResult::StdabiTestSuccess) => exit(0),
+        Ok(other) => fail(format!("stopped: {:?}", other)),
+        Err(e) => fail(e)
+    }
+}
+
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, rest)) = args.split_first() else { fail(USAGE) };
+    let options = parse_options(rest);
+    match command.as_str() {
+        "run" => run(&options),
+        "build" => {
+            let output = options.output.clone().unwrap_or_else(|| fail("build needs an output file (-o OUT)"));
+            let image = load(&options);
+            std::fs::write(&output, image.to_bytes()).unwrap_or_else(|e| fail(format!("can't write {}: {}", output, e)));
+        },
+        "disasm" => print!("{}", load(&options).disassemble()),
+        _ => fail(USAGE)
+    }
+}
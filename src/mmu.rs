@@ -0,0 +1,279 @@
+// the builtin MMU (startmmu, alloc, dealloc, realloc).
+// the heap and its page table live at the top of VM memory:
+//     [static][text][stack ->          ][heap pages][page table][padding]
+// startmmu hands the top half of the memory above the start of the stack to the heap. the page table is a 12 byte header (page size,
+// page count, and the number of pages in use, all u32s), then a bitmap with a 1 for every page in use, then a u32 for every page holding
+// the length (in pages) of the allocation starting at that page, or 0 if no allocation starts there.
+// like everything else the page table is in VM memory, so a guest can scribble all over it. mmu_check exists to catch that.
+use crate::error::*;
+use crate::Machine;
+
+
+const HEADER_SIZE : i64 = 12;
+
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Mmu { // host-side copy of the layout, so a corrupted header can't send the MMU outside of its own region
+    pub(crate) table_start : i64,
+    pub(crate) heap_start : i64,
+    pub(crate) page_size : u32,
+    pub(crate) page_count : u32,
+    pub(crate) live_bytes : u64 // how much of the heap is allocated, in whole pages. kept here rather than trusting the page table's count,
+                                // since it's what set_alloc_limit checks
+}
+
+
+impl Mmu {
+    fn bitmap_start(&self) -> i64 {
+        self.table_start + HEADER_SIZE
+    }
+
+    fn runs_start(&self) -> i64 {
+        self.bitmap_start() + (self.page_count as i64 + 7) / 8
+    }
+}
+
+
+impl Machine {
+    // the page table is always inside memory (start_mmu made sure of it), so these skip stackaddr
+    fn table_u32(&self, addr : i64) -> u32 {
+        u32::from_be_bytes(self.memory[addr as usize..addr as usize + 4].try_into().unwrap())
+    }
+
+    fn set_table_u32(&mut self, addr : i64, val : u32) {
+        self.memory[addr as usize..addr as usize + 4].copy_from_slice(&val.to_be_bytes());
+    }
+
+    fn page_used(&self, mmu : &Mmu, page : u32) -> bool {
+        self.memory[(mmu.bitmap_start() + page as i64 / 8) as usize] & (1 << (page % 8)) != 0
+    }
+
+    fn set_page_used(&mut self, mmu : &Mmu, page : u32, used : bool) {
+        let byte = &mut self.memory[(mmu.bitmap_start() + page as i64 / 8) as usize];
+        if used {
+            *byte |= 1 << (page % 8);
+        }
+        else {
+            *byte &= !(1 << (page % 8));
+        }
+    }
+
+    fn run_length(&self, mmu : &Mmu, page : u32) -> u32 {
+        self.table_u32(mmu.runs_start() + page as i64 * 4)
+    }
+
+    fn set_run(&mut self, mmu : &Mmu, page : u32, length : u32, used : bool) { // mark an allocation as used or free
+        for p in page..page + length {
+            self.set_page_used(mmu, p, used);
+        }
+        self.set_table_u32(mmu.runs_start() + page as i64 * 4, if used { length } else { 0 });
+        if let Some(mmu) = &mut self.mmu {
+            let bytes = length as u64 * mmu.page_size as u64;
+            mmu.live_bytes = if used { mmu.live_bytes + bytes } else { mmu.live_bytes.saturating_sub(bytes) };
+        }
+        let in_use = self.table_u32(mmu.table_start + 8); // the guest can scribble on this, so it wraps rather than panicking. mmu_check
+        // reports the mismatch
+        self.set_table_u32(mmu.table_start + 8, if used { in_use.wrapping_add(length) } else { in_use.wrapping_sub(length) });
+    }
+
+    pub(crate) fn start_mmu(&mut self, page_size : u32) -> Result<(), InvokeErr> {
+        if self.mmu.is_some() || page_size == 0 {
+            return self.throw(ErrorCode::ALLOC_FAILED);
+        }
+        let available = (self.end - self.stack_start) / 2;
+        // every page costs its own bytes, 4 bytes of run length, and (rounding up generously) a byte of bitmap
+        let page_count = ((available - HEADER_SIZE) / (page_size as i64 + 5)).min(u32::MAX as i64);
+        if page_count <= 0 {
+            return self.throw(ErrorCode::ALLOC_FAILED);
+        }
+        let table_size = HEADER_SIZE + (page_count + 7) / 8 + page_count * 4;
+        let table_start = self.end - table_size;
+        let heap_start = table_start - page_count * page_size as i64;
+        if heap_start < self.stack_pointer {
+            return self.throw(ErrorCode::ALLOC_FAILED);
+        }
+        self.memory[table_start as usize..self.end as usize].fill(0);
+        self.set_table_u32(table_start, page_size);
+        self.set_table_u32(table_start + 4, page_count as u32);
+        self.mmu = Some(Mmu {
+            table_start,
+            heap_start,
+            page_size,
+            page_count : page_count as u32,
+            live_bytes : 0
+        });
+        Ok(())
+    }
+
+    pub(crate) fn grow_mmu(&mut self, old_end : i64) { // memory just grew past old_end: add pages to the end of the heap, and move the
+        // table up to the new end of memory. the old table becomes part of the heap, so it gets zeroed once it's been copied out
+        let mmu = match self.mmu {
+            Some(mmu) => mmu,
+            None => return
+        };
+        let heap_end = mmu.heap_start + mmu.page_count as i64 * mmu.page_size as i64;
+        let available = self.end - mmu.heap_start;
+        let page_count = ((available - HEADER_SIZE) / (mmu.page_size as i64 + 5)).clamp(mmu.page_count as i64, u32::MAX as i64);
+        let bitmap = self.memory[mmu.bitmap_start() as usize..mmu.runs_start() as usize].to_vec();
+        let runs = self.memory[mmu.runs_start() as usize..old_end as usize].to_vec();
+        let in_use = self.table_u32(mmu.table_start + 8);
+        let table_size = HEADER_SIZE + (page_count + 7) / 8 + page_count * 4;
+        let grown = Mmu {
+            table_start : self.end - table_size,
+            heap_start : mmu.heap_start,
+            page_size : mmu.page_size,
+            page_count : page_count as u32,
+            live_bytes : mmu.live_bytes
+        };
+        self.memory[heap_end as usize..self.end as usize].fill(0);
+        self.set_table_u32(grown.table_start, grown.page_size);
+        self.set_table_u32(grown.table_start + 4, grown.page_count);
+        self.set_table_u32(grown.table_start + 8, in_use);
+        let bitmap_start = grown.bitmap_start() as usize;
+        self.memory[bitmap_start..bitmap_start + bitmap.len()].copy_from_slice(&bitmap);
+        let runs_start = grown.runs_start() as usize;
+        self.memory[runs_start..runs_start + runs.len()].copy_from_slice(&runs);
+        self.mmu = Some(grown);
+    }
+
+    fn mmu_alloc(&mut self, size : u64) -> Option<i64> { // first-fit allocation. returns None if there isn't enough room (or no MMU)
+        let mmu = self.mmu?;
+        let pages = size.div_ceil(mmu.page_size as u64).max(1);
+        if pages > mmu.page_count as u64 {
+            return None;
+        }
+        if self.alloc_limit.is_some_and(|limit| mmu.live_bytes + pages * mmu.page_size as u64 > limit) {
+            return None;
+        }
+        let pages = pages as u32;
+        let mut free_run = 0;
+        for page in 0..mmu.page_count {
+            if self.page_used(&mmu, page) {
+                free_run = 0;
+            }
+            else {
+                free_run += 1;
+                if free_run == pages {
+                    let start = page + 1 - pages;
+                    self.set_run(&mmu, start, pages, true);
+                    return Some(mmu.heap_start + start as i64 * mmu.page_size as i64);
+                }
+            }
+        }
+        None
+    }
+
+    fn mmu_allocation(&self, addr : i64) -> Option<(u32, u32)> { // find the (first page, page count) of the allocation starting at addr
+        let mmu = self.mmu?;
+        let offset = addr - mmu.heap_start;
+        if offset < 0 || offset % mmu.page_size as i64 != 0 || offset / mmu.page_size as i64 >= mmu.page_count as i64 {
+            return None;
+        }
+        let page = (offset / mmu.page_size as i64) as u32;
+        let length = self.run_length(&mmu, page);
+        if length == 0 || page as u64 + length as u64 > mmu.page_count as u64 {
+            return None;
+        }
+        Some((page, length))
+    }
+
+    fn check_alloc(&self, size : u64) -> Result<(), InvokeErr> { // the sandbox's limit, if there is one
+        match self.sandbox.and_then(|sandbox| sandbox.max_alloc) {
+            Some(max) if size > max => Err(InvokeErr::AllocLimitExceeded),
+            _ => Ok(())
+        }
+    }
+
+    pub(crate) fn alloc(&mut self) -> Result<(), InvokeErr> {
+        let size : u64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        self.check_alloc(size)?;
+        match self.mmu_alloc(size) {
+            Some(addr) => self.push_as(addr).map_err(InvokeErr::MemErr),
+            None => self.throw(ErrorCode::ALLOC_FAILED)
+        }
+    }
+
+    pub(crate) fn dealloc(&mut self) -> Result<(), InvokeErr> {
+        let addr : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        match (self.mmu, self.mmu_allocation(addr)) {
+            (Some(mmu), Some((page, length))) => {
+                self.set_run(&mmu, page, length, false);
+                Ok(())
+            },
+            _ => self.throw(ErrorCode::OUT_OF_BOUNDS) // not something alloc handed out
+        }
+    }
+
+    pub(crate) fn realloc(&mut self) -> Result<(), InvokeErr> {
+        let addr : i64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        let size : u64 = self.pop_as().map_err(InvokeErr::MemErr)?;
+        self.check_alloc(size)?;
+        let (mmu, (page, length)) = match (self.mmu, self.mmu_allocation(addr)) {
+            (Some(mmu), Some(allocation)) => (mmu, allocation),
+            _ => return self.throw(ErrorCode::OUT_OF_BOUNDS)
+        };
+        let old_start = addr as usize;
+        let contents = self.memory[old_start..old_start + length as usize * mmu.page_size as usize].to_vec();
+        self.set_run(&mmu, page, length, false);
+        match self.mmu_alloc(size) {
+            Some(new_addr) => {
+                let keep = contents.len().min(size.max(1).div_ceil(mmu.page_size as u64) as usize * mmu.page_size as usize);
+                self.wrote(new_addr as usize, keep);
+                self.memory[new_addr as usize..new_addr as usize + keep].copy_from_slice(&contents[..keep]);
+                self.push_as(new_addr).map_err(InvokeErr::MemErr)
+            },
+            None => {
+                self.set_run(&mmu, page, length, true); // nothing else has run since the free, so the old pages are still ours
+                self.throw(ErrorCode::ALLOC_FAILED)
+            }
+        }
+    }
+
+    pub(crate) fn memstat(&mut self) -> Result<(), InvokeErr> {
+        let stack_pointer = self.stack_pointer;
+        let free = self.mmu.map_or(0, |mmu| (mmu.page_count as u64 * mmu.page_size as u64).saturating_sub(mmu.live_bytes));
+        self.check_stack(24).map_err(InvokeErr::MemErr)?; // all or nothing
+        self.push_as(stack_pointer).map_err(InvokeErr::MemErr)?;
+        self.push_as(self.stack_ceiling()).map_err(InvokeErr::MemErr)?;
+        self.push_as(free).map_err(InvokeErr::MemErr)
+    }
+
+    pub fn mmu_check(&self) -> Result<(), MmuCorruption> { // check that the page table is self-consistent. trivially fine if the MMU isn't running.
+        let mmu = match self.mmu {
+            Some(mmu) => mmu,
+            None => return Ok(())
+        };
+        if self.table_u32(mmu.table_start) != mmu.page_size || self.table_u32(mmu.table_start + 4) != mmu.page_count {
+            return Err(MmuCorruption::BadHeader);
+        }
+        let mut counted = 0;
+        let mut run_end = 0; // the page after the end of the allocation we're currently inside
+        for page in 0..mmu.page_count {
+            let length = self.run_length(&mmu, page);
+            if length != 0 {
+                if page < run_end {
+                    return Err(MmuCorruption::RunOverlap(page));
+                }
+                if page as u64 + length as u64 > mmu.page_count as u64 {
+                    return Err(MmuCorruption::RunOutOfRange(page));
+                }
+                run_end = page + length;
+            }
+            let used = self.page_used(&mmu, page);
+            if page < run_end && !used {
+                return Err(MmuCorruption::FreePageInRun(page));
+            }
+            if page >= run_end && used {
+                return Err(MmuCorruption::OrphanPage(page));
+            }
+            if used {
+                counted += 1;
+            }
+        }
+        let recorded = self.table_u32(mmu.table_start + 8);
+        if recorded != counted {
+            return Err(MmuCorruption::UsedCountMismatch { recorded, counted });
+        }
+        Ok(())
+    }
+}
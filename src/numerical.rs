@@ -1,186 +1,841 @@
 // abstractions for numerical types that make interacting with the VM much simpler
 
-pub trait Numerical : Copy + Clone + PartialEq + Ord {
+use core::ops::{ BitOr, BitAnd, BitXor, Add, Sub, Mul, Div };
+
+pub trait Storable : Copy + Clone + PartialEq { // anything that can be read from and written to VM memory
     const BYTE_COUNT : usize;
 
-    fn from_be(self) -> Self; // flip the endianness if we're on an LE platform
+    fn from_be_bytes(bytes : &[u8]) -> Self; // bytes has to be exactly BYTE_COUNT long. VM memory isn't aligned, so values are always
+                                             // copied in and out byte by byte rather than pointed at
 
-    fn to_be(self) -> Self { // flip the endianness back (this is actually exactly the same thing as from_be but the name adds clarity)
-        self.from_be()
-    }
+    fn to_be_bytes(self, out : &mut [u8]); // same deal: out is exactly BYTE_COUNT long
+}
+
+
+pub trait Numerical : Storable + Ord + BitOr<Output=Self> + BitAnd<Output=Self> + BitXor<Output=Self> { // integers
+    const ZERO : Self;
+
+    fn from_count(count : u32) -> Self; // a bit count (from count_ones and friends) as this type. counts are at most 64, so this never
+                                        // loses anything
+
+    fn wrapping_add(self, other : Self) -> Self;
+
+    fn wrapping_sub(self, other : Self) -> Self;
+
+    fn wrapping_mul(self, other : Self) -> Self;
+
+    fn checked_add(self, other : Self) -> Option<Self>; // None if the result doesn't fit
+
+    fn checked_sub(self, other : Self) -> Option<Self>;
+
+    fn checked_mul(self, other : Self) -> Option<Self>;
 
-    fn naive_u64(self) -> u64;
+    fn saturating_add(self, other : Self) -> Self; // clamps at the type's bounds
 
-    fn from_naive_u64(v : u64) -> Self;
+    fn saturating_sub(self, other : Self) -> Self;
+
+    fn saturating_mul(self, other : Self) -> Self;
+
+    fn count_ones(self) -> u32;
+
+    fn leading_zeros(self) -> u32;
+
+    fn trailing_zeros(self) -> u32; // the bit width if self is 0, same as leading_zeros
+
+    fn rotate_left(self, amount : u32) -> Self; // amounts wrap around at the bit width, so rotating by the whole width does nothing
+
+    fn rotate_right(self, amount : u32) -> Self;
+
+    fn wrapping_div(self, other : Self) -> Self;
+
+    fn wrapping_rem(self, other : Self) -> Self; // truncated, like C: for signed types the result has the sign of self
+
+    fn shift_left(self, amount : u32) -> Self; // shifting by the bit width or more produces 0, rather than panicking like `<<`
+
+    fn shift_right(self, amount : u32) -> Self; // logical for unsigned types, arithmetic for signed ones. shifting by the bit width or more
+                                                // produces 0 (unsigned) or fills every bit with the sign bit (signed).
 }
 
 
-impl Numerical for u64 {
+pub trait NumericalFloat : Storable + PartialOrd + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> {} // floats.
+// they can't be Numerical: NaN means they aren't Ord.
+
+
+pub(crate) const WIDEST_STORABLE : usize = { // the most bytes one memory access can touch. every Storable type has to be in this list
+    let widths = [u64::BYTE_COUNT, u32::BYTE_COUNT, u16::BYTE_COUNT, u8::BYTE_COUNT, i64::BYTE_COUNT, i32::BYTE_COUNT, i16::BYTE_COUNT,
+                  i8::BYTE_COUNT, f64::BYTE_COUNT, f32::BYTE_COUNT];
+    let mut widest = 0;
+    let mut i = 0;
+    while i < widths.len() {
+        if widths[i] > widest {
+            widest = widths[i];
+        }
+        i += 1;
+    }
+    widest
+};
+
+
+impl Storable for u64 {
     const BYTE_COUNT : usize = 8;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for u64 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        self
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        v
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self.checked_shr(amount).unwrap_or(0)
     }
 }
 
-impl Numerical for u32 {
+impl Storable for u32 {
     const BYTE_COUNT : usize = 4;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for u32 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self.checked_shr(amount).unwrap_or(0)
     }
 }
 
-impl Numerical for u16 {
+impl Storable for u16 {
     const BYTE_COUNT : usize = 2;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for u16 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self.checked_shr(amount).unwrap_or(0)
     }
 }
 
-impl Numerical for u8 {
+impl Storable for u8 {
     const BYTE_COUNT : usize = 1;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for u8 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self.checked_shr(amount).unwrap_or(0)
     }
 }
 
-impl Numerical for i64 {
+impl Storable for i64 {
     const BYTE_COUNT : usize = 8;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for i64 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self >> amount.min(Self::BITS - 1)
     }
 }
 
-impl Numerical for i32 {
+impl Storable for i32 {
     const BYTE_COUNT : usize = 4;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for i32 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self >> amount.min(Self::BITS - 1)
     }
 }
 
-impl Numerical for i16 {
+impl Storable for i16 {
     const BYTE_COUNT : usize = 2;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
     }
+}
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+impl Numerical for i16 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
+    }
+
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self >> amount.min(Self::BITS - 1)
     }
 }
 
-impl Numerical for i8 {
+impl Storable for i8 {
     const BYTE_COUNT : usize = 1;
 
-    fn from_be(self) -> Self {
-        Self::from_be(self)
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
     }
 
-    fn naive_u64(self) -> u64 { // NAIVELY cast this to a u64. this means that negative numbers will suddenly be absurdly large.
-        let mut sp64 = [0u8; 8];
-        let mbytes = self.to_be_bytes();
-        for i in 0..Self::BYTE_COUNT {
-            sp64[i + 7 - Self::BYTE_COUNT] = mbytes[i];
-        }
-        u64::from_be_bytes(sp64)
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl Numerical for i8 {
+    const ZERO : Self = 0;
+
+    fn from_count(count : u32) -> Self {
+        count as Self
+    }
+
+    fn wrapping_add(self, other : Self) -> Self {
+        Self::wrapping_add(self, other)
+    }
+
+    fn wrapping_sub(self, other : Self) -> Self {
+        Self::wrapping_sub(self, other)
     }
 
-    fn from_naive_u64(v : u64) -> Self {
-        let bytes = v.to_be_bytes();
-        Self::from_be_bytes(bytes[8 - Self::BYTE_COUNT..].try_into().unwrap())
+    fn wrapping_mul(self, other : Self) -> Self {
+        Self::wrapping_mul(self, other)
+    }
+
+    fn checked_add(self, other : Self) -> Option<Self> {
+        Self::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other : Self) -> Option<Self> {
+        Self::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other : Self) -> Option<Self> {
+        Self::checked_mul(self, other)
+    }
+
+    fn saturating_add(self, other : Self) -> Self {
+        Self::saturating_add(self, other)
+    }
+
+    fn saturating_sub(self, other : Self) -> Self {
+        Self::saturating_sub(self, other)
+    }
+
+    fn saturating_mul(self, other : Self) -> Self {
+        Self::saturating_mul(self, other)
+    }
+
+    fn count_ones(self) -> u32 {
+        Self::count_ones(self)
+    }
+
+    fn leading_zeros(self) -> u32 {
+        Self::leading_zeros(self)
+    }
+
+    fn trailing_zeros(self) -> u32 {
+        Self::trailing_zeros(self)
+    }
+
+    fn rotate_left(self, amount : u32) -> Self {
+        Self::rotate_left(self, amount)
+    }
+
+    fn rotate_right(self, amount : u32) -> Self {
+        Self::rotate_right(self, amount)
+    }
+
+    fn wrapping_div(self, other : Self) -> Self {
+        Self::wrapping_div(self, other)
+    }
+
+    fn wrapping_rem(self, other : Self) -> Self {
+        Self::wrapping_rem(self, other)
+    }
+
+    fn shift_left(self, amount : u32) -> Self {
+        self.checked_shl(amount).unwrap_or(0)
+    }
+
+    fn shift_right(self, amount : u32) -> Self {
+        self >> amount.min(Self::BITS - 1)
     }
 }
+
+impl Storable for f64 {
+    const BYTE_COUNT : usize = 8;
+
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl NumericalFloat for f64 {}
+
+impl Storable for f32 {
+    const BYTE_COUNT : usize = 4;
+
+    fn from_be_bytes(bytes : &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
+    }
+
+    fn to_be_bytes(self, out : &mut [u8]) {
+        out.copy_from_slice(&Self::to_be_bytes(self));
+    }
+}
+
+impl NumericalFloat for f32 {}
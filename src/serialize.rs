@@ -0,0 +1,192 @@
+// saving images as bytes and loading them back, so a program can be built once and run later (the anyvm binary's .avm files).
+// the format is the magic number, then every part of the image in order, all big-endian like the VM itself:
+//  static section, text section: a u64 length, then the bytes
+//  function table, static table: a u64 count, then for each entry a u64 name length, the name (utf-8), and the i64 offset. sorted by name,
+//      so the same image always comes out as the same bytes
+//  function extents: a u64 count, then (start, end) i64 pairs
+//  relocations: a byte, 0 if the image has no relocation table and 1 if it does. then a u64 count, and for each one the section byte (0 for
+//      static, 1 for text), the u64 offset, and the target: 0 for the static section, 1 for text, or 2 and then a u64 length and the name
+//      of a symbol
+//  debug info: a byte, 0 if the image has none and 1 if it does. then a u64 count, and for each span the i64 start and end and the u64
+//      line and column
+//  frame sizes: a byte, 0 if the image has none and 1 if it does. then a table like the function table, with u64 sizes
+use crate::{ DebugInfo, Image, Relocation, RelocTarget, Section, SourceSpan };
+use crate::error::ImageErr;
+use alloc::{ string::String, vec::Vec };
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+
+
+const MAGIC : &[u8; 4] = b"AVM\x04"; // the last byte is the format version
+
+
+struct Reader<'a> {
+    data : &'a [u8]
+}
+
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len : u64) -> Result<&'a [u8], ImageErr> {
+        if len > self.data.len() as u64 {
+            return Err(ImageErr::Truncated);
+        }
+        let (head, rest) = self.data.split_at(len as usize);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> Result<u64, ImageErr> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn byte(&mut self) -> Result<u8, ImageErr> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn name(&mut self) -> Result<String, ImageErr> {
+        let len = self.u64()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| ImageErr::BadName)
+    }
+
+    fn relocation(&mut self) -> Result<Relocation, ImageErr> {
+        let section = id_section(self.byte()?)?;
+        let offset = self.u64()? as usize;
+        let target = match self.byte()? {
+            2 => RelocTarget::Symbol(self.name()?),
+            id => RelocTarget::Section(id_section(id)?)
+        };
+        Ok(Relocation { section, offset, target })
+    }
+
+    fn span(&mut self) -> Result<SourceSpan, ImageErr> {
+        Ok(SourceSpan { start : self.u64()? as i64, end : self.u64()? as i64, line : self.u64()? as usize, column : self.u64()? as usize })
+    }
+
+    fn section(&mut self) -> Result<Vec<u8>, ImageErr> {
+        let len = self.u64()?;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    fn table(&mut self) -> Result<HashMap<String, i64>, ImageErr> {
+        let mut table = HashMap::new();
+        for _ in 0..self.u64()? {
+            let name = self.name()?;
+            table.insert(name, self.u64()? as i64);
+        }
+        Ok(table)
+    }
+}
+
+
+fn write_table(out : &mut Vec<u8>, table : &HashMap<String, i64>) {
+    let mut entries : Vec<(&String, &i64)> = table.iter().collect();
+    entries.sort();
+    out.extend((entries.len() as u64).to_be_bytes());
+    for (name, offset) in entries {
+        out.extend((name.len() as u64).to_be_bytes());
+        out.extend(name.as_bytes());
+        out.extend(offset.to_be_bytes());
+    }
+}
+
+
+fn section_id(section : Section) -> u8 {
+    match section {
+        Section::Static => 0,
+        Section::Text => 1
+    }
+}
+
+
+fn id_section(id : u8) -> Result<Section, ImageErr> {
+    match id {
+        0 => Ok(Section::Static),
+        1 => Ok(Section::Text),
+        _ => Err(ImageErr::BadRelocation)
+    }
+}
+
+
+impl Image {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for section in [&self.static_section, &self.text_section] {
+            out.extend((section.len() as u64).to_be_bytes());
+            out.extend(section);
+        }
+        write_table(&mut out, &self.function_table);
+        write_table(&mut out, &self.static_table);
+        out.extend((self.function_extents.len() as u64).to_be_bytes());
+        for (start, end) in &self.function_extents {
+            out.extend(start.to_be_bytes());
+            out.extend(end.to_be_bytes());
+        }
+        out.push(self.relocations.is_some() as u8);
+        if let Some(relocations) = &self.relocations {
+            out.extend((relocations.len() as u64).to_be_bytes());
+            for relocation in relocations {
+                out.push(section_id(relocation.section));
+                out.extend((relocation.offset as u64).to_be_bytes());
+                match &relocation.target {
+                    RelocTarget::Section(section) => out.push(section_id(*section)),
+                    RelocTarget::Symbol(name) => {
+                        out.push(2);
+                        out.extend((name.len() as u64).to_be_bytes());
+                        out.extend(name.as_bytes());
+                    }
+                }
+            }
+        }
+        out.push(self.debug_info.is_some() as u8);
+        if let Some(debug_info) = &self.debug_info {
+            out.extend((debug_info.spans.len() as u64).to_be_bytes());
+            for span in &debug_info.spans {
+                out.extend(span.start.to_be_bytes());
+                out.extend(span.end.to_be_bytes());
+                out.extend((span.line as u64).to_be_bytes());
+                out.extend((span.column as u64).to_be_bytes());
+            }
+        }
+        out.push(self.frame_sizes.is_some() as u8);
+        if let Some(frame_sizes) = &self.frame_sizes {
+            write_table(&mut out, &frame_sizes.iter().map(|(name, size)| (name.clone(), *size as i64)).collect());
+        }
+        out
+    }
+
+    pub fn from_bytes(data : &[u8]) -> Result<Image, ImageErr> { // the inverse of to_bytes. the bytecode isn't checked; see verify
+        let mut reader = Reader { data };
+        if reader.bytes(4).map_err(|_| ImageErr::BadMagic)? != MAGIC {
+            return Err(ImageErr::BadMagic);
+        }
+        let static_section = reader.section()?;
+        let text_section = reader.section()?;
+        let function_table = reader.table()?;
+        let static_table = reader.table()?;
+        let mut function_extents = Vec::new();
+        for _ in 0..reader.u64()? {
+            function_extents.push((reader.u64()? as i64, reader.u64()? as i64));
+        }
+        let relocations = match reader.byte()? {
+            0 => None,
+            1 => Some((0..reader.u64()?).map(|_| reader.relocation()).collect::<Result<Vec<_>, _>>()?),
+            _ => return Err(ImageErr::BadRelocation)
+        };
+        let debug_info = match reader.byte()? {
+            0 => None,
+            1 => Some(DebugInfo { spans : (0..reader.u64()?).map(|_| reader.span()).collect::<Result<Vec<_>, _>>()? }),
+            _ => return Err(ImageErr::BadDebugInfo)
+        };
+        let frame_sizes = match reader.byte()? {
+            0 => None,
+            1 => Some(reader.table()?.into_iter().map(|(name, size)| (name, size as u64)).collect()),
+            _ => return Err(ImageErr::BadFrameSizes)
+        };
+        if !reader.data.is_empty() {
+            return Err(ImageErr::TrailingData);
+        }
+        Ok(Image { function_table, static_table, static_section, text_section, function_extents, relocations, debug_info, frame_sizes })
+    }
+}
@@ -0,0 +1,80 @@
+// saving and restoring the state of a machine, for fuzzing, replaying, or backing out of a call that went badly.
+// externals are closures and can't be cloned, so a snapshot doesn't include them: restoring one rolls back memory and all the registers,
+// but leaves the machine's externals (and the logs of mocked externals) exactly as they are. unless it's been freed since, any rabbit address
+// in the snapshot's memory still points at the same external.
+use crate::Machine;
+use alloc::{ string::String, vec::Vec };
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+use crate::mmu::Mmu;
+
+
+#[derive(Debug, Clone)]
+pub struct MachineSnapshot {
+    memory : Vec<u8>,
+    text_start : i64,
+    stack_start : i64,
+    end : i64,
+    stack_pointer : i64,
+    exec_pointer : i64,
+    errcode : u8,
+    sbm : (i64, i64),
+    mmu : Option<Mmu>, // the page table itself is in memory, this is just the host's copy of the layout
+    function_extents : Vec<(i64, i64)>,
+    function_names : HashMap<i64, String>,
+    static_names : HashMap<String, i64>,
+    call_stack : Vec<(i64, i64, i64)>, // so returning from a call the snapshot was taken inside still pops its frame
+    mounted_statics : Vec<u8>,
+    next_id : u64 // so a replay hands out the same ids as the first run did
+}
+
+
+impl MachineSnapshot {
+    pub fn memory(&self) -> &[u8] { // everything in VM memory when the snapshot was taken: static, text, stack, heap and page table
+        &self.memory
+    }
+}
+
+
+impl Machine {
+    pub fn snapshot(&self) -> MachineSnapshot {
+        MachineSnapshot {
+            memory : self.memory.clone(),
+            text_start : self.text_start,
+            stack_start : self.stack_start,
+            end : self.end,
+            stack_pointer : self.stack_pointer,
+            exec_pointer : self.exec_pointer,
+            errcode : self.errcode,
+            sbm : self.sbm,
+            mmu : self.mmu,
+            function_extents : self.function_extents.clone(),
+            function_names : self.function_names.clone(),
+            static_names : self.static_names.clone(),
+            call_stack : self.call_stack.clone(),
+            mounted_statics : self.mounted_statics.clone(),
+            next_id : self.next_id
+        }
+    }
+
+    pub fn restore(&mut self, snap : &MachineSnapshot) { // roll back to a snapshot. it doesn't have to have come from this machine
+        self.memory.clone_from(&snap.memory);
+        self.text_start = snap.text_start;
+        self.stack_start = snap.stack_start;
+        self.end = snap.end;
+        self.stack_pointer = snap.stack_pointer;
+        self.exec_pointer = snap.exec_pointer;
+        self.errcode = snap.errcode;
+        self.sbm = snap.sbm;
+        self.mmu = snap.mmu;
+        self.function_extents.clone_from(&snap.function_extents);
+        self.function_names.clone_from(&snap.function_names);
+        self.static_names.clone_from(&snap.static_names);
+        self.call_stack.clone_from(&snap.call_stack);
+        self.mounted_statics.clone_from(&snap.mounted_statics);
+        self.next_id = snap.next_id;
+        self.clear_insn_cache();
+    }
+}
@@ -0,0 +1,49 @@
+// the standard library hosts can give their machines. register puts it in the library registry as "stdabi", so guests can dock it.
+// symbols:
+//  stest: pops a pointer to a null-terminated string and prints it (with a newline) to the machine's output (see Machine::set_output).
+//      if the string is "STDABI TEST", the machine halts and invoke returns InvokeResult::StdabiTestSuccess; this is what the tests use to
+//      prove they got all the way through.
+//      a pointer that doesn't lead to a string is thrown as 1 (which only does anything inside an SBM - externals can't abort the VM).
+use crate::{ ExtData, Machine, Table };
+use crate::error::*;
+use std::io::Write;
+
+
+struct Stdabi;
+
+
+impl Table for Stdabi {
+    fn lookup(&self, data : &str) -> Option<ExtData> {
+        match data {
+            "stest" => Some(ExtData::Function(Box::new(stest))),
+            _ => None
+        }
+    }
+}
+
+
+fn stest(machine : &mut Machine) {
+    let message = match machine.pop_as::<i64>() {
+        Ok(message) => message,
+        Err(_) => {
+            let _ = machine.throw(ErrorCode::OUT_OF_BOUNDS);
+            return;
+        }
+    };
+    match machine.read_str(message).map(str::to_string) {
+        Ok(text) => {
+            let _ = writeln!(machine.output, "{}", text); // there's nobody to tell if the output fails
+            if text == "STDABI TEST" {
+                machine.halt(InvokeResult::StdabiTestSuccess);
+            }
+        },
+        Err(_) => {
+            let _ = machine.throw(ErrorCode::OUT_OF_BOUNDS);
+        }
+    }
+}
+
+
+pub fn register(machine : &mut Machine) -> i64 { // install the stdabi. returns the library's rabbit address
+    machine.register_lib("stdabi", Stdabi)
+}
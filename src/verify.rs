@@ -0,0 +1,49 @@
+// Image::verify: a best-effort static check for broken bytecode. functions are crammed together in the text section, so a linear sweep from
+// the start decodes every instruction in every function. it can't follow computed jumps (ret, invokevirtual, throws), but it'll catch
+// truncated or misaligned code and unknown opcodes before they get anywhere near a machine.
+use crate::error::*;
+use crate::{ Image, Machine };
+use alloc::vec;
+use hashbrown::HashSet;
+
+
+impl Image {
+    pub fn verify(&self) -> Result<(), VerifyErr> {
+        let text = &self.text_section;
+        let text_start = self.static_section.len() as i64;
+        let mut boundaries = HashSet::new();
+        let mut targets = vec![]; // (offset of the instruction, absolute target)
+        let mut pos = 0;
+        while pos < text.len() {
+            let op = text[pos];
+            let len = Machine::instruction_len(op).ok_or(VerifyErr::UnknownOpcode(pos, op))?;
+            if pos + len > text.len() {
+                return Err(VerifyErr::Truncated(pos));
+            }
+            let arg = |at : usize| i64::from_be_bytes(text[at..at + 8].try_into().unwrap());
+            match op {
+                63 => { // jmp is relative to the next instruction. the offset is untrusted, so a target past i64::MAX is just a bad target
+                    let next = text_start + (pos + len) as i64;
+                    let target = next.checked_add(arg(pos + 1)).ok_or(VerifyErr::BadTarget(pos, next.saturating_add(arg(pos + 1))))?;
+                    targets.push((pos, target));
+                },
+                64 => targets.push((pos, arg(pos + 9))), // branch
+                65 => targets.push((pos, arg(pos + 1))), // call
+                _ => {}
+            }
+            boundaries.insert(pos as i64);
+            pos += len;
+        }
+        for (offset, target) in targets {
+            if !target.checked_sub(text_start).is_some_and(|offset| boundaries.contains(&offset)) {
+                return Err(VerifyErr::BadTarget(offset, target));
+            }
+        }
+        for (name, offset) in &self.function_table {
+            if !boundaries.contains(offset) {
+                return Err(VerifyErr::BadFunction(name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+// runs the anyvm binary on real files
+use std::process::Command;
+use std::path::PathBuf;
+
+
+const PROGRAM : &str = r#"
+=message bytes "hello\0"
+=stdabi bytes "stdabi\0"
+=stest bytes "stest\0"
+.main export
+    dock $stdabi
+    loadfun $stest
+    pushvl $message
+    invokevirtual -16
+    exit 42
+"#;
+
+
+fn scratch(name : &str) -> PathBuf { // a file in the temp dir that no other test run will be using
+    std::env::temp_dir().join(format!("anyvm-cli-{}-{}", std::process::id(), name))
+}
+
+
+fn anyvm(args : &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_anyvm")).args(args).output().unwrap()
+}
+
+
+#[test]
+fn cli_test() {
+    let source = scratch("prog.ir");
+    let image = scratch("prog.avm");
+    std::fs::write(&source, PROGRAM).unwrap();
+    let out = anyvm(&["run", "--memory", "1024", source.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(42));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "hello\nexited with 42\n");
+    let out = anyvm(&["build", source.to_str().unwrap(), "-o", image.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert_eq!(anyvm(&["run", image.to_str().unwrap()]).status.code(), Some(42)); // the saved image runs the same
+    let out = anyvm(&["disasm", image.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("exit 42"));
+    assert_eq!(anyvm(&["run", "--avc", source.to_str().unwrap()]).status.code(), Some(1)); // it isn't AVC
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(image).unwrap();
+}